@@ -0,0 +1,71 @@
+use futures_util::StreamExt;
+use lhm_client::{
+    ComputerOptions, HardwareType, LHMClient, SensorType, ServerEvent,
+    alert::{AlertComparison, AlertRule, SensorSelector},
+};
+
+#[tokio::main]
+async fn main() {
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    client
+        .set_options(ComputerOptions {
+            cpu_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let mut events = client.subscribe_events().await.unwrap();
+
+    // Alert when any CPU temperature stays above 80 degrees for 5 seconds,
+    // adding rules requires the configure permission
+    client
+        .add_alert_rule(AlertRule {
+            name: "cpu-hot".to_string(),
+            selector: SensorSelector::Type {
+                ty: SensorType::Temperature,
+                hardware_ty: Some(HardwareType::Cpu),
+            },
+            comparison: AlertComparison::Above,
+            threshold: 80.0,
+            duration_ms: 5000,
+            hysteresis: 5.0,
+            cooldown_ms: 30000,
+        })
+        .await
+        .unwrap();
+
+    let rules = client.list_alert_rules().await.unwrap();
+    println!("Registered {} alert rules", rules.len());
+
+    // Alerts are evaluated when the hardware is updated
+    let updater = client.clone();
+    tokio::spawn(async move {
+        loop {
+            if updater.update_all().await.is_err() {
+                break;
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+    });
+
+    while let Some(event) = events.next().await {
+        match event.unwrap() {
+            ServerEvent::AlertFired {
+                rule,
+                sensor_id,
+                value,
+            } => println!("Alert {rule} fired for {sensor_id}: {value}"),
+            ServerEvent::AlertCleared {
+                rule,
+                sensor_id,
+                value,
+            } => println!("Alert {rule} cleared for {sensor_id}: {value}"),
+            _ => {}
+        }
+    }
+}
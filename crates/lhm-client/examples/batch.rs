@@ -0,0 +1,43 @@
+use lhm_client::{ComputerOptions, LHMClient, SensorType};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() {
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    client
+        .set_options(ComputerOptions {
+            cpu_enabled: true,
+            gpu_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    client.update_all().await.unwrap();
+
+    let sensors = client
+        .query_sensors(None, Some(SensorType::Temperature))
+        .await
+        .unwrap();
+
+    // Build a batch reading every temperature sensor
+    let mut batch = client.batch();
+    let keys: Vec<_> = sensors
+        .iter()
+        .map(|sensor| batch.get_sensor_value_by_id(sensor.identifier.clone(), true))
+        .collect();
+
+    // Poll all the sensors in a single round trip each tick
+    for _ in 0..5 {
+        let mut results = batch.send().await.unwrap();
+
+        for (sensor, key) in sensors.iter().zip(&keys) {
+            println!("{} is {:?}°C", sensor.name, results.take(*key).unwrap());
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
@@ -0,0 +1,45 @@
+use futures_util::StreamExt;
+use lhm_client::{ComputerOptions, LHMClient, ServerEvent};
+
+#[tokio::main]
+async fn main() {
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    let mut events = client.subscribe_events().await.unwrap();
+
+    client
+        .set_options(ComputerOptions {
+            cpu_enabled: true,
+            gpu_enabled: true,
+            storage_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    client.update_all().await.unwrap();
+
+    // Print hardware changes as they are detected
+    while let Some(event) = events.next().await {
+        match event.unwrap() {
+            ServerEvent::HardwareAdded { hardware } => {
+                println!(
+                    "Hardware added: {} ({})",
+                    hardware.name, hardware.identifier
+                )
+            }
+            ServerEvent::HardwareRemoved { identifier, .. } => {
+                println!("Hardware removed: {identifier}")
+            }
+            ServerEvent::SensorAdded { sensor } => {
+                println!("Sensor added: {} ({})", sensor.name, sensor.identifier)
+            }
+            ServerEvent::SensorRemoved { identifier, .. } => {
+                println!("Sensor removed: {identifier}")
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use lhm_client::{ComputerOptions, LHMClient, snapshot::HardwareNode};
+
+#[tokio::main]
+async fn main() {
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    client
+        .set_options(ComputerOptions {
+            cpu_enabled: true,
+            motherboard_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    client.update_all().await.unwrap();
+
+    let nodes = client.get_hardware_tree(None).await.unwrap();
+    for node in &nodes {
+        print_node(node, 0);
+    }
+}
+
+fn print_node(node: &HardwareNode, depth: usize) {
+    let indent = "  ".repeat(depth);
+    println!("{indent}{} ({:?})", node.hardware.name, node.hardware.ty);
+
+    for sensor in &node.sensors {
+        println!("{indent}  - {}: {}", sensor.name, sensor.value);
+    }
+
+    for child in &node.children {
+        print_node(child, depth + 1);
+    }
+}
@@ -0,0 +1,51 @@
+use lhm_client::{ComputerOptions, HardwareType, LHMClient, SensorType};
+use std::time::{Duration, SystemTime};
+
+#[tokio::main]
+async fn main() {
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    client
+        .set_options(ComputerOptions {
+            cpu_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    client.update_all().await.unwrap();
+
+    // Request all CPU hardware
+    let cpu_list = client
+        .query_hardware(None, Some(HardwareType::Cpu))
+        .await
+        .unwrap();
+
+    for cpu in cpu_list {
+        let cpu_temps = client
+            .query_sensors(Some(cpu.identifier.clone()), Some(SensorType::Temperature))
+            .await
+            .unwrap();
+
+        for sensor in cpu_temps {
+            // Load the last 10 minutes in 20 buckets
+            let since = SystemTime::now() - Duration::from_secs(600);
+            let points = client
+                .get_sensor_history(sensor.identifier.clone(), Some(since), Some(20), true)
+                .await
+                .unwrap()
+                .unwrap_or_default();
+
+            println!("{} ({} points)", sensor.name, points.len());
+
+            for point in points {
+                println!(
+                    "  {}: min {} avg {} max {}",
+                    point.timestamp_ms, point.min, point.value, point.max
+                );
+            }
+        }
+    }
+}
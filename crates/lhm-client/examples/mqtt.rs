@@ -0,0 +1,42 @@
+use lhm_client::{
+    ComputerOptions, LHMClient,
+    mqtt::{MqttConfig, MqttPublisher},
+};
+use std::time::Duration;
+
+/// Publishes the sensor values to an MQTT broker along with Home Assistant
+/// discovery messages
+///
+/// ```sh
+/// cargo run -p lhm-client --features mqtt --example mqtt -- localhost 1883
+/// ```
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let host = args.next().unwrap_or_else(|| "localhost".to_string());
+    let port = args
+        .next()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1883);
+
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    client
+        .set_options(ComputerOptions {
+            cpu_enabled: true,
+            gpu_enabled: true,
+            memory_enabled: true,
+            motherboard_enabled: true,
+            storage_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let mut config = MqttConfig::new("desktop", host, port);
+    config.interval = Duration::from_secs(2);
+
+    MqttPublisher::new(client, config).run().await.unwrap();
+}
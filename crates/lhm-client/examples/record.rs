@@ -0,0 +1,42 @@
+use lhm_client::{ComputerOptions, LHMClient, recorder::Recorder};
+use std::{path::PathBuf, time::Duration};
+
+#[tokio::main]
+async fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("recording.json"));
+
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    client
+        .set_options(ComputerOptions {
+            controller_enabled: true,
+            cpu_enabled: true,
+            gpu_enabled: true,
+            memory_enabled: true,
+            motherboard_enabled: true,
+            storage_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    client.update_all().await.unwrap();
+
+    let mut recorder = Recorder::start(client).await.unwrap();
+
+    // Record a sample every second for 30 seconds
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    for _ in 0..30 {
+        interval.tick().await;
+        recorder.sample().await.unwrap();
+    }
+
+    recorder.save(&path).unwrap();
+
+    println!("Saved recording to {}", path.display());
+}
@@ -0,0 +1,43 @@
+use lhm_client::{ComputerOptions, HardwareType, LHMClient, SensorType, snapshot::SnapshotFilter};
+
+#[tokio::main]
+async fn main() {
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    client
+        .set_options(ComputerOptions {
+            cpu_enabled: true,
+            gpu_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // Load the temperatures of all the hardware in a single request
+    let snapshot = client
+        .get_snapshot(
+            true,
+            SnapshotFilter {
+                sensor_types: vec![SensorType::Temperature],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    for node in snapshot.iter_hardware() {
+        println!("{} ({:?})", node.hardware.name, node.hardware.ty);
+
+        for sensor in &node.sensors {
+            println!(
+                "  {} is {}°C (min {} max {})",
+                sensor.name, sensor.value, sensor.min, sensor.max
+            );
+        }
+    }
+
+    let cpu_count = snapshot.hardware_of_type(HardwareType::Cpu).count();
+    println!("Found {cpu_count} CPUs");
+}
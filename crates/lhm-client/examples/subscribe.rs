@@ -0,0 +1,56 @@
+use futures_util::StreamExt;
+use lhm_client::{ComputerOptions, HardwareType, LHMClient, SensorType};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() {
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    client
+        .set_options(ComputerOptions {
+            cpu_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    println!("Set options");
+
+    client.update_all().await.unwrap();
+
+    println!("Updated hardware");
+
+    // Request all CPU hardware
+    let cpu_list = client
+        .query_hardware(None, Some(HardwareType::Cpu))
+        .await
+        .unwrap();
+
+    // Collect all the CPU temperature sensors
+    let mut sensor_ids = Vec::new();
+    for cpu in cpu_list {
+        let cpu_temps = client
+            .query_sensors(Some(cpu.identifier.clone()), Some(SensorType::Temperature))
+            .await
+            .unwrap();
+
+        sensor_ids.extend(cpu_temps.into_iter().map(|sensor| sensor.identifier));
+    }
+
+    // Subscribe to the temperatures
+    let subscription = client
+        .subscribe(sensor_ids, Duration::from_secs(1))
+        .await
+        .unwrap();
+
+    // Take the first 5 batches of values
+    let mut values = subscription.take(5);
+
+    while let Some(batch) = values.next().await {
+        for value in batch.unwrap() {
+            println!("{} is {:?}°C", value.identifier, value.value);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use lhm_client::{
+    ComputerOptions, HardwareType, LHMClient, SensorType,
+    alert::SensorSelector,
+    virtual_sensor::{VirtualFormula, VirtualSensor},
+};
+
+#[tokio::main]
+async fn main() {
+    let client = LHMClient::connect().await.unwrap();
+
+    println!("Connected to client");
+
+    client
+        .set_options(ComputerOptions {
+            cpu_enabled: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // Average of all the CPU temperatures
+    let sensor = VirtualSensor {
+        id: "cpu-temp-avg".to_string(),
+        name: "CPU Temperature Average".to_string(),
+        ty: SensorType::Temperature,
+        formula: VirtualFormula::Average {
+            inputs: vec![SensorSelector::Type {
+                ty: SensorType::Temperature,
+                hardware_ty: Some(HardwareType::Cpu),
+            }],
+        },
+    };
+    let identifier = sensor.identifier();
+
+    // Adding virtual sensors requires the configure permission
+    client.add_virtual_sensor(sensor).await.unwrap();
+
+    // Virtual sensors are computed when the hardware is updated
+    client.update_all().await.unwrap();
+
+    let sensor = client.get_sensor_by_id(identifier).await.unwrap().unwrap();
+    println!("{}: {}", sensor.name, sensor.value);
+}
@@ -0,0 +1,300 @@
+use crate::{
+    Capability, Hardware, HardwareType, LHMClientError, LHMClientHandle, PipeRequest, PipeResponse,
+    Sensor, SensorReading, SensorType, sensor_reading,
+};
+use std::marker::PhantomData;
+
+/// Parses the response for a request within a batch
+type ParseFn<T> = fn(PipeResponse) -> Result<T, LHMClientError>;
+
+/// Collection of requests sent to the server in a single frame, created
+/// using [LHMClientHandle::batch]
+///
+/// The server runs the requests in order. Each request added to the batch
+/// provides a [BatchKey] used to take its result from the [BatchResults].
+/// The batch can be sent multiple times, such as once per polling tick
+pub struct Batch<'a> {
+    /// Handle used to send the batch
+    handle: &'a LHMClientHandle,
+    /// Requests within the batch
+    requests: Vec<PipeRequest>,
+}
+
+/// Key for the result of a request within a [Batch]
+pub struct BatchKey<T> {
+    /// Index of the request within the batch
+    index: usize,
+    /// Function parsing the response for the request
+    parse: ParseFn<T>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for BatchKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for BatchKey<T> {}
+
+/// Responses from sending a [Batch]
+pub struct BatchResults {
+    /// Response for each request, [None] once the response is taken
+    responses: Vec<Option<PipeResponse>>,
+}
+
+impl BatchResults {
+    /// Takes the result of a request from the batch, each result can
+    /// only be taken once
+    pub fn take<T>(&mut self, key: BatchKey<T>) -> Result<T, LHMClientError> {
+        let response = self
+            .responses
+            .get_mut(key.index)
+            .and_then(Option::take)
+            .ok_or(LHMClientError::UnexpectedMessage)?;
+
+        match response {
+            PipeResponse::Error { code, message } => {
+                Err(LHMClientError::from_server(code, message))
+            }
+            response => (key.parse)(response),
+        }
+    }
+}
+
+impl<'a> Batch<'a> {
+    pub(crate) fn new(handle: &'a LHMClientHandle) -> Self {
+        Self {
+            handle,
+            requests: Vec::new(),
+        }
+    }
+
+    /// Number of requests within the batch
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Check if the batch contains no requests
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Sends the batch to the server in a single frame
+    ///
+    /// Failures of individual requests are provided through
+    /// [BatchResults::take] rather than failing the whole batch
+    pub async fn send(&self) -> Result<BatchResults, LHMClientError> {
+        if !self.handle.server.has_capability(Capability::Batch) {
+            return Err(LHMClientError::Unsupported(Capability::Batch));
+        }
+
+        if self.requests.is_empty() {
+            return Ok(BatchResults {
+                responses: Vec::new(),
+            });
+        }
+
+        let request = PipeRequest::Batch {
+            requests: self.requests.clone(),
+        };
+
+        let responses = match self.handle.send_request(request).await? {
+            PipeResponse::Batch { responses } if responses.len() == self.requests.len() => {
+                responses
+            }
+            _ => return Err(LHMClientError::UnexpectedMessage),
+        };
+
+        Ok(BatchResults {
+            responses: responses.into_iter().map(Some).collect(),
+        })
+    }
+
+    fn push<T>(&mut self, request: PipeRequest, parse: ParseFn<T>) -> BatchKey<T> {
+        let index = self.requests.len();
+        self.requests.push(request);
+
+        BatchKey {
+            index,
+            parse,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Adds a request to update all hardware and sensors
+    ///
+    /// See [LHMClientHandle::update_all]
+    pub fn update_all(&mut self) -> BatchKey<()> {
+        self.push(PipeRequest::UpdateAll, parse_success)
+    }
+
+    /// Adds a request for a specific hardware item using its identifier
+    ///
+    /// See [LHMClientHandle::get_hardware_by_id]
+    pub fn get_hardware_by_id(&mut self, id: String) -> BatchKey<Option<Hardware>> {
+        self.push(
+            PipeRequest::GetHardwareById { id },
+            |response| match response {
+                PipeResponse::Hardware { hardware } => Ok(hardware),
+                _ => Err(LHMClientError::UnexpectedMessage),
+            },
+        )
+    }
+
+    /// Adds a query for the currently loaded selection of hardware
+    ///
+    /// See [LHMClientHandle::query_hardware]
+    pub fn query_hardware(
+        &mut self,
+        parent_id: Option<Option<String>>,
+        ty: Option<HardwareType>,
+    ) -> BatchKey<Vec<Hardware>> {
+        self.push(
+            PipeRequest::QueryHardware { parent_id, ty },
+            |response| match response {
+                PipeResponse::Hardwares { hardware } => Ok(hardware),
+                _ => Err(LHMClientError::UnexpectedMessage),
+            },
+        )
+    }
+
+    /// Adds a request to update a specific hardware item by ID
+    pub fn update_hardware_by_id(&mut self, id: String) -> BatchKey<()> {
+        self.push(PipeRequest::UpdateHardwareById { id }, parse_success)
+    }
+
+    /// Adds a request to update a specific hardware item using its cache index
+    ///
+    /// See [LHMClientHandle::update_hardware_by_idx]
+    pub fn update_hardware_by_idx(&mut self, idx: usize) -> BatchKey<()> {
+        self.push(PipeRequest::UpdateHardwareByIndex { idx }, parse_success)
+    }
+
+    /// Adds a request for a specific sensor by ID
+    pub fn get_sensor_by_id(&mut self, id: String) -> BatchKey<Option<Sensor>> {
+        self.push(
+            PipeRequest::GetSensorById { id },
+            |response| match response {
+                PipeResponse::Sensor { sensor } => Ok(sensor),
+                _ => Err(LHMClientError::UnexpectedMessage),
+            },
+        )
+    }
+
+    /// Adds a request for the value of a specific sensor by ID
+    ///
+    /// See [LHMClientHandle::get_sensor_value_by_id]
+    pub fn get_sensor_value_by_id(&mut self, id: String, update: bool) -> BatchKey<Option<f32>> {
+        self.push(
+            PipeRequest::GetSensorValueById { id, update },
+            parse_sensor_value,
+        )
+    }
+
+    /// Adds a request for the value of a specific sensor using its cache index
+    ///
+    /// See [LHMClientHandle::get_sensor_value_by_idx]
+    pub fn get_sensor_value_by_idx(&mut self, idx: usize, update: bool) -> BatchKey<Option<f32>> {
+        self.push(
+            PipeRequest::GetSensorValueByIndex { idx, update },
+            parse_sensor_value,
+        )
+    }
+
+    /// Adds a request for the value of a specific sensor by ID along with
+    /// the age of the value
+    ///
+    /// See [LHMClientHandle::get_sensor_reading_by_id]
+    pub fn get_sensor_reading_by_id(
+        &mut self,
+        id: String,
+        update: bool,
+    ) -> BatchKey<Option<SensorReading>> {
+        self.push(
+            PipeRequest::GetSensorValueById { id, update },
+            parse_sensor_reading,
+        )
+    }
+
+    /// Adds a request for the value of a specific sensor using its cache
+    /// index along with the age of the value
+    ///
+    /// See [LHMClientHandle::get_sensor_reading_by_idx]
+    pub fn get_sensor_reading_by_idx(
+        &mut self,
+        idx: usize,
+        update: bool,
+    ) -> BatchKey<Option<SensorReading>> {
+        self.push(
+            PipeRequest::GetSensorValueByIndex { idx, update },
+            parse_sensor_reading,
+        )
+    }
+
+    /// Adds a query for the currently loaded selection of sensors
+    ///
+    /// See [LHMClientHandle::query_sensors]
+    pub fn query_sensors(
+        &mut self,
+        parent_id: Option<String>,
+        ty: Option<SensorType>,
+    ) -> BatchKey<Vec<Sensor>> {
+        self.push(
+            PipeRequest::QuerySensors { parent_id, ty },
+            |response| match response {
+                PipeResponse::Sensors { sensors } => Ok(sensors),
+                _ => Err(LHMClientError::UnexpectedMessage),
+            },
+        )
+    }
+
+    /// Adds a request to update a specific sensor item by ID
+    pub fn update_sensor_by_id(&mut self, id: String) -> BatchKey<()> {
+        self.push(PipeRequest::UpdateSensorById { id }, parse_success)
+    }
+
+    /// Adds a request for the generation of the server cache, allowing the
+    /// cache indexes used within the batch to be checked
+    ///
+    /// See [LHMClientHandle::get_cache_generation]
+    pub fn get_cache_generation(&mut self) -> BatchKey<u64> {
+        self.push(PipeRequest::GetCacheGeneration, |response| match response {
+            PipeResponse::CacheGeneration { generation } => Ok(generation),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        })
+    }
+
+    /// Adds a request to update a specific sensor item using its cache index
+    ///
+    /// See [LHMClientHandle::update_sensor_by_idx]
+    pub fn update_sensor_by_idx(&mut self, idx: usize) -> BatchKey<()> {
+        self.push(PipeRequest::UpdateSensorByIndex { idx }, parse_success)
+    }
+}
+
+fn parse_success(response: PipeResponse) -> Result<(), LHMClientError> {
+    match response {
+        PipeResponse::Success => Ok(()),
+        _ => Err(LHMClientError::UnexpectedMessage),
+    }
+}
+
+fn parse_sensor_value(response: PipeResponse) -> Result<Option<f32>, LHMClientError> {
+    match response {
+        PipeResponse::SensorValue { value, .. } => Ok(value),
+        _ => Err(LHMClientError::UnexpectedMessage),
+    }
+}
+
+fn parse_sensor_reading(response: PipeResponse) -> Result<Option<SensorReading>, LHMClientError> {
+    match response {
+        PipeResponse::SensorValue {
+            value,
+            age_ms,
+            min,
+            max,
+        } => Ok(sensor_reading(value, age_ms, min, max)),
+        _ => Err(LHMClientError::UnexpectedMessage),
+    }
+}
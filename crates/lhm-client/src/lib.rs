@@ -1,29 +1,76 @@
-use codec::LHMFrame;
+use alert::AlertRule;
+use codec::{LHMCodecError, LHMFrame};
+use lhm_shared::pipe::PipeTx;
 use parking_lot::Mutex;
-use pipe::{PipeFuture, PipeTx};
+use snapshot::{HardwareNode, Snapshot, SnapshotFilter};
 use std::{
     collections::HashMap,
     sync::{
         Arc,
         atomic::{AtomicU32, Ordering},
     },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use thiserror::Error;
-use tokio::{spawn, sync::oneshot};
+use tokio::{
+    spawn,
+    sync::{mpsc, oneshot},
+};
 use tokio_util::bytes::Bytes;
+use virtual_sensor::VirtualSensor;
 
+pub use batch::{Batch, BatchKey, BatchResults};
 pub use lhm_shared::*;
+pub use subscription::{EventSubscription, SensorSubscription};
 
-mod pipe;
+mod batch;
+mod subscription;
+mod transport;
 
-#[cfg(feature = "service")]
+#[cfg(all(windows, feature = "service"))]
 pub mod service;
 
+#[cfg(feature = "record")]
+pub mod recorder;
+
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+
+/// Value of a sensor along with how long ago it was updated
+#[derive(Debug, Clone, Copy)]
+pub struct SensorReading {
+    /// Value of the sensor
+    pub value: f32,
+    /// Time since the value was updated, [None] if the server
+    /// has not updated the sensor
+    pub age: Option<Duration>,
+    /// Minimum recorded value of the sensor
+    pub min: Option<f32>,
+    /// Maximum recorded value of the sensor
+    pub max: Option<f32>,
+}
+
+/// Creates a reading from the fields of a [PipeResponse::SensorValue]
+fn sensor_reading(
+    value: Option<f32>,
+    age_ms: Option<u64>,
+    min: Option<f32>,
+    max: Option<f32>,
+) -> Option<SensorReading> {
+    value.map(|value| SensorReading {
+        value,
+        age: age_ms.map(Duration::from_millis),
+        min,
+        max,
+    })
+}
+
 /// Handle to send requests through a [LHMClient]
 #[derive(Clone)]
 pub struct LHMClientHandle {
     tx: PipeTx,
     state: Arc<ClientState>,
+    server: Arc<ServerInfo>,
 }
 
 #[derive(Debug, Error)]
@@ -38,27 +85,78 @@ pub enum LHMClientError {
     RecvError,
     #[error("server error: {0}")]
     Server(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("server backend unavailable: {0}")]
+    BackendUnavailable(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("server timed out: {0}")]
+    Timeout(String),
+    #[error("limit exceeded: {0}")]
+    LimitExceeded(String),
     #[error("unexpected message")]
     UnexpectedMessage,
+    #[error("server does not support {0:?}")]
+    Unsupported(Capability),
+    #[error("failed to connect to service: {0}")]
+    Connect(std::io::Error),
+    #[error("server does not support protocol version {PROTOCOL_VERSION}")]
+    IncompatibleServer {
+        /// Details about the server, [None] if the server is too old
+        /// to understand the [PipeRequest::Hello] request
+        server: Option<ServerInfo>,
+    },
 
     /// Error from the underlying client
     #[error(transparent)]
-    Client(Arc<std::io::Error>),
+    Client(Arc<LHMCodecError>),
+}
+
+impl LHMClientError {
+    /// Create an error from an error response from the server
+    pub(crate) fn from_server(code: ErrorCode, message: String) -> Self {
+        match code {
+            ErrorCode::NotFound => LHMClientError::NotFound(message),
+            ErrorCode::InvalidRequest => LHMClientError::InvalidRequest(message),
+            ErrorCode::BackendUnavailable => LHMClientError::BackendUnavailable(message),
+            ErrorCode::PermissionDenied => LHMClientError::PermissionDenied(message),
+            ErrorCode::Timeout => LHMClientError::Timeout(message),
+            ErrorCode::LimitExceeded => LHMClientError::LimitExceeded(message),
+            ErrorCode::Internal | ErrorCode::Unknown => LHMClientError::Server(message),
+        }
+    }
 }
 
 impl LHMClientHandle {
+    /// Get the details about the connected server
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.server
+    }
+
     /// Get an error that has stopped the client if one has occurred
-    pub async fn client_error(&self) -> Option<Arc<std::io::Error>> {
+    pub async fn client_error(&self) -> Option<Arc<LHMCodecError>> {
         self.state.error.lock().await.clone()
     }
 
     async fn send_request(&self, request: PipeRequest) -> Result<PipeResponse, LHMClientError> {
-        let body = rmp_serde::to_vec(&request).map_err(LHMClientError::Encode)?;
-        let body = Bytes::from(body);
+        let body = encode_request(&request)?;
 
         let (tx, rx) = oneshot::channel();
         let id = self.state.subscriptions.insert(tx);
 
+        self.send_frame(id, body, rx).await
+    }
+
+    /// Sends a request frame using a specific ID, waiting for the response on `rx`
+    async fn send_frame(
+        &self,
+        id: u32,
+        body: Bytes,
+        rx: oneshot::Receiver<LHMFrame>,
+    ) -> Result<PipeResponse, LHMClientError> {
         if self.tx.send(LHMFrame { id, body }).is_err() {
             self.state.subscriptions.remove(id);
 
@@ -86,11 +184,53 @@ impl LHMClientHandle {
             rmp_serde::from_slice(&frame.body).map_err(LHMClientError::Decode)?;
 
         match msg {
-            PipeResponse::Error { error } => Err(LHMClientError::Server(error)),
+            PipeResponse::Error { code, message } => {
+                Err(LHMClientError::from_server(code, message))
+            }
             msg => Ok(msg),
         }
     }
 
+    /// Exchange versions with the server, checking that the server
+    /// is able to understand this client
+    async fn hello(&self) -> Result<ServerInfo, LHMClientError> {
+        let request = PipeRequest::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+
+        let server = match self.send_request(request).await {
+            Ok(PipeResponse::Hello { server }) => server,
+            Ok(_) => return Err(LHMClientError::UnexpectedMessage),
+
+            // Servers before the handshake was introduced will fail to
+            // parse the request, responding with an error this version
+            // is unable to decode
+            Err(LHMClientError::Decode(_)) => {
+                return Err(LHMClientError::IncompatibleServer { server: None });
+            }
+            Err(err) => return Err(err),
+        };
+
+        if !server.is_compatible() {
+            return Err(LHMClientError::IncompatibleServer {
+                server: Some(server),
+            });
+        }
+
+        Ok(server)
+    }
+
+    /// Sends a request without waiting for a response
+    fn send_request_detached(&self, request: PipeRequest) -> Result<(), LHMClientError> {
+        let body = encode_request(&request)?;
+        let id = self.state.subscriptions.next_id();
+
+        self.tx
+            .send(LHMFrame { id, body })
+            .map_err(|_| LHMClientError::SendError)
+    }
+
     /// Check if the underlying connection channel is closed
     pub fn is_closed(&self) -> bool {
         self.tx.is_closed()
@@ -166,8 +306,9 @@ impl LHMClientHandle {
     /// This is more efficient than sending the large identifier string
     /// in case where you are repeatedly calling update
     ///
-    /// Note: Cache indexes will change each time [Self::update_all] is called
-    /// you must ensure you obtain the latest cache index.
+    /// Note: Cache indexes are only stable across [Self::update_all] when the
+    /// server supports [Capability::CacheGeneration], otherwise you must
+    /// ensure you obtain the latest cache index.
     pub async fn update_hardware_by_idx(&self, idx: usize) -> Result<(), LHMClientError> {
         match self
             .send_request(PipeRequest::UpdateHardwareByIndex { idx })
@@ -199,7 +340,7 @@ impl LHMClientHandle {
             .send_request(PipeRequest::GetSensorValueById { id, update })
             .await?
         {
-            PipeResponse::SensorValue { value } => Ok(value),
+            PipeResponse::SensorValue { value, .. } => Ok(value),
             _ => Err(LHMClientError::UnexpectedMessage),
         }
     }
@@ -209,8 +350,9 @@ impl LHMClientHandle {
     /// This is more efficient than sending the large identifier string
     /// in case where you are repeatedly loading the value
     ///
-    /// Note: Cache indexes will change each time [Self::update_all] is called
-    /// you must ensure you obtain the latest cache index.
+    /// Note: Cache indexes are only stable across [Self::update_all] when the
+    /// server supports [Capability::CacheGeneration], otherwise you must
+    /// ensure you obtain the latest cache index.
     pub async fn get_sensor_value_by_idx(
         &self,
         idx: usize,
@@ -220,7 +362,56 @@ impl LHMClientHandle {
             .send_request(PipeRequest::GetSensorValueByIndex { idx, update })
             .await?
         {
-            PipeResponse::SensorValue { value } => Ok(value),
+            PipeResponse::SensorValue { value, .. } => Ok(value),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Get the value of a specific sensor by ID along with the age of the value
+    ///
+    /// If `update` true is provided the sensor will be updated before
+    /// querying, unless the server refreshes the hardware in the
+    /// background (See [Capability::BackgroundRefresh])
+    pub async fn get_sensor_reading_by_id(
+        &self,
+        id: String,
+        update: bool,
+    ) -> Result<Option<SensorReading>, LHMClientError> {
+        match self
+            .send_request(PipeRequest::GetSensorValueById { id, update })
+            .await?
+        {
+            PipeResponse::SensorValue {
+                value,
+                age_ms,
+                min,
+                max,
+            } => Ok(sensor_reading(value, age_ms, min, max)),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Get the value of a specific sensor using its cache index along with
+    /// the age of the value
+    ///
+    /// Note: Cache indexes are only stable across [Self::update_all] when the
+    /// server supports [Capability::CacheGeneration], otherwise you must
+    /// ensure you obtain the latest cache index.
+    pub async fn get_sensor_reading_by_idx(
+        &self,
+        idx: usize,
+        update: bool,
+    ) -> Result<Option<SensorReading>, LHMClientError> {
+        match self
+            .send_request(PipeRequest::GetSensorValueByIndex { idx, update })
+            .await?
+        {
+            PipeResponse::SensorValue {
+                value,
+                age_ms,
+                min,
+                max,
+            } => Ok(sensor_reading(value, age_ms, min, max)),
             _ => Err(LHMClientError::UnexpectedMessage),
         }
     }
@@ -259,8 +450,9 @@ impl LHMClientHandle {
     /// This is more efficient than sending the large identifier string
     /// in case where you are repeatedly calling update
     ///
-    /// Note: Cache indexes will change each time [Self::update_all] is called
-    /// you must ensure you obtain the latest cache index.
+    /// Note: Cache indexes are only stable across [Self::update_all] when the
+    /// server supports [Capability::CacheGeneration], otherwise you must
+    /// ensure you obtain the latest cache index.
     pub async fn update_sensor_by_idx(&self, idx: usize) -> Result<(), LHMClientError> {
         match self
             .send_request(PipeRequest::UpdateSensorByIndex { idx })
@@ -270,31 +462,396 @@ impl LHMClientHandle {
             _ => Err(LHMClientError::UnexpectedMessage),
         }
     }
+
+    /// Resets the minimum and maximum recorded values of a sensor by ID
+    pub async fn reset_sensor_min_max(&self, id: String) -> Result<(), LHMClientError> {
+        match self
+            .send_request(PipeRequest::ResetSensorMinMax { id })
+            .await?
+        {
+            PipeResponse::Success => Ok(()),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Resets the minimum and maximum recorded values of all the sensors
+    /// belonging to a hardware item by ID
+    pub async fn reset_hardware_min_max(&self, id: String) -> Result<(), LHMClientError> {
+        match self
+            .send_request(PipeRequest::ResetHardwareMinMax { id })
+            .await?
+        {
+            PipeResponse::Success => Ok(()),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Resets the minimum and maximum recorded values of all sensors
+    pub async fn reset_all_min_max(&self) -> Result<(), LHMClientError> {
+        match self.send_request(PipeRequest::ResetAllMinMax).await? {
+            PipeResponse::Success => Ok(()),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Sets a software controlled value for a [SensorType::Control] sensor
+    /// such as a fan or pump, `percent` must be between 0 and 100
+    ///
+    /// The default control is restored automatically when the client
+    /// disconnects
+    pub async fn set_sensor_control(&self, id: String, percent: f32) -> Result<(), LHMClientError> {
+        if !self.server.has_capability(Capability::SensorControl) {
+            return Err(LHMClientError::Unsupported(Capability::SensorControl));
+        }
+
+        match self
+            .send_request(PipeRequest::SetSensorControl { id, value: percent })
+            .await?
+        {
+            PipeResponse::Success => Ok(()),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Restores the default control for a [SensorType::Control] sensor
+    pub async fn reset_sensor_control(&self, id: String) -> Result<(), LHMClientError> {
+        if !self.server.has_capability(Capability::SensorControl) {
+            return Err(LHMClientError::Unsupported(Capability::SensorControl));
+        }
+
+        match self
+            .send_request(PipeRequest::ResetSensorControl { id })
+            .await?
+        {
+            PipeResponse::Success => Ok(()),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Get the recorded history of a sensor by ID
+    ///
+    /// `since` Only includes samples recorded at or after this time
+    /// `max_points` Limits the number of points provided
+    /// `downsample` Groups the samples into `max_points` buckets each with
+    /// the min, average and max value, instead of providing only the latest
+    /// `max_points` samples
+    pub async fn get_sensor_history(
+        &self,
+        id: String,
+        since: Option<SystemTime>,
+        max_points: Option<usize>,
+        downsample: bool,
+    ) -> Result<Option<Vec<HistoryPoint>>, LHMClientError> {
+        if !self.server.has_capability(Capability::SensorHistory) {
+            return Err(LHMClientError::Unsupported(Capability::SensorHistory));
+        }
+
+        let since = since.map(|since| {
+            since
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_millis() as u64)
+                .unwrap_or_default()
+        });
+
+        match self
+            .send_request(PipeRequest::GetSensorHistory {
+                id,
+                since,
+                max_points,
+                downsample,
+            })
+            .await?
+        {
+            PipeResponse::SensorHistory { points } => Ok(points),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Get the generation of the server cache
+    ///
+    /// The generation changes each time hardware or sensors are added or
+    /// removed, cache indexes obtained during a generation remain valid
+    /// while the generation is unchanged
+    pub async fn get_cache_generation(&self) -> Result<u64, LHMClientError> {
+        if !self.server.has_capability(Capability::CacheGeneration) {
+            return Err(LHMClientError::Unsupported(Capability::CacheGeneration));
+        }
+
+        match self.send_request(PipeRequest::GetCacheGeneration).await? {
+            PipeResponse::CacheGeneration { generation } => Ok(generation),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Get the hardware hierarchy along with the sensors of each hardware
+    ///
+    /// `root_id` Provides only the tree starting at the hardware with a
+    /// specific ID, all top level hardware are provided when [None]
+    pub async fn get_hardware_tree(
+        &self,
+        root_id: Option<String>,
+    ) -> Result<Vec<HardwareNode>, LHMClientError> {
+        if !self.server.has_capability(Capability::HardwareTree) {
+            return Err(LHMClientError::Unsupported(Capability::HardwareTree));
+        }
+
+        match self
+            .send_request(PipeRequest::GetHardwareTree { root_id })
+            .await?
+        {
+            PipeResponse::HardwareTree { nodes } => Ok(nodes),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Get a snapshot of the whole hardware tree along with the values
+    /// of every sensor in a single request
+    ///
+    /// If `update` true is provided all the hardware will be updated
+    /// before taking the snapshot, replacing the need to call
+    /// [Self::update_all] first
+    ///
+    /// `filter` Filters the hardware and sensors included in the snapshot
+    pub async fn get_snapshot(
+        &self,
+        update: bool,
+        filter: SnapshotFilter,
+    ) -> Result<Snapshot, LHMClientError> {
+        if !self.server.has_capability(Capability::Snapshot) {
+            return Err(LHMClientError::Unsupported(Capability::Snapshot));
+        }
+
+        match self
+            .send_request(PipeRequest::GetSnapshot { update, filter })
+            .await?
+        {
+            PipeResponse::Snapshot { snapshot } => Ok(snapshot),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Adds an alert rule to the server, replacing any existing rule
+    /// with the same name
+    ///
+    /// Alerts are evaluated by the server when the hardware is updated and
+    /// are provided as [ServerEvent::AlertFired] and [ServerEvent::AlertCleared]
+    /// events through [Self::subscribe_events]
+    pub async fn add_alert_rule(&self, rule: AlertRule) -> Result<(), LHMClientError> {
+        if !self.server.has_capability(Capability::Alerts) {
+            return Err(LHMClientError::Unsupported(Capability::Alerts));
+        }
+
+        match self
+            .send_request(PipeRequest::AddAlertRule { rule })
+            .await?
+        {
+            PipeResponse::Success => Ok(()),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Removes an alert rule from the server by name, any alerts active
+    /// for the rule are cleared
+    pub async fn remove_alert_rule(&self, name: String) -> Result<(), LHMClientError> {
+        if !self.server.has_capability(Capability::Alerts) {
+            return Err(LHMClientError::Unsupported(Capability::Alerts));
+        }
+
+        match self
+            .send_request(PipeRequest::RemoveAlertRule { name })
+            .await?
+        {
+            PipeResponse::Success => Ok(()),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Get the alert rules registered on the server
+    pub async fn list_alert_rules(&self) -> Result<Vec<AlertRule>, LHMClientError> {
+        if !self.server.has_capability(Capability::Alerts) {
+            return Err(LHMClientError::Unsupported(Capability::Alerts));
+        }
+
+        match self.send_request(PipeRequest::ListAlertRules).await? {
+            PipeResponse::AlertRules { rules } => Ok(rules),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Adds a virtual sensor to the server, replacing any existing virtual
+    /// sensor with the same ID
+    ///
+    /// Virtual sensors are computed by the server when the hardware is updated
+    /// and are provided by [Self::query_sensors] and [Self::get_sensor_by_id]
+    /// like any other sensor
+    pub async fn add_virtual_sensor(&self, sensor: VirtualSensor) -> Result<(), LHMClientError> {
+        if !self.server.has_capability(Capability::VirtualSensors) {
+            return Err(LHMClientError::Unsupported(Capability::VirtualSensors));
+        }
+
+        match self
+            .send_request(PipeRequest::AddVirtualSensor { sensor })
+            .await?
+        {
+            PipeResponse::Success => Ok(()),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Removes a virtual sensor from the server by ID
+    pub async fn remove_virtual_sensor(&self, id: String) -> Result<(), LHMClientError> {
+        if !self.server.has_capability(Capability::VirtualSensors) {
+            return Err(LHMClientError::Unsupported(Capability::VirtualSensors));
+        }
+
+        match self
+            .send_request(PipeRequest::RemoveVirtualSensor { id })
+            .await?
+        {
+            PipeResponse::Success => Ok(()),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Get the virtual sensors registered on the server
+    pub async fn list_virtual_sensors(&self) -> Result<Vec<VirtualSensor>, LHMClientError> {
+        if !self.server.has_capability(Capability::VirtualSensors) {
+            return Err(LHMClientError::Unsupported(Capability::VirtualSensors));
+        }
+
+        match self.send_request(PipeRequest::ListVirtualSensors).await? {
+            PipeResponse::VirtualSensors { sensors } => Ok(sensors),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Create a [Batch] for sending multiple requests in a single frame
+    ///
+    /// Requires the server to support [Capability::Batch]
+    pub fn batch(&self) -> Batch<'_> {
+        Batch::new(self)
+    }
+
+    /// Subscribe to the values of a collection of sensors
+    ///
+    /// The server will update the sensors every `interval` and push the latest
+    /// values through the returned [SensorSubscription] stream. Dropping the
+    /// stream will unsubscribe
+    pub async fn subscribe(
+        &self,
+        sensor_ids: Vec<String>,
+        interval: Duration,
+    ) -> Result<SensorSubscription, LHMClientError> {
+        if !self.server.has_capability(Capability::Subscriptions) {
+            return Err(LHMClientError::Unsupported(Capability::Subscriptions));
+        }
+
+        let request = PipeRequest::Subscribe {
+            sensor_ids,
+            interval_ms: interval.as_millis() as u64,
+        };
+
+        self.send_subscribe(request, SensorSubscription::new).await
+    }
+
+    /// Subscribe to the events emitted by the server, such as hardware
+    /// being added or removed
+    ///
+    /// Hardware changes are detected when the hardware is reloaded, either
+    /// by [Self::update_all] or periodically by the background refresh
+    /// (See [Capability::BackgroundRefresh]). Dropping the stream will
+    /// unsubscribe
+    pub async fn subscribe_events(&self) -> Result<EventSubscription, LHMClientError> {
+        if !self.server.has_capability(Capability::Events) {
+            return Err(LHMClientError::Unsupported(Capability::Events));
+        }
+
+        self.send_subscribe(PipeRequest::SubscribeEvents, EventSubscription::new)
+            .await
+    }
+
+    /// Sends a subscribe request, creating the subscription stream
+    /// using `create` from the subscription ID and receiver
+    async fn send_subscribe<S>(
+        &self,
+        request: PipeRequest,
+        create: impl FnOnce(u32, mpsc::UnboundedReceiver<LHMFrame>, LHMClientHandle) -> S,
+    ) -> Result<S, LHMClientError> {
+        let body = encode_request(&request)?;
+
+        let (tx, rx) = oneshot::channel();
+        let id = self.state.subscriptions.insert(tx);
+
+        // Stream must be registered before sending the request so that no
+        // values are missed, values after the first response are sent to it
+        let (stream_tx, stream_rx) = mpsc::unbounded_channel();
+        self.state.subscriptions.insert_stream(id, stream_tx);
+
+        // Created early so the stream is cleaned up if subscribing fails
+        let subscription = create(id, stream_rx, self.clone());
+
+        match self.send_frame(id, body, rx).await? {
+            PipeResponse::Subscribed { .. } => Ok(subscription),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        }
+    }
+
+    /// Stop a subscription created by [Self::subscribe] or [Self::subscribe_events]
+    fn unsubscribe(&self, subscription_id: u32) {
+        self.state.subscriptions.remove_stream(subscription_id);
+
+        // Response is not required, the subscription is removed on our end
+        _ = self.send_request_detached(PipeRequest::Unsubscribe { subscription_id });
+    }
+}
+
+/// Encodes a request to be sent as the body of a frame
+fn encode_request(request: &PipeRequest) -> Result<Bytes, LHMClientError> {
+    let body = rmp_serde::to_vec(request).map_err(LHMClientError::Encode)?;
+    Ok(Bytes::from(body))
 }
 
 struct ClientState {
     /// Subscriptions state
     subscriptions: Subscriptions,
     /// Error that has occurred on the connection and is irrecoverable from
-    error: tokio::sync::Mutex<Option<Arc<std::io::Error>>>,
+    error: tokio::sync::Mutex<Option<Arc<LHMCodecError>>>,
 }
 
 #[derive(Default)]
 struct Subscriptions {
     id: AtomicU32,
     value: Mutex<HashMap<u32, oneshot::Sender<LHMFrame>>>,
+    /// Channels for frames pushed by the server for sensor subscriptions
+    streams: Mutex<HashMap<u32, mpsc::UnboundedSender<LHMFrame>>>,
 }
 
 impl Subscriptions {
+    pub fn next_id(&self) -> u32 {
+        self.id.fetch_add(1, Ordering::AcqRel)
+    }
+
     pub fn insert(&self, tx: oneshot::Sender<LHMFrame>) -> u32 {
-        let id = self.id.fetch_add(1, Ordering::AcqRel);
+        let id = self.next_id();
         self.value.lock().insert(id, tx);
         id
     }
 
+    pub fn insert_stream(&self, id: u32, tx: mpsc::UnboundedSender<LHMFrame>) {
+        self.streams.lock().insert(id, tx);
+    }
+
     pub fn invoke(&self, id: u32, msg: LHMFrame) {
         if let Some(tx) = self.value.lock().remove(&id) {
             _ = tx.send(msg);
+            return;
+        }
+
+        let mut streams = self.streams.lock();
+        if let Some(tx) = streams.get(&id)
+            && tx.send(msg).is_err()
+        {
+            streams.remove(&id);
         }
     }
 
@@ -302,8 +859,31 @@ impl Subscriptions {
         self.value.lock().remove(&id);
     }
 
+    pub fn remove_stream(&self, id: u32) {
+        self.streams.lock().remove(&id);
+    }
+
     pub fn clear(&self) {
         self.value.lock().clear();
+        self.streams.lock().clear();
+    }
+}
+
+/// Configuration for connecting to the server
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Transport to connect to the server through
+    pub transport: Transport,
+    /// Maximum length of a frame accepted from the server
+    pub max_frame_length: usize,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            transport: Transport::default(),
+            max_frame_length: codec::DEFAULT_MAX_FRAME_LENGTH,
+        }
     }
 }
 
@@ -311,15 +891,28 @@ impl Subscriptions {
 pub struct LHMClient;
 
 impl LHMClient {
-    /// Connect to the LHM service
-    pub async fn connect() -> std::io::Result<LHMClientHandle> {
+    /// Connect to the LHM service using the default config
+    ///
+    /// Exchanges versions with the service once connected, failing with
+    /// [LHMClientError::IncompatibleServer] if the service is unable to
+    /// understand this client
+    pub async fn connect() -> Result<LHMClientHandle, LHMClientError> {
+        Self::connect_with_config(ClientConfig::default()).await
+    }
+
+    /// Connect to the LHM service using the provided config
+    pub async fn connect_with_config(
+        config: ClientConfig,
+    ) -> Result<LHMClientHandle, LHMClientError> {
         let subscriptions = Subscriptions::default();
         let state = Arc::new(ClientState {
             subscriptions,
             error: Default::default(),
         });
 
-        let (future, rx, tx) = PipeFuture::connect().await?;
+        let (future, rx, tx) = transport::connect(&config.transport, config.max_frame_length)
+            .await
+            .map_err(LHMClientError::Connect)?;
 
         // Spawn handler to run the pipe itself
         spawn({
@@ -347,7 +940,14 @@ impl LHMClient {
             }
         });
 
-        let handle = LHMClientHandle { state, tx };
+        let mut handle = LHMClientHandle {
+            state,
+            tx,
+            server: Default::default(),
+        };
+
+        let server = handle.hello().await?;
+        handle.server = Arc::new(server);
 
         Ok(handle)
     }
@@ -0,0 +1,665 @@
+//! Publishing of sensor values to an MQTT broker, optionally along with
+//! Home Assistant discovery messages so the sensors are added to Home
+//! Assistant automatically
+
+use crate::{
+    Hardware, LHMClientError, LHMClientHandle, Sensor, SensorType, snapshot::HardwareNode,
+};
+use rumqttc::{AsyncClient, ClientError, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    spawn,
+    time::{MissedTickBehavior, interval, sleep},
+};
+
+pub use rumqttc;
+
+/// Number of outgoing messages buffered before publishing waits
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Delay before reconnecting after the connection to the broker fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum MqttError {
+    /// Error from the LHM client
+    #[error(transparent)]
+    Client(#[from] LHMClientError),
+    /// Error from the MQTT client
+    #[error(transparent)]
+    Mqtt(#[from] ClientError),
+}
+
+/// Configuration for a [MqttPublisher]
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    /// Options for connecting to the broker
+    pub options: MqttOptions,
+    /// Unique ID of this machine, included in the topics and
+    /// discovery IDs so multiple machines can share a broker
+    pub node_id: String,
+    /// Prefix for the topics the values are published to
+    pub topic_prefix: String,
+    /// Interval between publishing the sensor values
+    pub interval: Duration,
+    /// Quality of service used for publishing
+    pub qos: QoS,
+    /// Prefix for the retained Home Assistant discovery messages,
+    /// discovery messages are not published when [None]
+    pub discovery_prefix: Option<String>,
+}
+
+impl MqttConfig {
+    /// Create a config for a broker at `host` and `port`, Home Assistant
+    /// discovery is enabled using the default prefix
+    pub fn new(node_id: impl Into<String>, host: impl Into<String>, port: u16) -> Self {
+        let node_id = sanitize_id(&node_id.into());
+
+        Self {
+            options: MqttOptions::new(format!("lhm-{node_id}"), host, port),
+            node_id,
+            topic_prefix: "lhm".to_string(),
+            interval: Duration::from_secs(5),
+            qos: QoS::AtLeastOnce,
+            discovery_prefix: Some("homeassistant".to_string()),
+        }
+    }
+
+    /// Topic the availability of the publisher is published to
+    fn availability_topic(&self) -> String {
+        format!("{}/{}/status", self.topic_prefix, self.node_id)
+    }
+
+    /// Topic the value of a sensor is published to, derived from
+    /// the sensor identifier (e.g `lhm/{node_id}/intelcpu/0/temperature/0`)
+    fn state_topic(&self, sensor: &Sensor) -> String {
+        let path: String = sensor
+            .identifier
+            .trim_matches('/')
+            .chars()
+            .map(|c| match c {
+                // Wildcards are not allowed within topic names
+                '+' | '#' => '_',
+                c => c,
+            })
+            .collect();
+
+        format!("{}/{}/{}", self.topic_prefix, self.node_id, path)
+    }
+
+    /// Topic the Home Assistant discovery message for a sensor is published to
+    fn discovery_topic(&self, discovery_prefix: &str, sensor: &Sensor) -> String {
+        format!(
+            "{}/sensor/{}/{}/config",
+            discovery_prefix,
+            self.node_id,
+            sanitize_id(&sensor.identifier)
+        )
+    }
+
+    /// Creates the Home Assistant discovery message for a sensor, sensors
+    /// without any hardware such as virtual sensors share a single device
+    fn discovery_payload(&self, hardware: Option<&Hardware>, sensor: &Sensor) -> String {
+        let node_id = &self.node_id;
+        let (device_class, unit) = discovery_class(sensor.ty);
+
+        let device = match hardware {
+            Some(hardware) => json!({
+                "identifiers": [format!("lhm_{node_id}_{}", sanitize_id(&hardware.identifier))],
+                "name": hardware.name,
+                "manufacturer": "LibreHardwareMonitor",
+                "model": format!("{:?}", hardware.ty),
+            }),
+            None => json!({
+                "identifiers": [format!("lhm_{node_id}_virtual")],
+                "name": "Virtual Sensors",
+                "manufacturer": "LibreHardwareMonitor",
+                "model": "Virtual",
+            }),
+        };
+
+        let mut payload = json!({
+            "name": sensor.name,
+            "unique_id": format!("lhm_{node_id}_{}", sanitize_id(&sensor.identifier)),
+            "state_topic": self.state_topic(sensor),
+            "availability_topic": self.availability_topic(),
+            "state_class": "measurement",
+            "device": device,
+        });
+
+        if let Some(device_class) = device_class {
+            payload["device_class"] = device_class.into();
+        }
+
+        if let Some(unit) = unit {
+            payload["unit_of_measurement"] = unit.into();
+        }
+
+        payload.to_string()
+    }
+}
+
+/// Periodically publishes the values of the sensors loaded by the server
+/// to an MQTT broker
+///
+/// The published sensors are the sensors of the hardware currently loaded
+/// by the server along with any virtual sensors, [LHMClientHandle::set_options]
+/// should be called before running the publisher
+pub struct MqttPublisher {
+    /// Handle to the client the sensors are read from
+    handle: LHMClientHandle,
+    /// Configuration for publishing
+    config: MqttConfig,
+    /// Identifiers of the sensors a discovery message has been published
+    /// for since connecting to the broker
+    discovered: HashSet<String>,
+}
+
+impl MqttPublisher {
+    pub fn new(handle: LHMClientHandle, config: MqttConfig) -> Self {
+        Self {
+            handle,
+            config,
+            discovered: HashSet::new(),
+        }
+    }
+
+    /// Publishes the sensor values at the configured interval until
+    /// the client fails
+    ///
+    /// The connection to the broker is re-established automatically, messages
+    /// are buffered while the broker is unavailable and publishing waits once
+    /// the buffer is full. The availability and discovery messages are
+    /// published again each time the connection is established
+    pub async fn run(mut self) -> Result<(), MqttError> {
+        let availability_topic = self.config.availability_topic();
+
+        // Broker marks the publisher as offline if it disconnects
+        let mut options = self.config.options.clone();
+        options.set_last_will(LastWill::new(
+            &availability_topic,
+            "offline",
+            self.config.qos,
+            true,
+        ));
+
+        let (client, mut event_loop) = AsyncClient::new(options, CHANNEL_CAPACITY);
+        let connected = Arc::new(AtomicBool::new(false));
+
+        // Spawn task to drive the connection to the broker
+        let connection = spawn({
+            let connected = connected.clone();
+
+            async move {
+                loop {
+                    match event_loop.poll().await {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            connected.store(true, Ordering::Release);
+                        }
+                        Ok(_) => {}
+                        Err(_) => sleep(RECONNECT_DELAY).await,
+                    }
+                }
+            }
+        });
+
+        let result = self
+            .publish_loop(&client, &availability_topic, &connected)
+            .await;
+
+        // Dropping the connection publishes the last will
+        connection.abort();
+
+        result
+    }
+
+    async fn publish_loop(
+        &mut self,
+        client: &AsyncClient,
+        availability_topic: &str,
+        connected: &AtomicBool,
+    ) -> Result<(), MqttError> {
+        let mut interval = interval(self.config.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            // Broker may have lost the retained messages while disconnected
+            if connected.swap(false, Ordering::AcqRel) {
+                self.discovered.clear();
+                client
+                    .publish(availability_topic, self.config.qos, true, "online")
+                    .await?;
+            }
+
+            self.publish(client).await?;
+        }
+    }
+
+    /// Updates the hardware and publishes the current value of every
+    /// sensor, along with discovery messages for any new sensors
+    async fn publish(&mut self, client: &AsyncClient) -> Result<(), MqttError> {
+        let mut batch = self.handle.batch();
+        let update = batch.update_all();
+        let query = batch.query_sensors(None, None);
+
+        let mut results = batch.send().await?;
+        results.take(update)?;
+        let sensors = results.take(query)?;
+
+        if let Some(discovery_prefix) = &self.config.discovery_prefix
+            && sensors
+                .iter()
+                .any(|sensor| !self.discovered.contains(&sensor.identifier))
+        {
+            // Hardware of each sensor is only needed for the discovery messages
+            let tree = self.handle.get_hardware_tree(None).await?;
+            let mut hardware = HashMap::new();
+            collect_sensor_hardware(&tree, &mut hardware);
+
+            for sensor in &sensors {
+                if self.discovered.contains(&sensor.identifier) {
+                    continue;
+                }
+
+                let topic = self.config.discovery_topic(discovery_prefix, sensor);
+                let payload = self
+                    .config
+                    .discovery_payload(hardware.get(sensor.identifier.as_str()).copied(), sensor);
+                client
+                    .publish(topic, self.config.qos, true, payload)
+                    .await?;
+
+                self.discovered.insert(sensor.identifier.clone());
+            }
+        }
+
+        for sensor in &sensors {
+            // Sensors without a value are not published
+            if sensor.value.is_nan() {
+                continue;
+            }
+
+            client
+                .publish(
+                    self.config.state_topic(sensor),
+                    self.config.qos,
+                    false,
+                    sensor.value.to_string(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the hardware each sensor within the tree belongs to
+fn collect_sensor_hardware<'a>(
+    nodes: &'a [HardwareNode],
+    output: &mut HashMap<&'a str, &'a Hardware>,
+) {
+    for node in nodes {
+        for sensor in &node.sensors {
+            output.insert(sensor.identifier.as_str(), &node.hardware);
+        }
+
+        collect_sensor_hardware(&node.children, output);
+    }
+}
+
+/// Home Assistant device class and unit for a type of sensor
+fn discovery_class(ty: SensorType) -> (Option<&'static str>, Option<&'static str>) {
+    match ty {
+        SensorType::Voltage => (Some("voltage"), Some("V")),
+        SensorType::Current => (Some("current"), Some("A")),
+        SensorType::Power => (Some("power"), Some("W")),
+        SensorType::Clock => (Some("frequency"), Some("MHz")),
+        SensorType::Temperature => (Some("temperature"), Some("°C")),
+        SensorType::Load => (None, Some("%")),
+        SensorType::Frequency => (Some("frequency"), Some("Hz")),
+        SensorType::Fan => (None, Some("RPM")),
+        SensorType::Flow => (None, Some("L/h")),
+        SensorType::Control => (None, Some("%")),
+        SensorType::Level => (None, Some("%")),
+        SensorType::Factor => (None, None),
+        SensorType::Data => (Some("data_size"), Some("GB")),
+        SensorType::SmallData => (Some("data_size"), Some("MB")),
+        SensorType::Throughput => (Some("data_rate"), Some("B/s")),
+        SensorType::TimeSpan => (Some("duration"), Some("s")),
+        SensorType::Energy => (Some("energy_storage"), Some("mWh")),
+        SensorType::Noise => (Some("sound_pressure"), Some("dBA")),
+        SensorType::Conductivity => (None, Some("µS/cm")),
+        SensorType::Humidity => (Some("humidity"), Some("%")),
+        SensorType::Unknown(_) => (None, None),
+    }
+}
+
+/// Converts a value into an ID only containing characters allowed
+/// within Home Assistant discovery IDs
+fn sanitize_id(value: &str) -> String {
+    value
+        .trim_matches('/')
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MqttConfig, MqttPublisher, sanitize_id};
+    use crate::{
+        ClientConfig, ComputerOptions, Hardware, HardwareType, LHMClient, Sensor, SensorType,
+        Transport,
+        alert::SensorSelector,
+        virtual_sensor::{VirtualFormula, VirtualSensor},
+    };
+    use lhm_server::{
+        ServerConfig,
+        backend::memory::test_hardware,
+        permission::{Permission, PermissionRules},
+        run_server_with,
+    };
+    use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+    use serde_json::Value;
+    use std::{collections::HashMap, net::TcpListener, sync::Arc, time::Duration};
+    use tokio::{
+        spawn,
+        time::{sleep, timeout},
+    };
+
+    fn hardware(identifier: &str) -> Hardware {
+        Hardware {
+            index: 0,
+            identifier: identifier.to_string(),
+            name: "Test CPU".to_string(),
+            ty: HardwareType::Cpu,
+            parent_index: None,
+            parent_identifier: None,
+        }
+    }
+
+    fn sensor(identifier: &str, ty: SensorType) -> Sensor {
+        Sensor {
+            index: 0,
+            identifier: identifier.to_string(),
+            name: "CPU Package".to_string(),
+            ty,
+            value: 50.0,
+            min: 50.0,
+            max: 50.0,
+        }
+    }
+
+    #[test]
+    fn test_sanitize_id() {
+        assert_eq!(
+            sanitize_id("/intelcpu/0/temperature/0"),
+            "intelcpu_0_temperature_0"
+        );
+        assert_eq!(sanitize_id("my pc.local"), "my_pc_local");
+        assert_eq!(sanitize_id("node-1_a"), "node-1_a");
+    }
+
+    #[test]
+    fn test_config_sanitizes_node_id() {
+        let config = MqttConfig::new("My PC/1", "localhost", 1883);
+
+        assert_eq!(config.node_id, "My_PC_1");
+        assert_eq!(config.options.client_id(), "lhm-My_PC_1");
+        assert_eq!(config.availability_topic(), "lhm/My_PC_1/status");
+    }
+
+    #[test]
+    fn test_state_topic() {
+        let config = MqttConfig::new("node", "localhost", 1883);
+
+        assert_eq!(
+            config.state_topic(&sensor(
+                "/intelcpu/0/temperature/0",
+                SensorType::Temperature
+            )),
+            "lhm/node/intelcpu/0/temperature/0"
+        );
+
+        // Wildcards are replaced so the topic can be published to
+        assert_eq!(
+            config.state_topic(&sensor("/virtual/+/#", SensorType::Load)),
+            "lhm/node/virtual/_/_"
+        );
+    }
+
+    #[test]
+    fn test_discovery_topic() {
+        let config = MqttConfig::new("node", "localhost", 1883);
+
+        assert_eq!(
+            config.discovery_topic(
+                "homeassistant",
+                &sensor("/intelcpu/0/temperature/0", SensorType::Temperature)
+            ),
+            "homeassistant/sensor/node/intelcpu_0_temperature_0/config"
+        );
+    }
+
+    #[test]
+    fn test_discovery_payload() {
+        let config = MqttConfig::new("node", "localhost", 1883);
+        let payload = config.discovery_payload(
+            Some(&hardware("/intelcpu/0")),
+            &sensor("/intelcpu/0/temperature/0", SensorType::Temperature),
+        );
+        let payload: Value = serde_json::from_str(&payload).unwrap();
+
+        assert_eq!(payload["name"], "CPU Package");
+        assert_eq!(payload["unique_id"], "lhm_node_intelcpu_0_temperature_0");
+        assert_eq!(payload["state_topic"], "lhm/node/intelcpu/0/temperature/0");
+        assert_eq!(payload["availability_topic"], "lhm/node/status");
+        assert_eq!(payload["state_class"], "measurement");
+        assert_eq!(payload["device_class"], "temperature");
+        assert_eq!(payload["unit_of_measurement"], "°C");
+        assert_eq!(payload["device"]["identifiers"][0], "lhm_node_intelcpu_0");
+        assert_eq!(payload["device"]["name"], "Test CPU");
+        assert_eq!(payload["device"]["manufacturer"], "LibreHardwareMonitor");
+        assert_eq!(payload["device"]["model"], "Cpu");
+    }
+
+    #[test]
+    fn test_discovery_payload_without_class() {
+        let config = MqttConfig::new("node", "localhost", 1883);
+        let payload = config.discovery_payload(
+            Some(&hardware("/intelcpu/0")),
+            &sensor("/intelcpu/0/factor/0", SensorType::Factor),
+        );
+        let payload: Value = serde_json::from_str(&payload).unwrap();
+
+        assert!(payload.get("device_class").is_none());
+        assert!(payload.get("unit_of_measurement").is_none());
+    }
+
+    #[test]
+    fn test_discovery_payload_virtual() {
+        let config = MqttConfig::new("node", "localhost", 1883);
+        let payload =
+            config.discovery_payload(None, &sensor("/virtual/cpu-max", SensorType::Temperature));
+        let payload: Value = serde_json::from_str(&payload).unwrap();
+
+        assert_eq!(payload["unique_id"], "lhm_node_virtual_cpu-max");
+        assert_eq!(payload["state_topic"], "lhm/node/virtual/cpu-max");
+        assert_eq!(payload["device"]["identifiers"][0], "lhm_node_virtual");
+        assert_eq!(payload["device"]["model"], "Virtual");
+    }
+
+    /// Publishes the sensors of an in-memory server to a real broker and
+    /// checks the messages received by a subscriber
+    ///
+    /// The broker address is read from `LHM_MQTT_TEST_BROKER` as `host:port`,
+    /// defaulting to `127.0.0.1:1883`
+    #[tokio::test]
+    #[ignore = "requires an MQTT broker, set LHM_MQTT_TEST_BROKER to its address"]
+    async fn test_publish_to_broker() {
+        let broker =
+            std::env::var("LHM_MQTT_TEST_BROKER").unwrap_or_else(|_| "127.0.0.1:1883".to_string());
+        let (host, port) = broker
+            .rsplit_once(':')
+            .expect("broker address missing port");
+        let port: u16 = port.parse().expect("invalid broker port");
+
+        // Reserve a free port for the server
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        spawn(run_server_with(
+            ServerConfig {
+                transport: Transport::Tcp(address),
+                policy: Arc::new(PermissionRules::allow_all(Permission::Configure)),
+                ..Default::default()
+            },
+            test_hardware().backend,
+        ));
+
+        // Wait for the server to start listening
+        let handle = timeout(Duration::from_secs(5), async {
+            loop {
+                let config = ClientConfig {
+                    transport: Transport::Tcp(address),
+                    ..Default::default()
+                };
+
+                match LHMClient::connect_with_config(config).await {
+                    Ok(handle) => break handle,
+                    Err(_) => sleep(Duration::from_millis(50)).await,
+                }
+            }
+        })
+        .await
+        .expect("server did not start");
+
+        handle.set_options(ComputerOptions::all()).await.unwrap();
+        handle
+            .add_virtual_sensor(VirtualSensor {
+                id: "cpu-max".to_string(),
+                name: "CPU Max".to_string(),
+                ty: SensorType::Temperature,
+                formula: VirtualFormula::Max {
+                    inputs: vec![SensorSelector::Type {
+                        ty: SensorType::Temperature,
+                        hardware_ty: Some(HardwareType::Cpu),
+                    }],
+                },
+            })
+            .await
+            .unwrap();
+
+        let node_id = format!("test-{}", std::process::id());
+        let discovery_prefix = format!("homeassistant/sensor/{node_id}/");
+        let state_prefix = format!("lhm/{node_id}/");
+        let availability_topic = format!("lhm/{node_id}/status");
+
+        let mut options = MqttOptions::new(format!("lhm-subscriber-{node_id}"), host, port);
+        options.set_keep_alive(Duration::from_secs(5));
+        let (subscriber, mut event_loop) = AsyncClient::new(options, 16);
+
+        subscriber
+            .subscribe(format!("{discovery_prefix}#"), QoS::AtLeastOnce)
+            .await
+            .unwrap();
+        subscriber
+            .subscribe(format!("{state_prefix}#"), QoS::AtLeastOnce)
+            .await
+            .unwrap();
+
+        // Wait for both subscriptions before publishing
+        let mut acknowledged = 0;
+        while acknowledged < 2 {
+            if let Event::Incoming(Packet::SubAck(_)) = event_loop.poll().await.unwrap() {
+                acknowledged += 1;
+            }
+        }
+
+        let mut config = MqttConfig::new(&node_id, host, port);
+        config.interval = Duration::from_millis(200);
+        let publisher = spawn(MqttPublisher::new(handle, config).run());
+
+        // Every hardware sensor along with the virtual sensor
+        const SENSOR_COUNT: usize = 6;
+
+        let mut discovery: HashMap<String, Value> = HashMap::new();
+        let mut states: HashMap<String, (bool, String)> = HashMap::new();
+        let mut availability = None;
+
+        timeout(Duration::from_secs(10), async {
+            while discovery.len() < SENSOR_COUNT
+                || states.len() < SENSOR_COUNT
+                || availability.is_none()
+            {
+                let Event::Incoming(Packet::Publish(publish)) = event_loop.poll().await.unwrap()
+                else {
+                    continue;
+                };
+
+                let payload = String::from_utf8(publish.payload.to_vec()).unwrap();
+                if publish.topic.starts_with(&discovery_prefix) {
+                    discovery.insert(publish.topic, serde_json::from_str(&payload).unwrap());
+                } else if publish.topic == availability_topic {
+                    availability = Some(payload);
+                } else {
+                    states.insert(publish.topic, (publish.retain, payload));
+                }
+            }
+        })
+        .await
+        .expect("publishes were not received");
+
+        publisher.abort();
+
+        assert_eq!(availability.as_deref(), Some("online"));
+
+        let (retain, state) = &states[&format!("{state_prefix}intelcpu/0/temperature/0")];
+        assert!(!retain);
+        assert_eq!(state, "55");
+
+        let (_, state) = &states[&format!("{state_prefix}virtual/cpu-max")];
+        assert_eq!(state, "55");
+
+        let payload = &discovery[&format!("{discovery_prefix}intelcpu_0_temperature_0/config")];
+        assert_eq!(
+            payload["unique_id"],
+            format!("lhm_{node_id}_intelcpu_0_temperature_0")
+        );
+        assert_eq!(
+            payload["state_topic"],
+            format!("{state_prefix}intelcpu/0/temperature/0")
+        );
+        assert_eq!(payload["availability_topic"], availability_topic);
+        assert_eq!(payload["device"]["name"], "Intel Core i7");
+
+        let payload = &discovery[&format!("{discovery_prefix}virtual_cpu-max/config")];
+        assert_eq!(payload["device"]["model"], "Virtual");
+
+        // Clear the retained messages left on the broker
+        for topic in discovery.keys().chain([&availability_topic]) {
+            subscriber
+                .publish(topic, QoS::AtLeastOnce, true, Vec::new())
+                .await
+                .unwrap();
+        }
+        subscriber.disconnect().await.unwrap();
+        while event_loop.poll().await.is_ok() {}
+    }
+}
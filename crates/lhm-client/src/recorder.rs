@@ -0,0 +1,158 @@
+//! Recording of a live session into a [Fixture] that can be replayed
+//! through the simulated server backend
+
+use crate::{Hardware, LHMClientError, LHMClientHandle, Sensor};
+use lhm_shared::fixture::{Curve, Fixture, FixtureHardware, FixtureSensor};
+use std::{collections::HashMap, path::Path, time::Instant};
+
+/// Records the hardware tree and timestamped sensor values from a client
+///
+/// The recording is taken from the hardware currently loaded by the server,
+/// [LHMClientHandle::set_options] and [LHMClientHandle::update_all] should be
+/// called before starting a recording
+pub struct Recorder {
+    /// Handle to the client being recorded
+    handle: LHMClientHandle,
+    /// All the hardware being recorded
+    hardware: Vec<Hardware>,
+    /// Identifiers of the top level hardware
+    top_level: Vec<String>,
+    /// Children of each hardware item, keyed by parent identifier
+    children: HashMap<String, Vec<String>>,
+    /// Sensors of each hardware item, keyed by parent identifier
+    sensors: HashMap<String, Vec<Sensor>>,
+    /// Recorded samples for each sensor, keyed by sensor identifier
+    samples: HashMap<String, Vec<(u64, f32)>>,
+    /// Time the recording started, samples are relative to this
+    start: Instant,
+}
+
+impl Recorder {
+    /// Capture the hardware tree and start a new recording
+    pub async fn start(handle: LHMClientHandle) -> Result<Recorder, LHMClientError> {
+        let hardware = handle.query_hardware(None, None).await?;
+        let top_level = handle
+            .query_hardware(Some(None), None)
+            .await?
+            .into_iter()
+            .map(|hardware| hardware.identifier)
+            .collect();
+
+        let mut children = HashMap::new();
+        let mut sensors = HashMap::new();
+
+        for item in &hardware {
+            let child_ids = handle
+                .query_hardware(Some(Some(item.identifier.clone())), None)
+                .await?
+                .into_iter()
+                .map(|child| child.identifier)
+                .collect();
+            children.insert(item.identifier.clone(), child_ids);
+
+            let item_sensors = handle
+                .query_sensors(Some(item.identifier.clone()), None)
+                .await?;
+            sensors.insert(item.identifier.clone(), item_sensors);
+        }
+
+        Ok(Recorder {
+            handle,
+            hardware,
+            top_level,
+            children,
+            sensors,
+            samples: HashMap::new(),
+            start: Instant::now(),
+        })
+    }
+
+    /// Updates all the recorded hardware and records the current
+    /// value of every sensor
+    pub async fn sample(&mut self) -> Result<(), LHMClientError> {
+        for hardware in &self.hardware {
+            self.handle.update_hardware_by_idx(hardware.index).await?;
+        }
+
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        let sensors = self.handle.query_sensors(None, None).await?;
+
+        for sensor in sensors {
+            self.samples
+                .entry(sensor.identifier)
+                .or_default()
+                .push((elapsed, sensor.value));
+        }
+
+        Ok(())
+    }
+
+    /// Finish the recording, creating a fixture from the recorded
+    /// hardware tree and samples
+    pub fn finish(mut self) -> Fixture {
+        let hardware = std::mem::take(&mut self.top_level)
+            .iter()
+            .filter_map(|identifier| self.build_hardware(identifier))
+            .collect();
+
+        Fixture {
+            seed: None,
+            hardware,
+        }
+    }
+
+    /// Finish the recording and save it as a JSON fixture at `path`
+    pub fn save(self, path: &Path) -> std::io::Result<()> {
+        let fixture = self.finish();
+        let contents = serde_json::to_vec_pretty(&fixture).map_err(std::io::Error::other)?;
+        std::fs::write(path, contents)
+    }
+
+    fn build_hardware(&mut self, identifier: &str) -> Option<FixtureHardware> {
+        let index = self
+            .hardware
+            .iter()
+            .position(|hardware| hardware.identifier == identifier)?;
+        let hardware = self.hardware[index].clone();
+
+        let children = self
+            .children
+            .remove(identifier)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|child| self.build_hardware(child))
+            .collect();
+
+        let sensors = self
+            .sensors
+            .remove(identifier)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|sensor| {
+                // Sensors that were never sampled keep their initial value
+                let samples = self
+                    .samples
+                    .remove(&sensor.identifier)
+                    .unwrap_or_else(|| vec![(0, sensor.value)]);
+
+                FixtureSensor {
+                    identifier: sensor.identifier,
+                    name: sensor.name,
+                    ty: sensor.ty,
+                    curve: Curve::Samples {
+                        samples,
+                        repeat: true,
+                    },
+                }
+            })
+            .collect();
+
+        Some(FixtureHardware {
+            identifier: hardware.identifier,
+            name: hardware.name,
+            ty: hardware.ty,
+            children,
+            sensors,
+        })
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{
+    LHMClientError, LHMClientHandle, PipeResponse, SensorValueUpdate, ServerEvent, codec::LHMFrame,
+};
+use futures_util::Stream;
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+use tokio::sync::mpsc;
+
+/// Frames pushed by the server for a subscription
+///
+/// The subscription is stopped when this is dropped
+struct Subscription {
+    /// ID of the subscription
+    id: u32,
+    /// Channel receiving frames pushed for the subscription
+    rx: mpsc::UnboundedReceiver<LHMFrame>,
+    /// Handle used to unsubscribe
+    handle: LHMClientHandle,
+}
+
+impl Subscription {
+    /// Polls for the next response pushed for the subscription, error
+    /// responses are provided as errors
+    fn poll_response(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<PipeResponse, LHMClientError>>> {
+        let frame = match ready!(self.rx.poll_recv(cx)) {
+            Some(value) => value,
+            // Client has disconnected
+            None => return Poll::Ready(None),
+        };
+
+        let msg: PipeResponse = match rmp_serde::from_slice(&frame.body) {
+            Ok(value) => value,
+            Err(err) => return Poll::Ready(Some(Err(LHMClientError::Decode(err)))),
+        };
+
+        let result = match msg {
+            PipeResponse::Error { code, message } => {
+                Err(LHMClientError::from_server(code, message))
+            }
+            msg => Ok(msg),
+        };
+
+        Poll::Ready(Some(result))
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.handle.unsubscribe(self.id);
+    }
+}
+
+/// Stream of sensor values pushed by the server, created
+/// using [LHMClientHandle::subscribe]
+///
+/// The subscription is stopped when this is dropped
+pub struct SensorSubscription {
+    inner: Subscription,
+}
+
+impl SensorSubscription {
+    pub(crate) fn new(
+        id: u32,
+        rx: mpsc::UnboundedReceiver<LHMFrame>,
+        handle: LHMClientHandle,
+    ) -> Self {
+        Self {
+            inner: Subscription { id, rx, handle },
+        }
+    }
+
+    /// ID of the subscription
+    pub fn id(&self) -> u32 {
+        self.inner.id
+    }
+}
+
+impl Stream for SensorSubscription {
+    type Item = Result<Vec<SensorValueUpdate>, LHMClientError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let result = ready!(this.inner.poll_response(cx)).map(|result| match result? {
+            PipeResponse::SubscriptionValues { values } => Ok(values),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        });
+
+        Poll::Ready(result)
+    }
+}
+
+/// Stream of events pushed by the server, created
+/// using [LHMClientHandle::subscribe_events]
+///
+/// The subscription is stopped when this is dropped
+pub struct EventSubscription {
+    inner: Subscription,
+}
+
+impl EventSubscription {
+    pub(crate) fn new(
+        id: u32,
+        rx: mpsc::UnboundedReceiver<LHMFrame>,
+        handle: LHMClientHandle,
+    ) -> Self {
+        Self {
+            inner: Subscription { id, rx, handle },
+        }
+    }
+
+    /// ID of the subscription
+    pub fn id(&self) -> u32 {
+        self.inner.id
+    }
+}
+
+impl Stream for EventSubscription {
+    type Item = Result<ServerEvent, LHMClientError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let result = ready!(this.inner.poll_response(cx)).map(|result| match result? {
+            PipeResponse::Event { event } => Ok(event),
+            _ => Err(LHMClientError::UnexpectedMessage),
+        });
+
+        Poll::Ready(result)
+    }
+}
@@ -0,0 +1,49 @@
+use lhm_shared::{
+    Transport,
+    codec::LHMFrameCodec,
+    pipe::{PipeFuture, PipeRx, PipeTx, TransportStream},
+};
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+#[cfg(windows)]
+use interprocess::os::windows::named_pipe::{pipe_mode, tokio::DuplexPipeStream};
+
+/// Connect to the server using the provided transport, the server
+/// closing the connection is reported as an error
+pub async fn connect(
+    transport: &Transport,
+    max_frame_length: usize,
+) -> std::io::Result<(PipeFuture<Box<dyn TransportStream>>, PipeRx, PipeTx)> {
+    let stream = connect_transport(transport).await?;
+    let framed = Framed::new(stream, LHMFrameCodec::new(max_frame_length));
+    let (future, rx, tx) = PipeFuture::new(framed);
+    Ok((future.with_eof_error(), rx, tx))
+}
+
+/// Connect to the server using the provided transport
+async fn connect_transport(transport: &Transport) -> std::io::Result<Box<dyn TransportStream>> {
+    match transport {
+        #[cfg(windows)]
+        Transport::NamedPipe(path) => {
+            let stream =
+                DuplexPipeStream::<pipe_mode::Bytes>::connect_by_path(path.as_str()).await?;
+            Ok(Box::new(stream))
+        }
+
+        #[cfg(unix)]
+        Transport::UnixSocket(path) => {
+            let stream = UnixStream::connect(path).await?;
+            Ok(Box::new(stream))
+        }
+
+        Transport::Tcp(addr) => {
+            let stream = TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            Ok(Box::new(stream))
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use lhm_server::{RefreshConfig, ServerConfig, backend::simulated, run_server_with};
+use std::{path::PathBuf, time::Duration};
+
+/// Runs the server using simulated hardware loaded from a fixture
+///
+/// ```sh
+/// cargo run -p lhm-server --features simulated --example simulated -- path/to/fixture.toml
+/// ```
+///
+/// Setting the `LHM_REFRESH_MS` environment variable enables the background
+/// refresh using the provided interval. With the `metrics` feature, setting
+/// the `LHM_METRICS_ADDR` environment variable serves the metrics endpoint
+/// on the provided address. With the `gateway` feature, setting the
+/// `LHM_GATEWAY_PORT` environment variable serves the gateway on the
+/// provided port, including the WebSocket endpoint with the `websocket`
+/// feature
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let fixture_path = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples/fixtures/desktop.toml")
+        });
+
+    let backend = simulated::load(&fixture_path)?;
+    let refresh = std::env::var("LHM_REFRESH_MS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .map(|interval_ms| RefreshConfig {
+            interval: Duration::from_millis(interval_ms),
+            ..Default::default()
+        });
+
+    let config = ServerConfig {
+        refresh,
+        #[cfg(feature = "metrics")]
+        metrics: std::env::var("LHM_METRICS_ADDR")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .map(|address| lhm_server::metrics::MetricsConfig {
+                address,
+                ..Default::default()
+            }),
+        #[cfg(feature = "gateway")]
+        gateway: std::env::var("LHM_GATEWAY_PORT")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .map(|port| lhm_server::gateway::GatewayConfig {
+                port,
+                ..Default::default()
+            }),
+        ..Default::default()
+    };
+
+    println!(
+        "Serving {} on {:?}",
+        fixture_path.display(),
+        config.transport
+    );
+
+    run_server_with(config, backend).await
+}
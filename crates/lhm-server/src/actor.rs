@@ -1,69 +1,182 @@
-use crate::cache::HardwareCache;
-use lhm_shared::{Hardware, HardwareType, PipeRequest, PipeResponse, Sensor, SensorType};
-use lhm_sys::Computer;
-use tokio::sync::{mpsc, oneshot};
+use crate::{
+    RefreshConfig, ServerConfig,
+    alert::AlertEngine,
+    backend::{BackendHardware, BackendSensor, HardwareBackend},
+    cache::{CacheChanges, HardwareCache},
+    history::SensorHistory,
+    sample::SensorSample,
+    virtual_sensor::VirtualSensors,
+};
+use lhm_shared::{
+    Capability, ComputerOptions, ErrorCode, Hardware, PROTOCOL_VERSION, PipeRequest, PipeResponse,
+    Sensor, SensorValueUpdate, ServerEvent, ServerInfo,
+    snapshot::{HardwareNode, Snapshot, SnapshotFilter},
+    virtual_sensor::{VirtualFormula, VirtualSensor},
+};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+/// Optional features supported by this server
+const CAPABILITIES: &[Capability] = &[
+    Capability::Subscriptions,
+    Capability::SensorControl,
+    Capability::HardwareTree,
+    Capability::CacheGeneration,
+    Capability::Snapshot,
+    Capability::Events,
+    Capability::Batch,
+    Capability::Alerts,
+    Capability::VirtualSensors,
+];
 
 #[derive(Clone)]
 pub struct ComputerActorHandle {
     pub tx: mpsc::UnboundedSender<ComputerActorMessage>,
+    /// Sender for events emitted by the actor, used to create receivers
+    pub events: broadcast::Sender<ServerEvent>,
 }
 
 pub struct ComputerActorMessage {
-    pub request: PipeRequest,
+    pub request: ActorRequest,
     pub tx: oneshot::Sender<PipeResponse>,
 }
 
-pub struct ComputerActor {
-    computer: Computer,
-    cache: HardwareCache,
+/// Number of events buffered for each event subscriber before
+/// the subscriber misses events
+const EVENT_CAPACITY: usize = 256;
+
+/// Unique ID for a connected client
+pub type ConnectionId = u32;
+
+pub enum ActorRequest {
+    /// Request from a client
+    Pipe {
+        connection_id: ConnectionId,
+        request: PipeRequest,
+    },
+    /// Update and read the values of the sensors for a subscription
+    PollSubscription { sensor_ids: Arc<[String]> },
+    /// Client has disconnected, any options it requested are released
+    Disconnect { connection_id: ConnectionId },
+    /// Refresh any hardware that is due for a background refresh
+    Refresh,
 }
 
-impl ComputerActor {
-    /// Create a new actor thread
-    pub fn create() -> std::io::Result<ComputerActorHandle> {
-        let computer = Computer::create()?;
+pub struct ComputerActor<B: HardwareBackend> {
+    backend: B,
+    cache: HardwareCache<B::Hardware>,
+    /// Options requested by each connected client
+    connection_options: HashMap<ConnectionId, ComputerOptions>,
+    /// Options currently applied to the computer
+    options: ComputerOptions,
+    /// Background refresh configuration, when present the hardware is
+    /// only updated by the background refresh
+    refresh: Option<RefreshConfig>,
+    /// History of the sensor values
+    history: SensorHistory,
+    /// Connection that controls each software controlled sensor,
+    /// keyed by sensor identifier
+    controls: HashMap<String, ConnectionId>,
+    /// Sender for events emitted to subscribed clients
+    events: broadcast::Sender<ServerEvent>,
+    /// Last time the hardware was scanned for added or removed hardware
+    /// by the background refresh
+    scanned_at: Option<Instant>,
+    /// Alert rules evaluated against the sensor values
+    alerts: AlertEngine,
+    /// Virtual sensors computed from the sensor values
+    virtual_sensors: VirtualSensors,
+}
 
+impl<B: HardwareBackend> ComputerActor<B> {
+    /// Create a new actor thread using the provided backend
+    pub fn create(backend: B, config: &ServerConfig) -> ComputerActorHandle {
         let (tx, rx) = mpsc::unbounded_channel();
-        let actor = ComputerActor {
-            computer,
-            cache: Default::default(),
-        };
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let actor = ComputerActor::new(backend, config, events.clone());
 
         std::thread::spawn(move || {
             let mut rx = rx;
             let mut actor = actor;
 
             while let Some(ComputerActorMessage { request, tx }) = rx.blocking_recv() {
-                let response = actor.handle_request(request);
+                let response = match request {
+                    ActorRequest::Pipe {
+                        connection_id,
+                        request,
+                    } => actor.handle_request(connection_id, request),
+                    ActorRequest::PollSubscription { sensor_ids } => {
+                        actor.poll_subscription(&sensor_ids)
+                    }
+                    ActorRequest::Disconnect { connection_id } => actor.disconnect(connection_id),
+                    ActorRequest::Refresh => actor.refresh(),
+                };
                 _ = tx.send(response);
             }
         });
 
-        Ok(ComputerActorHandle { tx })
+        ComputerActorHandle { tx, events }
+    }
+
+    /// Create the actor state without starting the actor thread
+    fn new(backend: B, config: &ServerConfig, events: broadcast::Sender<ServerEvent>) -> Self {
+        ComputerActor {
+            backend,
+            cache: Default::default(),
+            connection_options: Default::default(),
+            options: Default::default(),
+            refresh: config.refresh.clone(),
+            history: SensorHistory::new(config.history_length),
+            controls: Default::default(),
+            events,
+            scanned_at: None,
+            alerts: Default::default(),
+            virtual_sensors: Default::default(),
+        }
     }
 
-    fn handle_request(&mut self, request: PipeRequest) -> PipeResponse {
+    fn handle_request(
+        &mut self,
+        connection_id: ConnectionId,
+        request: PipeRequest,
+    ) -> PipeResponse {
         match request {
-            PipeRequest::UpdateAll => {
-                self.computer.update();
+            PipeRequest::Hello { .. } => {
+                let mut capabilities = CAPABILITIES.to_vec();
+                if self.refresh.is_some() {
+                    capabilities.push(Capability::BackgroundRefresh);
+                }
+                if self.history.is_enabled() {
+                    capabilities.push(Capability::SensorHistory);
+                }
 
-                // Load the hardware and populate the cache
-                let hardware = self.computer.hardware();
-                self.cache.init(hardware);
+                return PipeResponse::Hello {
+                    server: ServerInfo {
+                        protocol_version: PROTOCOL_VERSION,
+                        server_version: env!("CARGO_PKG_VERSION").to_string(),
+                        capabilities,
+                    },
+                };
+            }
+
+            // Updates are handled by the background refresh
+            PipeRequest::UpdateHardwareById { .. }
+            | PipeRequest::UpdateHardwareByIndex { .. }
+            | PipeRequest::UpdateSensorById { .. }
+            | PipeRequest::UpdateSensorByIndex { .. }
+                if self.refresh.is_some() => {}
+
+            PipeRequest::UpdateAll => {
+                self.update_all();
             }
 
             PipeRequest::SetOptions { options } => {
-                self.computer.set_options(lhm_sys::ComputerOptions {
-                    battery_enabled: options.battery_enabled,
-                    controller_enabled: options.controller_enabled,
-                    cpu_enabled: options.cpu_enabled,
-                    gpu_enabled: options.gpu_enabled,
-                    memory_enabled: options.memory_enabled,
-                    motherboard_enabled: options.motherboard_enabled,
-                    network_enabled: options.network_enabled,
-                    psu_enabled: options.psu_enabled,
-                    storage_enabled: options.storage_enabled,
-                });
+                self.connection_options.insert(connection_id, options);
+                self.apply_options();
             }
 
             PipeRequest::QueryHardware { parent_id, ty } => {
@@ -74,9 +187,10 @@ impl ComputerActor {
                         let index = match self.cache.get_hardware_by_id(&parent_id) {
                             Some((index, _)) => index,
                             None => {
-                                return PipeResponse::Error {
-                                    error: "parent not found".to_string(),
-                                };
+                                return PipeResponse::error(
+                                    ErrorCode::NotFound,
+                                    "parent not found",
+                                );
                             }
                         };
 
@@ -93,7 +207,7 @@ impl ComputerActor {
                 let hardware = self
                     .cache
                     .query_hardware_iter(parent_index, ty)
-                    .map(map_hardware)
+                    .map(|item| map_hardware(&self.cache, item))
                     .collect();
 
                 return PipeResponse::Hardwares { hardware };
@@ -106,101 +220,1275 @@ impl ComputerActor {
                     Some(Some((index, _))) => Some(index),
                     // Parent hardware did not exist
                     Some(None) => {
-                        return PipeResponse::Error {
-                            error: "parent not found".to_string(),
-                        };
+                        return PipeResponse::error(ErrorCode::NotFound, "parent not found");
                     }
                     // Not requesting a parent index
                     None => None,
                 };
 
-                let sensors = self
+                let mut sensors: Vec<Sensor> = self
                     .cache
                     .query_sensors(parent_index, ty)
                     .map(map_sensor)
                     .collect();
 
+                // Virtual sensors do not belong to any hardware
+                if parent_index.is_none() {
+                    sensors.extend(
+                        self.virtual_sensors
+                            .iter()
+                            .filter(|(_, entry)| ty.is_none_or(|ty| entry.sensor.ty == ty))
+                            .map(|(index, entry)| entry.to_sensor(index)),
+                    );
+                }
+
                 return PipeResponse::Sensors { sensors };
             }
 
             PipeRequest::GetHardwareById { id } => {
-                let hardware = self.cache.get_hardware_by_id(&id).map(map_hardware);
+                let hardware = self
+                    .cache
+                    .get_hardware_by_id(&id)
+                    .map(|item| map_hardware(&self.cache, item));
                 return PipeResponse::Hardware { hardware };
             }
 
             PipeRequest::GetSensorById { id } => {
-                let sensor = self.cache.get_sensor_by_id(&id).map(map_sensor);
+                let sensor = match self.cache.get_sensor_by_id(&id) {
+                    Some(sensor) => Some(map_sensor(sensor)),
+                    None => self
+                        .virtual_sensors
+                        .get_index(&id)
+                        .and_then(|index| self.get_sensor(index)),
+                };
                 return PipeResponse::Sensor { sensor };
             }
 
             PipeRequest::UpdateHardwareById { id } => {
-                if let Some(hardware) = self.cache.get_hardware_by_id_mut(&id) {
-                    hardware.update();
+                if let Some((index, _)) = self.cache.get_hardware_by_id(&id) {
+                    self.update_hardware(index);
+                    self.values_updated();
                 }
             }
 
             PipeRequest::UpdateHardwareByIndex { idx } => {
-                if let Some(hardware) = self.cache.get_hardware_by_idx_mut(idx) {
-                    hardware.update();
-                }
+                self.update_hardware(idx);
+                self.values_updated();
             }
 
             PipeRequest::UpdateSensorById { id } => {
-                if let Some(sensor) = self.cache.get_sensor_by_id_mut(&id) {
-                    sensor.update();
+                if let Some(index) = self.get_sensor_index(&id) {
+                    self.update_sensor(index);
                 }
             }
 
             PipeRequest::UpdateSensorByIndex { idx } => {
-                if let Some(sensor) = self.cache.get_sensor_by_idx_mut(idx) {
-                    sensor.update();
-                }
+                self.update_sensor(idx);
             }
 
             PipeRequest::GetSensorValueById { id, update } => {
-                let value = self.cache.get_sensor_by_id_mut(&id).map(|sensor| {
-                    if update {
-                        sensor.update();
-                    }
+                let index = self.get_sensor_index(&id);
+                return self.get_sensor_value(index, update);
+            }
+
+            PipeRequest::GetSensorValueByIndex { idx, update } => {
+                return self.get_sensor_value(Some(idx), update);
+            }
 
-                    sensor.value()
-                });
+            PipeRequest::ResetSensorMinMax { id } => {
+                let Some(index) = self.get_sensor_index(&id) else {
+                    return PipeResponse::error(ErrorCode::NotFound, "sensor not found");
+                };
 
-                return PipeResponse::SensorValue { value };
+                self.reset_min_max(&[index]);
             }
 
-            PipeRequest::GetSensorValueByIndex { idx, update } => {
-                let value = self.cache.get_sensor_by_idx_mut(idx).map(|sensor| {
-                    if update {
-                        sensor.update();
+            PipeRequest::ResetHardwareMinMax { id } => {
+                let Some((parent_index, _)) = self.cache.get_hardware_by_id(&id) else {
+                    return PipeResponse::error(ErrorCode::NotFound, "hardware not found");
+                };
+
+                let indexes = self.cache.query_sensor_indexes(Some(parent_index));
+                self.reset_min_max(&indexes);
+            }
+
+            PipeRequest::ResetAllMinMax => {
+                let indexes = self.cache.query_sensor_indexes(None);
+                self.reset_min_max(&indexes);
+
+                for entry in self.virtual_sensors.iter_mut() {
+                    entry.reset_min_max();
+                }
+            }
+
+            PipeRequest::SetSensorControl { id, value } => {
+                if !(0.0..=100.0).contains(&value) {
+                    return PipeResponse::error(
+                        ErrorCode::InvalidRequest,
+                        "control value must be between 0 and 100",
+                    );
+                }
+
+                let Some(index) = self.cache.get_sensor_index(&id) else {
+                    return PipeResponse::error(ErrorCode::NotFound, "sensor not found");
+                };
+
+                let controlled = self
+                    .cache
+                    .get_sensor_by_idx_mut(index)
+                    .is_some_and(|sensor| sensor.set_control_software(value));
+
+                if !controlled {
+                    return PipeResponse::error(
+                        ErrorCode::InvalidRequest,
+                        "sensor cannot be controlled",
+                    );
+                }
+
+                // Most recent connection to set the value takes ownership
+                self.controls.insert(id, connection_id);
+            }
+
+            PipeRequest::ResetSensorControl { id } => {
+                let Some(index) = self.cache.get_sensor_index(&id) else {
+                    return PipeResponse::error(ErrorCode::NotFound, "sensor not found");
+                };
+
+                let controlled = self
+                    .cache
+                    .get_sensor_by_idx_mut(index)
+                    .is_some_and(|sensor| sensor.set_control_default());
+
+                if !controlled {
+                    return PipeResponse::error(
+                        ErrorCode::InvalidRequest,
+                        "sensor cannot be controlled",
+                    );
+                }
+
+                self.controls.remove(&id);
+            }
+
+            PipeRequest::GetSensorHistory {
+                id,
+                since,
+                max_points,
+                downsample,
+            } => {
+                let points = self.history.query(&id, since, max_points, downsample);
+                return PipeResponse::SensorHistory { points };
+            }
+
+            PipeRequest::GetCacheGeneration => {
+                return PipeResponse::CacheGeneration {
+                    generation: self.cache.generation(),
+                };
+            }
+
+            PipeRequest::GetHardwareTree { root_id } => {
+                let filter = SnapshotFilter::default();
+                let nodes = match root_id {
+                    Some(root_id) => {
+                        let Some((index, _)) = self.cache.get_hardware_by_id(&root_id) else {
+                            return PipeResponse::error(ErrorCode::NotFound, "hardware not found");
+                        };
+
+                        self.hardware_node(index, &filter).into_iter().collect()
                     }
+                    None => self.hardware_nodes(None, &filter),
+                };
+
+                return PipeResponse::HardwareTree { nodes };
+            }
+
+            PipeRequest::GetSnapshot { update, filter } => {
+                if update {
+                    self.update_all();
+                }
+
+                let snapshot = Snapshot {
+                    timestamp_ms: timestamp_ms(),
+                    generation: self.cache.generation(),
+                    hardware: self.hardware_nodes(None, &filter),
+                };
+
+                return PipeResponse::Snapshot { snapshot };
+            }
+
+            PipeRequest::AddAlertRule { rule } => {
+                if rule.name.is_empty() {
+                    return PipeResponse::error(
+                        ErrorCode::InvalidRequest,
+                        "alert rule name must not be empty",
+                    );
+                }
+
+                if !rule.threshold.is_finite() {
+                    return PipeResponse::error(
+                        ErrorCode::InvalidRequest,
+                        "alert rule threshold must be finite",
+                    );
+                }
+
+                if !(rule.hysteresis.is_finite() && rule.hysteresis >= 0.0) {
+                    return PipeResponse::error(
+                        ErrorCode::InvalidRequest,
+                        "alert rule hysteresis must not be negative",
+                    );
+                }
+
+                let events = self.alerts.add_rule(rule);
+                self.emit_events(events);
+            }
+
+            PipeRequest::RemoveAlertRule { name } => {
+                let Some(events) = self.alerts.remove_rule(&name) else {
+                    return PipeResponse::error(ErrorCode::NotFound, "alert rule not found");
+                };
+
+                self.emit_events(events);
+            }
+
+            PipeRequest::ListAlertRules => {
+                let rules = self.alerts.rules().cloned().collect();
+                return PipeResponse::AlertRules { rules };
+            }
+
+            PipeRequest::AddVirtualSensor { sensor } => {
+                if let Err(message) = validate_virtual_sensor(&sensor) {
+                    return PipeResponse::error(ErrorCode::InvalidRequest, message);
+                }
+
+                let (index, replaced) = self.virtual_sensors.add(sensor);
+                self.cache.bump_generation();
+                self.update_virtual_sensors();
+
+                if !replaced && let Some(sensor) = self.get_sensor(index) {
+                    self.emit_events([ServerEvent::SensorAdded { sensor }]);
+                }
+            }
+
+            PipeRequest::RemoveVirtualSensor { id } => {
+                let Some((index, identifier)) = self.virtual_sensors.remove(&id) else {
+                    return PipeResponse::error(ErrorCode::NotFound, "virtual sensor not found");
+                };
+
+                self.cache.bump_generation();
+                self.history.remove(&identifier);
+                self.emit_events([ServerEvent::SensorRemoved { index, identifier }]);
+            }
+
+            PipeRequest::ListVirtualSensors => {
+                let sensors = self
+                    .virtual_sensors
+                    .iter()
+                    .map(|(_, entry)| entry.sensor.clone())
+                    .collect();
+                return PipeResponse::VirtualSensors { sensors };
+            }
+
+            PipeRequest::Batch { requests } => {
+                let responses = requests
+                    .into_iter()
+                    .map(|request| match request {
+                        // Nested batches are rejected by the connection
+                        PipeRequest::Batch { .. } => PipeResponse::error(
+                            ErrorCode::InvalidRequest,
+                            "request cannot be made within a batch",
+                        ),
+                        request => self.handle_request(connection_id, request),
+                    })
+                    .collect();
+
+                return PipeResponse::Batch { responses };
+            }
+
+            // Subscriptions are managed by the connection itself
+            PipeRequest::Subscribe { .. }
+            | PipeRequest::Unsubscribe { .. }
+            | PipeRequest::SubscribeEvents => {
+                return PipeResponse::error(
+                    ErrorCode::Internal,
+                    "subscriptions are not handled by the actor",
+                );
+            }
+        }
+
+        PipeResponse::Success
+    }
+
+    /// Updates all the hardware and reloads the cache
+    fn update_all(&mut self) {
+        // Background refresh keeps the cache populated and updated
+        if self.refresh.is_some() {
+            if self.cache.is_empty() {
+                self.reload_cache();
+            }
+
+            return;
+        }
+
+        self.backend.update();
+        self.reload_cache();
+        self.cache.mark_all_updated();
+        self.record_history(None);
+        self.values_updated();
+    }
+
+    /// Builds the tree of hardware nodes that are children of the provided
+    /// parent, or the top level hardware when no parent is provided
+    fn hardware_nodes(
+        &self,
+        parent_index: Option<usize>,
+        filter: &SnapshotFilter,
+    ) -> Vec<HardwareNode> {
+        self.cache
+            .query_hardware_iter(Some(parent_index), None)
+            .filter_map(|(index, _)| self.hardware_node(index, filter))
+            .collect()
+    }
+
+    /// Builds the tree of hardware nodes starting at the provided hardware,
+    /// [None] if neither the hardware or its children match the filter
+    fn hardware_node(&self, index: usize, filter: &SnapshotFilter) -> Option<HardwareNode> {
+        let hardware = self.cache.get_hardware_by_idx(index)?;
+        let children = self.hardware_nodes(Some(index), filter);
+        let included = filter.includes_hardware(hardware.hardware_type());
+
+        // Keep hardware that does not match when its children do
+        if !included && children.is_empty() {
+            return None;
+        }
+
+        let sensors = if included {
+            self.cache
+                .query_sensors(Some(index), None)
+                .filter(|(_, sensor)| filter.includes_sensor(sensor.sensor_type()))
+                .map(map_sensor)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Some(HardwareNode {
+            hardware: map_hardware(&self.cache, (index, hardware)),
+            sensors,
+            children,
+        })
+    }
+
+    /// Reads the value of a sensor along with its age, updating the sensor
+    /// first if requested and the background refresh is not enabled
+    fn get_sensor_value(&mut self, index: Option<usize>, update: bool) -> PipeResponse {
+        let Some(index) = index else {
+            return PipeResponse::SensorValue {
+                value: None,
+                age_ms: None,
+                min: None,
+                max: None,
+            };
+        };
+
+        if update && self.refresh.is_none() {
+            self.update_sensor(index);
+        }
+
+        let sensor = self.get_sensor(index);
+
+        PipeResponse::SensorValue {
+            value: sensor.as_ref().map(|sensor| sensor.value),
+            age_ms: self.get_sensor_age_ms(index),
+            min: sensor.as_ref().map(|sensor| sensor.min),
+            max: sensor.as_ref().map(|sensor| sensor.max),
+        }
+    }
+
+    /// Index of a sensor by identifier, including virtual sensors
+    fn get_sensor_index(&self, id: &str) -> Option<usize> {
+        self.cache
+            .get_sensor_index(id)
+            .or_else(|| self.virtual_sensors.get_index(id))
+    }
+
+    /// Sensor at the provided index, including virtual sensors
+    fn get_sensor(&self, index: usize) -> Option<Sensor> {
+        match self.cache.get_sensor_by_idx(index) {
+            Some(sensor) => Some(map_sensor((index, sensor))),
+            None => self
+                .virtual_sensors
+                .get_by_idx(index)
+                .map(|entry| entry.to_sensor(index)),
+        }
+    }
+
+    /// Resets the minimum and maximum values of the provided sensors
+    fn reset_min_max(&mut self, indexes: &[usize]) {
+        for index in indexes {
+            if let Some(sensor) = self.cache.get_sensor_by_idx_mut(*index) {
+                sensor.reset_min_max();
+            } else if let Some(entry) = self.virtual_sensors.get_by_idx_mut(*index) {
+                entry.reset_min_max();
+            }
+        }
+    }
+
+    /// Updates a hardware item recording the new values of its sensors
+    fn update_hardware(&mut self, index: usize) {
+        self.cache.update_hardware_by_idx(index);
+        self.record_history(Some(index));
+    }
+
+    /// Updates a sensor recording its new value
+    fn update_sensor(&mut self, index: usize) {
+        // Virtual sensors are updated by updating the hardware of their inputs
+        if self.virtual_sensors.get_by_idx(index).is_some() {
+            for parent_index in self.virtual_input_parents(index) {
+                self.update_hardware(parent_index);
+            }
+
+            self.values_updated();
+            return;
+        }
+
+        self.cache.update_sensor_by_idx(index);
+
+        if let Some(sensor) = self.cache.get_sensor_by_idx(index) {
+            self.history
+                .record(&sensor.identifier(), timestamp_ms(), sensor.value());
+        }
+
+        self.values_updated();
+    }
+
+    /// Computes the virtual sensors and evaluates the alert rules after
+    /// the values of any sensors have been updated
+    fn values_updated(&mut self) {
+        self.update_virtual_sensors();
+        self.evaluate_alerts();
+    }
+
+    /// Indexes of the hardware the inputs of a virtual sensor belong to
+    fn virtual_input_parents(&self, index: usize) -> Vec<usize> {
+        let Some(entry) = self.virtual_sensors.get_by_idx(index) else {
+            return Vec::new();
+        };
+
+        let mut parents = Vec::new();
+        for sample in self.sensor_samples() {
+            if entry.is_input(&sample)
+                && let Some(parent_index) = self.cache.get_sensor_parent_index(&sample.identifier)
+                && !parents.contains(&parent_index)
+            {
+                parents.push(parent_index);
+            }
+        }
+
+        parents
+    }
+
+    /// Computes the values of the virtual sensors from the current
+    /// sensor values, recording the new values
+    fn update_virtual_sensors(&mut self) {
+        if self.virtual_sensors.is_empty() {
+            return;
+        }
+
+        let samples = self.sensor_samples();
+        self.virtual_sensors.evaluate(Instant::now(), &samples);
+
+        if !self.history.is_enabled() {
+            return;
+        }
+
+        let timestamp_ms = timestamp_ms();
+        for (_, entry) in self.virtual_sensors.iter() {
+            self.history
+                .record(&entry.sensor.identifier(), timestamp_ms, entry.value);
+        }
+    }
+
+    /// Records the current values of the sensors belonging to a hardware
+    /// item, or all sensors when no hardware is specified
+    fn record_history(&mut self, parent_index: Option<usize>) {
+        if !self.history.is_enabled() {
+            return;
+        }
+
+        let timestamp_ms = timestamp_ms();
+        for (_, sensor) in self.cache.query_sensors(parent_index, None) {
+            self.history
+                .record(&sensor.identifier(), timestamp_ms, sensor.value());
+        }
+    }
+
+    /// Milliseconds since a sensor was last updated
+    fn get_sensor_age_ms(&self, index: usize) -> Option<u64> {
+        self.cache
+            .get_sensor_updated_at(index)
+            .or_else(|| {
+                self.virtual_sensors
+                    .get_by_idx(index)
+                    .and_then(|entry| entry.updated_at)
+            })
+            .map(|updated_at| updated_at.elapsed().as_millis() as u64)
+    }
+
+    /// Updates any hardware that has gone longer than its refresh interval
+    /// without being updated
+    fn refresh(&mut self) -> PipeResponse {
+        // Periodically scan for hardware that has been added or removed
+        if self.is_scan_due() {
+            self.reload_cache();
+            self.scanned_at = Some(Instant::now());
+        }
+
+        let Some(refresh) = &self.refresh else {
+            return PipeResponse::Success;
+        };
+
+        // Allow for some slack so hardware sharing the tick interval is not
+        // skipped when the last refresh finished slightly after the tick
+        let slack = refresh.tick_interval() / 2;
+        let now = Instant::now();
+
+        let due: Vec<usize> = self
+            .cache
+            .hardware_updates_iter()
+            .filter(|(_, ty, updated_at)| {
+                updated_at
+                    .is_none_or(|updated_at| now + slack >= updated_at + refresh.interval_for(*ty))
+            })
+            .map(|(index, _, _)| index)
+            .collect();
 
-                    sensor.value()
-                });
+        for index in due {
+            self.update_hardware(index);
+        }
+
+        self.values_updated();
 
-                return PipeResponse::SensorValue { value };
+        PipeResponse::Success
+    }
+
+    /// Check if the background refresh should scan for added or removed
+    /// hardware, always true when the cache is empty
+    fn is_scan_due(&self) -> bool {
+        let Some(refresh) = &self.refresh else {
+            return false;
+        };
+
+        self.cache.is_empty()
+            || self
+                .scanned_at
+                .is_none_or(|scanned_at| scanned_at.elapsed() >= refresh.scan_interval)
+    }
+
+    fn disconnect(&mut self, connection_id: ConnectionId) -> PipeResponse {
+        // Restore the default control for any sensors the client was
+        // controlling, done first as releasing the options may close them
+        let mut released = Vec::new();
+        self.controls.retain(|id, owner| {
+            if *owner == connection_id {
+                released.push(id.clone());
+                return false;
+            }
+
+            true
+        });
+
+        for id in released {
+            if let Some(index) = self.cache.get_sensor_index(&id)
+                && let Some(sensor) = self.cache.get_sensor_by_idx_mut(index)
+            {
+                sensor.set_control_default();
             }
         }
 
+        if self.connection_options.remove(&connection_id).is_some() {
+            self.apply_options();
+        }
+
         PipeResponse::Success
     }
+
+    /// Applies the union of the options requested by all connected clients
+    /// to the computer
+    fn apply_options(&mut self) {
+        let options = self
+            .connection_options
+            .values()
+            .fold(ComputerOptions::default(), |options, other| {
+                options.union(other)
+            });
+
+        if options == self.options {
+            return;
+        }
+
+        // Check if any previously enabled hardware groups are being disabled
+        let disabled_groups = self.options.union(&options) != options;
+
+        self.backend.set_options(&options);
+        self.options = options;
+
+        // Hardware from disabled groups is closed by the backend, reload
+        // the cache so it no longer holds the closed hardware. Clients do
+        // not reload the cache with the background refresh so it must also
+        // be reloaded to include hardware from newly enabled groups
+        if disabled_groups || self.refresh.is_some() {
+            self.reload_cache();
+        }
+    }
+
+    /// Loads the hardware from the backend into the cache, emitting
+    /// events for any hardware or sensors that were added or removed
+    fn reload_cache(&mut self) {
+        let hardware = self.backend.hardware();
+        let changes = self.cache.reconcile(hardware);
+
+        for (_, identifier) in &changes.removed_sensors {
+            self.history.remove(identifier);
+        }
+
+        self.emit_changes(changes);
+    }
+
+    fn emit_changes(&self, changes: CacheChanges) {
+        // No clients are subscribed to the events
+        if self.events.receiver_count() == 0 {
+            return;
+        }
+
+        let removed_sensors = changes
+            .removed_sensors
+            .into_iter()
+            .map(|(index, identifier)| ServerEvent::SensorRemoved { index, identifier });
+
+        let removed_hardware = changes
+            .removed_hardware
+            .into_iter()
+            .map(|(index, identifier)| ServerEvent::HardwareRemoved { index, identifier });
+
+        let added_hardware = changes.added_hardware.into_iter().filter_map(|index| {
+            let hardware = self.cache.get_hardware_by_idx(index)?;
+            Some(ServerEvent::HardwareAdded {
+                hardware: map_hardware(&self.cache, (index, hardware)),
+            })
+        });
+
+        let added_sensors = changes.added_sensors.into_iter().filter_map(|index| {
+            let sensor = self.cache.get_sensor_by_idx(index)?;
+            Some(ServerEvent::SensorAdded {
+                sensor: map_sensor((index, sensor)),
+            })
+        });
+
+        // Sensors are removed before their hardware and added after it
+        self.emit_events(
+            removed_sensors
+                .chain(removed_hardware)
+                .chain(added_hardware)
+                .chain(added_sensors),
+        );
+    }
+
+    /// Sends events to the subscribed clients
+    fn emit_events(&self, events: impl IntoIterator<Item = ServerEvent>) {
+        for event in events {
+            // Sending only fails when no clients are subscribed
+            _ = self.events.send(event);
+        }
+    }
+
+    /// Evaluates the alert rules against the current sensor values,
+    /// emitting events for any alerts that fired or cleared
+    fn evaluate_alerts(&mut self) {
+        if self.alerts.is_empty() {
+            return;
+        }
+
+        let samples = self.sensor_samples();
+        let events = self.alerts.evaluate(Instant::now(), &samples);
+        self.emit_events(events);
+    }
+
+    /// Current values of the sensors in the cache along with the
+    /// hardware they belong to
+    fn sensor_samples(&self) -> Vec<SensorSample> {
+        self.cache
+            .query_hardware_iter(None, None)
+            .flat_map(|(index, hardware)| {
+                let hardware_identifier = hardware.identifier();
+                let hardware_ty = hardware.hardware_type();
+
+                self.cache
+                    .query_sensors(Some(index), None)
+                    .map(move |(_, sensor)| SensorSample {
+                        identifier: sensor.identifier(),
+                        ty: sensor.sensor_type(),
+                        hardware_identifier: hardware_identifier.clone(),
+                        hardware_ty,
+                        value: sensor.value(),
+                    })
+            })
+            .collect()
+    }
+
+    fn poll_subscription(&mut self, sensor_ids: &[String]) -> PipeResponse {
+        // Update the hardware for each sensor, only updating each hardware once
+        if self.refresh.is_none() {
+            let mut updated = Vec::new();
+            for id in sensor_ids {
+                let parents = match self.virtual_sensors.get_index(id) {
+                    Some(index) => self.virtual_input_parents(index),
+                    None => self.cache.get_sensor_parent_index(id).into_iter().collect(),
+                };
+
+                for parent_index in parents {
+                    if !updated.contains(&parent_index) {
+                        self.update_hardware(parent_index);
+                        updated.push(parent_index);
+                    }
+                }
+            }
+
+            if !updated.is_empty() {
+                self.values_updated();
+            }
+        }
+
+        let values = sensor_ids
+            .iter()
+            .map(|id| {
+                let index = self.get_sensor_index(id);
+
+                SensorValueUpdate {
+                    identifier: id.clone(),
+                    value: index
+                        .and_then(|index| self.get_sensor(index))
+                        .map(|sensor| sensor.value),
+                    age_ms: index.and_then(|index| self.get_sensor_age_ms(index)),
+                }
+            })
+            .collect();
+
+        PipeResponse::SubscriptionValues { values }
+    }
+}
+
+/// Checks a virtual sensor is valid before it is added
+fn validate_virtual_sensor(sensor: &VirtualSensor) -> Result<(), &'static str> {
+    let valid_id = !sensor.id.is_empty()
+        && sensor
+            .id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if !valid_id {
+        return Err("virtual sensor id must only contain letters, numbers, '_', '-' or '.'");
+    }
+
+    match &sensor.formula {
+        VirtualFormula::Average { inputs }
+        | VirtualFormula::Min { inputs }
+        | VirtualFormula::Max { inputs }
+        | VirtualFormula::Sum { inputs } => {
+            if inputs.is_empty() {
+                return Err("virtual sensor must have at least one input");
+            }
+        }
+        VirtualFormula::Weighted { terms, offset } => {
+            if terms.is_empty() {
+                return Err("virtual sensor must have at least one term");
+            }
+
+            if !offset.is_finite() || terms.iter().any(|term| !term.weight.is_finite()) {
+                return Err("virtual sensor weights and offset must be finite");
+            }
+        }
+    }
+
+    Ok(())
 }
 
-fn map_hardware((index, hardware): (usize, &lhm_sys::Hardware)) -> lhm_shared::Hardware {
+/// Current unix timestamp in milliseconds
+fn timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn map_hardware<H: BackendHardware>(
+    cache: &HardwareCache<H>,
+    (index, hardware): (usize, &H),
+) -> lhm_shared::Hardware {
+    let parent_index = cache.get_hardware_parent_index(index);
+
     Hardware {
         index,
         identifier: hardware.identifier(),
         name: hardware.name(),
-        ty: HardwareType::from(hardware.get_type()),
+        ty: hardware.hardware_type(),
+        parent_index,
+        parent_identifier: parent_index
+            .and_then(|parent_index| cache.get_hardware_by_idx(parent_index))
+            .map(|parent| parent.identifier()),
     }
 }
 
-fn map_sensor((index, sensor): (usize, &lhm_sys::Sensor)) -> lhm_shared::Sensor {
+fn map_sensor<S: BackendSensor>((index, sensor): (usize, &S)) -> lhm_shared::Sensor {
     Sensor {
         index,
         identifier: sensor.identifier(),
         name: sensor.name(),
-        ty: SensorType::from(sensor.get_type()),
+        ty: sensor.sensor_type(),
         value: sensor.value(),
+        min: sensor.min(),
+        max: sensor.max(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ComputerActor, EVENT_CAPACITY};
+    use crate::{
+        ServerConfig,
+        backend::memory::{MemoryBackend, MemorySensor, TestHardware, test_hardware},
+    };
+    use lhm_shared::{
+        ComputerOptions, ErrorCode, Hardware, HardwareType, PipeRequest, PipeResponse, Sensor,
+        SensorType, ServerEvent,
+        alert::{AlertComparison, AlertRule, SensorSelector},
+        virtual_sensor::{VirtualFormula, VirtualSensor},
+    };
+    use tokio::sync::broadcast;
+
+    /// Actor for the shared test hardware along with the sender
+    /// for its events
+    struct Fixture {
+        actor: ComputerActor<MemoryBackend>,
+        events: broadcast::Sender<ServerEvent>,
+        temperature: MemorySensor,
+        control: MemorySensor,
+    }
+
+    fn fixture() -> Fixture {
+        fixture_with_config(&ServerConfig::default())
+    }
+
+    fn fixture_with_config(config: &ServerConfig) -> Fixture {
+        let TestHardware {
+            backend,
+            temperature,
+            control,
+        } = test_hardware();
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+
+        Fixture {
+            actor: ComputerActor::new(backend, config, events.clone()),
+            events,
+            temperature,
+            control,
+        }
+    }
+
+    impl Fixture {
+        /// Enables the provided options for a connection and loads the hardware
+        fn enable(&mut self, connection_id: u32, options: ComputerOptions) {
+            self.request(connection_id, PipeRequest::SetOptions { options });
+            self.request(connection_id, PipeRequest::UpdateAll);
+        }
+
+        fn request(&mut self, connection_id: u32, request: PipeRequest) -> PipeResponse {
+            self.actor.handle_request(connection_id, request)
+        }
+
+        fn hardware(
+            &mut self,
+            parent_id: Option<Option<String>>,
+            ty: Option<HardwareType>,
+        ) -> Vec<Hardware> {
+            match self.request(0, PipeRequest::QueryHardware { parent_id, ty }) {
+                PipeResponse::Hardwares { hardware } => hardware,
+                response => panic!("unexpected response {response:?}"),
+            }
+        }
+
+        fn sensors(&mut self, parent_id: Option<&str>, ty: Option<SensorType>) -> Vec<Sensor> {
+            let request = PipeRequest::QuerySensors {
+                parent_id: parent_id.map(str::to_string),
+                ty,
+            };
+
+            match self.request(0, request) {
+                PipeResponse::Sensors { sensors } => sensors,
+                response => panic!("unexpected response {response:?}"),
+            }
+        }
+
+        fn set_control(&mut self, connection_id: u32, value: f32) -> PipeResponse {
+            self.request(
+                connection_id,
+                PipeRequest::SetSensorControl {
+                    id: "/lpc/nct6798d/control/0".to_string(),
+                    value,
+                },
+            )
+        }
+    }
+
+    fn identifiers<T>(items: &[T], identifier: impl Fn(&T) -> &str) -> Vec<&str> {
+        items.iter().map(identifier).collect()
+    }
+
+    fn error_code(response: &PipeResponse) -> Option<ErrorCode> {
+        match response {
+            PipeResponse::Error { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_query_hardware_by_parent() {
+        let mut fixture = fixture();
+        fixture.enable(0, ComputerOptions::all());
+
+        let all = fixture.hardware(None, None);
+        assert_eq!(
+            identifiers(&all, |item| &item.identifier),
+            ["/intelcpu/0", "/motherboard", "/lpc/nct6798d"]
+        );
+
+        // Only top level hardware
+        let top_level = fixture.hardware(Some(None), None);
+        assert_eq!(
+            identifiers(&top_level, |item| &item.identifier),
+            ["/intelcpu/0", "/motherboard"]
+        );
+
+        // Only children of the motherboard
+        let children = fixture.hardware(Some(Some("/motherboard".to_string())), None);
+        assert_eq!(
+            identifiers(&children, |item| &item.identifier),
+            ["/lpc/nct6798d"]
+        );
+        assert_eq!(
+            children[0].parent_identifier.as_deref(),
+            Some("/motherboard")
+        );
+
+        let by_type = fixture.hardware(None, Some(HardwareType::SuperIO));
+        assert_eq!(
+            identifiers(&by_type, |item| &item.identifier),
+            ["/lpc/nct6798d"]
+        );
+
+        let missing = fixture.request(
+            0,
+            PipeRequest::QueryHardware {
+                parent_id: Some(Some("/missing".to_string())),
+                ty: None,
+            },
+        );
+        assert_eq!(error_code(&missing), Some(ErrorCode::NotFound));
+    }
+
+    #[test]
+    fn test_query_sensors_by_type_and_parent() {
+        let mut fixture = fixture();
+        fixture.enable(0, ComputerOptions::all());
+
+        let temperatures = fixture.sensors(None, Some(SensorType::Temperature));
+        assert_eq!(
+            identifiers(&temperatures, |item| &item.identifier),
+            ["/intelcpu/0/temperature/0", "/lpc/nct6798d/temperature/0"]
+        );
+
+        let cpu = fixture.sensors(Some("/intelcpu/0"), None);
+        assert_eq!(
+            identifiers(&cpu, |item| &item.identifier),
+            ["/intelcpu/0/temperature/0", "/intelcpu/0/load/0"]
+        );
+        assert_eq!(cpu[0].value, 55.0);
+
+        let fans = fixture.sensors(Some("/lpc/nct6798d"), Some(SensorType::Fan));
+        assert_eq!(
+            identifiers(&fans, |item| &item.identifier),
+            ["/lpc/nct6798d/fan/0"]
+        );
+
+        // Sensors only belong to their direct parent
+        assert!(fixture.sensors(Some("/motherboard"), None).is_empty());
+
+        let missing = fixture.request(
+            0,
+            PipeRequest::QuerySensors {
+                parent_id: Some("/missing".to_string()),
+                ty: None,
+            },
+        );
+        assert_eq!(error_code(&missing), Some(ErrorCode::NotFound));
+    }
+
+    #[test]
+    fn test_options_filter_hardware() {
+        let mut fixture = fixture();
+
+        // Nothing is enabled by default
+        fixture.request(0, PipeRequest::UpdateAll);
+        assert!(fixture.hardware(None, None).is_empty());
+
+        fixture.enable(
+            1,
+            ComputerOptions {
+                cpu_enabled: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            identifiers(&fixture.hardware(None, None), |item| &item.identifier),
+            ["/intelcpu/0"]
+        );
+
+        // Options from each connection are combined
+        fixture.enable(
+            2,
+            ComputerOptions {
+                motherboard_enabled: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(fixture.hardware(None, None).len(), 3);
+
+        // Disconnecting releases the options requested by the connection
+        fixture.actor.disconnect(1);
+        fixture.request(2, PipeRequest::UpdateAll);
+        assert_eq!(
+            identifiers(&fixture.hardware(None, None), |item| &item.identifier),
+            ["/motherboard", "/lpc/nct6798d"]
+        );
+    }
+
+    #[test]
+    fn test_set_sensor_control_validation() {
+        let mut fixture = fixture();
+        fixture.enable(0, ComputerOptions::all());
+
+        for value in [-0.1, 100.1, f32::NAN] {
+            assert_eq!(
+                error_code(&fixture.set_control(0, value)),
+                Some(ErrorCode::InvalidRequest),
+                "{value} should be rejected"
+            );
+        }
+
+        for value in [0.0, 100.0, 65.0] {
+            assert!(matches!(
+                fixture.set_control(0, value),
+                PipeResponse::Success
+            ));
+        }
+
+        // Sensors that are not controls cannot be controlled
+        let fan = fixture.request(
+            0,
+            PipeRequest::SetSensorControl {
+                id: "/lpc/nct6798d/fan/0".to_string(),
+                value: 50.0,
+            },
+        );
+        assert_eq!(error_code(&fan), Some(ErrorCode::InvalidRequest));
+
+        let missing = fixture.request(
+            0,
+            PipeRequest::SetSensorControl {
+                id: "/missing".to_string(),
+                value: 50.0,
+            },
+        );
+        assert_eq!(error_code(&missing), Some(ErrorCode::NotFound));
+    }
+
+    #[test]
+    fn test_control_restored_on_disconnect() {
+        use crate::backend::BackendSensor;
+
+        let mut fixture = fixture();
+        fixture.enable(1, ComputerOptions::all());
+        fixture.enable(2, ComputerOptions::all());
+
+        assert!(matches!(
+            fixture.set_control(1, 80.0),
+            PipeResponse::Success
+        ));
+        fixture.request(1, PipeRequest::UpdateAll);
+        assert_eq!(fixture.control.value(), 80.0);
+
+        // Disconnecting another connection keeps the control
+        fixture.actor.disconnect(2);
+        fixture.request(1, PipeRequest::UpdateAll);
+        assert_eq!(fixture.control.value(), 80.0);
+
+        // Disconnecting the owner restores the default control
+        fixture.actor.disconnect(1);
+        fixture.enable(3, ComputerOptions::all());
+        assert_eq!(fixture.control.value(), 40.0);
+    }
+
+    #[test]
+    fn test_history_pruned_on_removal() {
+        let mut fixture = fixture_with_config(&ServerConfig {
+            history_length: 8,
+            ..Default::default()
+        });
+        fixture.enable(0, ComputerOptions::all());
+
+        let history = |fixture: &mut Fixture| {
+            let request = PipeRequest::GetSensorHistory {
+                id: "/intelcpu/0/load/0".to_string(),
+                since: None,
+                max_points: None,
+                downsample: false,
+            };
+
+            match fixture.request(0, request) {
+                PipeResponse::SensorHistory { points } => points,
+                response => panic!("unexpected response {response:?}"),
+            }
+        };
+
+        assert_eq!(history(&mut fixture).map(|points| points.len()), Some(1));
+
+        // Disabling the CPU removes its sensors along with their history
+        fixture.enable(
+            0,
+            ComputerOptions {
+                cpu_enabled: false,
+                ..ComputerOptions::all()
+            },
+        );
+        assert!(history(&mut fixture).is_none());
+    }
+
+    #[test]
+    fn test_alerts_evaluated_on_update() {
+        let mut fixture = fixture();
+        fixture.enable(0, ComputerOptions::all());
+
+        let rule = AlertRule {
+            name: "cpu-hot".to_string(),
+            selector: SensorSelector::Sensor {
+                id: "/intelcpu/0/temperature/0".to_string(),
+            },
+            comparison: AlertComparison::Above,
+            threshold: 80.0,
+            duration_ms: 0,
+            hysteresis: 0.0,
+            cooldown_ms: 0,
+        };
+        let response = fixture.request(0, PipeRequest::AddAlertRule { rule });
+        assert!(matches!(response, PipeResponse::Success));
+
+        let mut events = fixture.events.subscribe();
+
+        // Updating only the hardware of the sensor fires the alert
+        fixture.temperature.set_value(90.0);
+        fixture.request(
+            0,
+            PipeRequest::UpdateHardwareById {
+                id: "/intelcpu/0".to_string(),
+            },
+        );
+        match events.try_recv() {
+            Ok(ServerEvent::AlertFired {
+                rule,
+                sensor_id,
+                value,
+            }) => {
+                assert_eq!(rule, "cpu-hot");
+                assert_eq!(sensor_id, "/intelcpu/0/temperature/0");
+                assert_eq!(value, 90.0);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        // Updating the sensor through a subscription clears the alert
+        fixture.temperature.set_value(60.0);
+        fixture
+            .actor
+            .poll_subscription(&["/intelcpu/0/temperature/0".to_string()]);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ServerEvent::AlertCleared { value: 60.0, .. })
+        ));
+
+        // Updating the sensor directly fires the alert again
+        fixture.temperature.set_value(85.0);
+        fixture.request(
+            0,
+            PipeRequest::UpdateSensorById {
+                id: "/intelcpu/0/temperature/0".to_string(),
+            },
+        );
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ServerEvent::AlertFired { value: 85.0, .. })
+        ));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_virtual_sensor_changes_generation() {
+        let mut fixture = fixture();
+        fixture.enable(0, ComputerOptions::all());
+
+        let generation =
+            |fixture: &mut Fixture| match fixture.request(0, PipeRequest::GetCacheGeneration) {
+                PipeResponse::CacheGeneration { generation } => generation,
+                response => panic!("unexpected response {response:?}"),
+            };
+
+        let mut events = fixture.events.subscribe();
+        let initial = generation(&mut fixture);
+
+        let sensor = VirtualSensor {
+            id: "cpu-max".to_string(),
+            name: "CPU Max".to_string(),
+            ty: SensorType::Temperature,
+            formula: VirtualFormula::Max {
+                inputs: vec![SensorSelector::Type {
+                    ty: SensorType::Temperature,
+                    hardware_ty: Some(HardwareType::Cpu),
+                }],
+            },
+        };
+        let response = fixture.request(0, PipeRequest::AddVirtualSensor { sensor });
+        assert!(matches!(response, PipeResponse::Success));
+
+        let added = generation(&mut fixture);
+        assert_ne!(added, initial);
+        match events.try_recv() {
+            Ok(ServerEvent::SensorAdded { sensor }) => {
+                assert_eq!(sensor.identifier, "/virtual/cpu-max");
+                assert_eq!(sensor.value, 55.0);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        let response = fixture.request(
+            0,
+            PipeRequest::RemoveVirtualSensor {
+                id: "cpu-max".to_string(),
+            },
+        );
+        assert!(matches!(response, PipeResponse::Success));
+
+        assert_ne!(generation(&mut fixture), added);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ServerEvent::SensorRemoved { identifier, .. }) if identifier == "/virtual/cpu-max"
+        ));
+        assert!(events.try_recv().is_err());
     }
 }
@@ -0,0 +1,428 @@
+use crate::sample::SensorSample;
+use lhm_shared::{ServerEvent, alert::AlertRule};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Evaluates the registered alert rules against the sensor values,
+/// tracking the state of each rule separately for every sensor
+#[derive(Default)]
+pub struct AlertEngine {
+    /// Registered rules in the order they were added
+    rules: Vec<RuleState>,
+}
+
+/// Rule along with the state of the alert for each matching sensor
+struct RuleState {
+    rule: AlertRule,
+    /// State for each sensor, keyed by sensor identifier. Sensors that
+    /// are not past the threshold have no state
+    sensors: HashMap<String, AlertState>,
+}
+
+/// State of an alert for a single sensor
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AlertState {
+    /// Value is past the threshold but has not been for long enough
+    Pending { since: Instant },
+    /// Alert has fired
+    Active { value: f32 },
+    /// Alert has cleared and cannot fire again until the cooldown ends
+    Cooldown { until: Instant },
+}
+
+impl AlertEngine {
+    /// Check if there are no rules registered
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Registered rules in the order they were added
+    pub fn rules(&self) -> impl Iterator<Item = &AlertRule> + '_ {
+        self.rules.iter().map(|state| &state.rule)
+    }
+
+    /// Adds a rule replacing any existing rule with the same name, provides
+    /// events clearing any alerts active for the replaced rule
+    pub fn add_rule(&mut self, rule: AlertRule) -> Vec<ServerEvent> {
+        let events = self.remove_rule(&rule.name).unwrap_or_default();
+
+        self.rules.push(RuleState {
+            rule,
+            sensors: HashMap::new(),
+        });
+
+        events
+    }
+
+    /// Removes a rule by name, provides events clearing any alerts active
+    /// for the rule or [None] if the rule does not exist
+    pub fn remove_rule(&mut self, name: &str) -> Option<Vec<ServerEvent>> {
+        let index = self
+            .rules
+            .iter()
+            .position(|state| state.rule.name == name)?;
+        let RuleState { rule, sensors } = self.rules.remove(index);
+
+        let events = sensors
+            .into_iter()
+            .filter_map(|(sensor_id, state)| match state {
+                AlertState::Active { value } => Some(ServerEvent::AlertCleared {
+                    rule: rule.name.clone(),
+                    sensor_id,
+                    value,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Some(events)
+    }
+
+    /// Evaluates the rules against the provided sensor values at `now`,
+    /// providing events for any alerts that fired or cleared
+    ///
+    /// Alerts for sensors that are no longer present are cleared
+    pub fn evaluate(&mut self, now: Instant, samples: &[SensorSample]) -> Vec<ServerEvent> {
+        let mut events = Vec::new();
+
+        for state in &mut self.rules {
+            let rule = &state.rule;
+            let mut present = Vec::new();
+
+            for sample in samples {
+                if !sample.is_selected(&rule.selector) {
+                    continue;
+                }
+
+                present.push(sample.identifier.as_str());
+
+                let current = state.sensors.get(&sample.identifier).copied();
+                let next = step(rule, current, now, sample.value);
+
+                match (current, next) {
+                    (Some(AlertState::Active { .. }), Some(AlertState::Active { .. })) => {}
+                    (_, Some(AlertState::Active { value })) => {
+                        events.push(ServerEvent::AlertFired {
+                            rule: rule.name.clone(),
+                            sensor_id: sample.identifier.clone(),
+                            value,
+                        });
+                    }
+                    (Some(AlertState::Active { .. }), _) => {
+                        events.push(ServerEvent::AlertCleared {
+                            rule: rule.name.clone(),
+                            sensor_id: sample.identifier.clone(),
+                            value: sample.value,
+                        });
+                    }
+                    _ => {}
+                }
+
+                match next {
+                    Some(next) => {
+                        state.sensors.insert(sample.identifier.clone(), next);
+                    }
+                    None => {
+                        state.sensors.remove(&sample.identifier);
+                    }
+                }
+            }
+
+            // Clear the alerts for sensors that are no longer present
+            state.sensors.retain(|sensor_id, alert| {
+                if present.contains(&sensor_id.as_str()) {
+                    return true;
+                }
+
+                if let AlertState::Active { value } = alert {
+                    events.push(ServerEvent::AlertCleared {
+                        rule: rule.name.clone(),
+                        sensor_id: sensor_id.clone(),
+                        value: *value,
+                    });
+                }
+
+                false
+            });
+        }
+
+        events
+    }
+}
+
+/// Determines the next state of an alert for a sensor value
+fn step(
+    rule: &AlertRule,
+    current: Option<AlertState>,
+    now: Instant,
+    value: f32,
+) -> Option<AlertState> {
+    let current = match current {
+        // Cooldown has ended, alert can fire again
+        Some(AlertState::Cooldown { until }) if now >= until => None,
+        current => current,
+    };
+
+    match current {
+        Some(AlertState::Cooldown { until }) => Some(AlertState::Cooldown { until }),
+
+        Some(AlertState::Active { value: last_value }) => {
+            if !rule.is_cleared(value) {
+                // Sensors without a value keep the last known value
+                let value = if value.is_nan() { last_value } else { value };
+                return Some(AlertState::Active { value });
+            }
+
+            let cooldown = Duration::from_millis(rule.cooldown_ms);
+            if cooldown.is_zero() {
+                return None;
+            }
+
+            Some(AlertState::Cooldown {
+                until: now + cooldown,
+            })
+        }
+
+        Some(AlertState::Pending { .. }) | None => {
+            if !rule.is_triggered(value) {
+                return None;
+            }
+
+            let since = match current {
+                Some(AlertState::Pending { since }) => since,
+                _ => now,
+            };
+
+            if now.duration_since(since) >= Duration::from_millis(rule.duration_ms) {
+                Some(AlertState::Active { value })
+            } else {
+                Some(AlertState::Pending { since })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AlertEngine;
+    use crate::sample::SensorSample;
+    use lhm_shared::{
+        HardwareType, SensorType, ServerEvent,
+        alert::{AlertComparison, AlertRule, SensorSelector},
+    };
+    use std::time::{Duration, Instant};
+
+    /// Clock that only moves when advanced
+    struct FakeClock {
+        now: Instant,
+    }
+
+    impl FakeClock {
+        fn new() -> Self {
+            Self {
+                now: Instant::now(),
+            }
+        }
+
+        fn advance(&mut self, ms: u64) -> Instant {
+            self.now += Duration::from_millis(ms);
+            self.now
+        }
+    }
+
+    fn gpu_rule() -> AlertRule {
+        AlertRule {
+            name: "gpu-hot".to_string(),
+            selector: SensorSelector::Type {
+                ty: SensorType::Temperature,
+                hardware_ty: Some(HardwareType::GpuNvidia),
+            },
+            comparison: AlertComparison::Above,
+            threshold: 85.0,
+            duration_ms: 30_000,
+            hysteresis: 5.0,
+            cooldown_ms: 60_000,
+        }
+    }
+
+    fn gpu_temp(value: f32) -> Vec<SensorSample> {
+        vec![SensorSample {
+            identifier: "/gpu-nvidia/0/temperature/0".to_string(),
+            ty: SensorType::Temperature,
+            hardware_identifier: "/gpu-nvidia/0".to_string(),
+            hardware_ty: HardwareType::GpuNvidia,
+            value,
+        }]
+    }
+
+    fn is_fired(events: &[ServerEvent]) -> bool {
+        matches!(events, [ServerEvent::AlertFired { .. }])
+    }
+
+    fn is_cleared(events: &[ServerEvent]) -> bool {
+        matches!(events, [ServerEvent::AlertCleared { .. }])
+    }
+
+    /// Alert only fires once the value has been past the threshold
+    /// for the duration of the rule
+    #[test]
+    fn test_fires_after_duration() {
+        let mut clock = FakeClock::new();
+        let mut engine = AlertEngine::default();
+        engine.add_rule(gpu_rule());
+
+        assert!(engine.evaluate(clock.now, &gpu_temp(90.0)).is_empty());
+        assert!(
+            engine
+                .evaluate(clock.advance(29_000), &gpu_temp(90.0))
+                .is_empty()
+        );
+        assert!(is_fired(
+            &engine.evaluate(clock.advance(1_000), &gpu_temp(90.0))
+        ));
+
+        // Already active alerts do not fire again
+        assert!(
+            engine
+                .evaluate(clock.advance(1_000), &gpu_temp(91.0))
+                .is_empty()
+        );
+    }
+
+    /// Dropping below the threshold before the duration resets the timer
+    #[test]
+    fn test_duration_resets() {
+        let mut clock = FakeClock::new();
+        let mut engine = AlertEngine::default();
+        engine.add_rule(gpu_rule());
+
+        engine.evaluate(clock.now, &gpu_temp(90.0));
+        assert!(
+            engine
+                .evaluate(clock.advance(20_000), &gpu_temp(80.0))
+                .is_empty()
+        );
+        assert!(
+            engine
+                .evaluate(clock.advance(1_000), &gpu_temp(90.0))
+                .is_empty()
+        );
+        assert!(
+            engine
+                .evaluate(clock.advance(20_000), &gpu_temp(90.0))
+                .is_empty()
+        );
+        assert!(is_fired(
+            &engine.evaluate(clock.advance(10_000), &gpu_temp(90.0))
+        ));
+    }
+
+    /// Alert only clears once the value moves past the hysteresis
+    #[test]
+    fn test_hysteresis() {
+        let mut clock = FakeClock::new();
+        let mut engine = AlertEngine::default();
+        engine.add_rule(AlertRule {
+            duration_ms: 0,
+            ..gpu_rule()
+        });
+
+        assert!(is_fired(&engine.evaluate(clock.now, &gpu_temp(86.0))));
+        assert!(
+            engine
+                .evaluate(clock.advance(1_000), &gpu_temp(84.0))
+                .is_empty()
+        );
+        assert!(
+            engine
+                .evaluate(clock.advance(1_000), &gpu_temp(80.1))
+                .is_empty()
+        );
+        assert!(is_cleared(
+            &engine.evaluate(clock.advance(1_000), &gpu_temp(80.0))
+        ));
+    }
+
+    /// Alert cannot fire again until the cooldown has ended
+    #[test]
+    fn test_cooldown() {
+        let mut clock = FakeClock::new();
+        let mut engine = AlertEngine::default();
+        engine.add_rule(AlertRule {
+            duration_ms: 0,
+            ..gpu_rule()
+        });
+
+        assert!(is_fired(&engine.evaluate(clock.now, &gpu_temp(90.0))));
+        assert!(is_cleared(
+            &engine.evaluate(clock.advance(1_000), &gpu_temp(70.0))
+        ));
+        assert!(
+            engine
+                .evaluate(clock.advance(59_000), &gpu_temp(90.0))
+                .is_empty()
+        );
+        assert!(is_fired(
+            &engine.evaluate(clock.advance(1_000), &gpu_temp(90.0))
+        ));
+    }
+
+    /// Below comparisons fire when the value drops under the threshold
+    #[test]
+    fn test_below() {
+        let clock = FakeClock::new();
+        let mut engine = AlertEngine::default();
+        engine.add_rule(AlertRule {
+            comparison: AlertComparison::Below,
+            threshold: 20.0,
+            duration_ms: 0,
+            ..gpu_rule()
+        });
+
+        assert!(engine.evaluate(clock.now, &gpu_temp(25.0)).is_empty());
+        assert!(is_fired(&engine.evaluate(clock.now, &gpu_temp(15.0))));
+    }
+
+    /// Sensors that do not match the selector are ignored
+    #[test]
+    fn test_selector() {
+        let clock = FakeClock::new();
+        let mut engine = AlertEngine::default();
+        engine.add_rule(AlertRule {
+            duration_ms: 0,
+            ..gpu_rule()
+        });
+
+        let samples = vec![SensorSample {
+            identifier: "/intelcpu/0/temperature/0".to_string(),
+            ty: SensorType::Temperature,
+            hardware_identifier: "/intelcpu/0".to_string(),
+            hardware_ty: HardwareType::Cpu,
+            value: 95.0,
+        }];
+
+        assert!(engine.evaluate(clock.now, &samples).is_empty());
+    }
+
+    /// Removing a rule or the sensor disappearing clears active alerts
+    #[test]
+    fn test_clears_when_removed() {
+        let mut clock = FakeClock::new();
+        let mut engine = AlertEngine::default();
+        engine.add_rule(AlertRule {
+            duration_ms: 0,
+            ..gpu_rule()
+        });
+
+        assert!(is_fired(&engine.evaluate(clock.now, &gpu_temp(90.0))));
+        assert!(is_cleared(&engine.evaluate(clock.advance(1_000), &[])));
+
+        assert!(is_fired(
+            &engine.evaluate(clock.advance(1_000), &gpu_temp(90.0))
+        ));
+        assert!(is_cleared(&engine.remove_rule("gpu-hot").unwrap()));
+        assert!(engine.is_empty());
+    }
+}
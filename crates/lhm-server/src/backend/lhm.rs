@@ -0,0 +1,102 @@
+use super::{BackendHardware, BackendSensor, HardwareBackend};
+use lhm_shared::{ComputerOptions, HardwareType, SensorType};
+use lhm_sys::{Computer, Hardware, Sensor};
+
+impl HardwareBackend for Computer {
+    type Hardware = Hardware;
+
+    fn update(&mut self) {
+        Computer::update(self);
+    }
+
+    fn set_options(&mut self, options: &ComputerOptions) {
+        Computer::set_options(
+            self,
+            lhm_sys::ComputerOptions {
+                battery_enabled: options.battery_enabled,
+                controller_enabled: options.controller_enabled,
+                cpu_enabled: options.cpu_enabled,
+                gpu_enabled: options.gpu_enabled,
+                memory_enabled: options.memory_enabled,
+                motherboard_enabled: options.motherboard_enabled,
+                network_enabled: options.network_enabled,
+                psu_enabled: options.psu_enabled,
+                storage_enabled: options.storage_enabled,
+            },
+        );
+    }
+
+    fn hardware(&self) -> Vec<Hardware> {
+        Computer::hardware(self)
+    }
+}
+
+impl BackendHardware for Hardware {
+    type Sensor = Sensor;
+
+    fn identifier(&self) -> String {
+        Hardware::identifier(self)
+    }
+
+    fn name(&self) -> String {
+        Hardware::name(self)
+    }
+
+    fn hardware_type(&self) -> HardwareType {
+        HardwareType::from(self.get_type())
+    }
+
+    fn children(&self) -> Vec<Hardware> {
+        self.get_children()
+    }
+
+    fn sensors(&self) -> Vec<Sensor> {
+        Hardware::sensors(self)
+    }
+
+    fn update(&mut self) {
+        Hardware::update(self);
+    }
+}
+
+impl BackendSensor for Sensor {
+    fn identifier(&self) -> String {
+        Sensor::identifier(self)
+    }
+
+    fn name(&self) -> String {
+        Sensor::name(self)
+    }
+
+    fn sensor_type(&self) -> SensorType {
+        SensorType::from(self.get_type())
+    }
+
+    fn value(&self) -> f32 {
+        Sensor::value(self)
+    }
+
+    fn min(&self) -> f32 {
+        Sensor::min(self)
+    }
+
+    fn max(&self) -> f32 {
+        Sensor::max(self)
+    }
+
+    fn reset_min_max(&mut self) {
+        Sensor::reset_min_max(self);
+    }
+
+    fn set_control_software(&mut self, percent: f32) -> bool {
+        Sensor::set_control_software(self, percent)
+    }
+
+    fn set_control_default(&mut self) -> bool {
+        Sensor::set_control_default(self)
+    }
+
+    fn update(&mut self) {
+        Sensor::update(self);
+    }
+}
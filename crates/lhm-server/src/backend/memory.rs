@@ -0,0 +1,394 @@
+//! In-memory backend where the hardware and sensors are defined up front
+//! and the sensor values are set directly, useful for running the server
+//! without access to real hardware
+
+use super::{BackendHardware, BackendSensor, HardwareBackend};
+use lhm_shared::{ComputerOptions, HardwareType, SensorType};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// In-memory [HardwareBackend]
+///
+/// Only top level hardware belonging to an enabled hardware group
+/// (See [ComputerOptions]) is provided by [HardwareBackend::hardware]
+pub struct MemoryBackend {
+    /// Top level hardware
+    hardware: Vec<MemoryHardware>,
+    /// Currently enabled hardware groups
+    options: ComputerOptions,
+}
+
+impl MemoryBackend {
+    /// Create a backend from a list of top level hardware
+    pub fn new(hardware: Vec<MemoryHardware>) -> Self {
+        Self {
+            hardware,
+            options: ComputerOptions::default(),
+        }
+    }
+}
+
+impl HardwareBackend for MemoryBackend {
+    type Hardware = MemoryHardware;
+
+    fn update(&mut self) {
+        for hardware in &self.hardware {
+            if is_hardware_enabled(&self.options, hardware.hardware_type()) {
+                hardware.update_all();
+            }
+        }
+    }
+
+    fn set_options(&mut self, options: &ComputerOptions) {
+        self.options = options.clone();
+    }
+
+    fn hardware(&self) -> Vec<MemoryHardware> {
+        self.hardware
+            .iter()
+            .filter(|hardware| is_hardware_enabled(&self.options, hardware.hardware_type()))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Check if the hardware group for a type of hardware is enabled
+fn is_hardware_enabled(options: &ComputerOptions, ty: HardwareType) -> bool {
+    match ty {
+        HardwareType::Motherboard | HardwareType::SuperIO => options.motherboard_enabled,
+        HardwareType::Cpu => options.cpu_enabled,
+        HardwareType::Memory => options.memory_enabled,
+        HardwareType::GpuNvidia | HardwareType::GpuAmd | HardwareType::GpuIntel => {
+            options.gpu_enabled
+        }
+        HardwareType::Storage => options.storage_enabled,
+        HardwareType::Network => options.network_enabled,
+        HardwareType::Cooler | HardwareType::EmbeddedController => options.controller_enabled,
+        HardwareType::Psu => options.psu_enabled,
+        HardwareType::Battery => options.battery_enabled,
+        HardwareType::Unknown(_) => true,
+    }
+}
+
+/// In-memory hardware item, clones share the same underlying hardware
+#[derive(Clone)]
+pub struct MemoryHardware {
+    inner: Arc<MemoryHardwareInner>,
+}
+
+struct MemoryHardwareInner {
+    identifier: String,
+    name: String,
+    ty: HardwareType,
+    children: Vec<MemoryHardware>,
+    sensors: Vec<MemorySensor>,
+}
+
+impl MemoryHardware {
+    /// Create a new hardware item
+    pub fn new(
+        identifier: impl Into<String>,
+        name: impl Into<String>,
+        ty: HardwareType,
+        children: Vec<MemoryHardware>,
+        sensors: Vec<MemorySensor>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(MemoryHardwareInner {
+                identifier: identifier.into(),
+                name: name.into(),
+                ty,
+                children,
+                sensors,
+            }),
+        }
+    }
+
+    /// Updates the sensors for this hardware
+    fn update_sensors(&self) {
+        for sensor in &self.inner.sensors {
+            sensor.apply_value();
+        }
+    }
+
+    /// Updates the sensors for this hardware and all of its children
+    fn update_all(&self) {
+        self.update_sensors();
+
+        for child in &self.inner.children {
+            child.update_all();
+        }
+    }
+}
+
+impl BackendHardware for MemoryHardware {
+    type Sensor = MemorySensor;
+
+    fn identifier(&self) -> String {
+        self.inner.identifier.clone()
+    }
+
+    fn name(&self) -> String {
+        self.inner.name.clone()
+    }
+
+    fn hardware_type(&self) -> HardwareType {
+        self.inner.ty
+    }
+
+    fn children(&self) -> Vec<MemoryHardware> {
+        self.inner.children.clone()
+    }
+
+    fn sensors(&self) -> Vec<MemorySensor> {
+        self.inner.sensors.clone()
+    }
+
+    fn update(&mut self) {
+        self.update_sensors();
+    }
+}
+
+/// Source of values for a [MemorySensor], sampled each time the sensor
+/// is updated
+pub trait SensorValueSource: Send + 'static {
+    /// Get the next value for the sensor, [None] to keep the current value
+    fn next_value(&mut self) -> Option<f32>;
+}
+
+/// In-memory sensor item, clones share the same underlying sensor
+/// so a clone can be kept to set the sensor value
+#[derive(Clone)]
+pub struct MemorySensor {
+    inner: Arc<MemorySensorInner>,
+}
+
+struct MemorySensorInner {
+    identifier: String,
+    name: String,
+    ty: SensorType,
+    state: Mutex<MemorySensorState>,
+}
+
+struct MemorySensorState {
+    /// Current value of the sensor
+    value: f32,
+    /// Minimum recorded value
+    min: f32,
+    /// Maximum recorded value
+    max: f32,
+    /// Value to apply on the next update
+    next_value: Option<f32>,
+    /// Source for new values when the sensor is updated
+    source: Option<Box<dyn SensorValueSource>>,
+    /// Software controlled value for [SensorType::Control] sensors,
+    /// takes priority over the source while set
+    control: Option<f32>,
+    /// Value from before the sensor was controlled, restored when the
+    /// control is released from a sensor without a source
+    uncontrolled_value: f32,
+}
+
+impl MemorySensor {
+    /// Create a new sensor with an initial value
+    pub fn new(
+        identifier: impl Into<String>,
+        name: impl Into<String>,
+        ty: SensorType,
+        value: f32,
+    ) -> Self {
+        Self {
+            inner: Arc::new(MemorySensorInner {
+                identifier: identifier.into(),
+                name: name.into(),
+                ty,
+                state: Mutex::new(MemorySensorState {
+                    value,
+                    min: value,
+                    max: value,
+                    next_value: None,
+                    source: None,
+                    control: None,
+                    uncontrolled_value: value,
+                }),
+            }),
+        }
+    }
+
+    /// Create a new sensor that takes its values from `source` on each update
+    pub fn with_source(
+        identifier: impl Into<String>,
+        name: impl Into<String>,
+        ty: SensorType,
+        mut source: impl SensorValueSource,
+    ) -> Self {
+        let sensor = Self::new(
+            identifier,
+            name,
+            ty,
+            source.next_value().unwrap_or(f32::NAN),
+        );
+        sensor.inner.state.lock().source = Some(Box::new(source));
+        sensor
+    }
+
+    /// Set the value the sensor will take when it is next updated,
+    /// matching how real sensors only change when updated
+    pub fn set_value(&self, value: f32) {
+        self.inner.state.lock().next_value = Some(value);
+    }
+
+    /// Applies the next value if one has been set, otherwise
+    /// takes the next value from the source
+    fn apply_value(&self) {
+        let state = &mut *self.inner.state.lock();
+        let value = match (state.next_value.take(), state.control) {
+            (Some(value), _) => Some(value),
+            (None, Some(control)) => Some(control),
+            (None, None) => state.source.as_mut().and_then(|source| source.next_value()),
+        };
+
+        if let Some(value) = value {
+            state.value = value;
+            state.min = state.min.min(value);
+            state.max = state.max.max(value);
+        }
+    }
+}
+
+impl BackendSensor for MemorySensor {
+    fn identifier(&self) -> String {
+        self.inner.identifier.clone()
+    }
+
+    fn name(&self) -> String {
+        self.inner.name.clone()
+    }
+
+    fn sensor_type(&self) -> SensorType {
+        self.inner.ty
+    }
+
+    fn value(&self) -> f32 {
+        self.inner.state.lock().value
+    }
+
+    fn min(&self) -> f32 {
+        self.inner.state.lock().min
+    }
+
+    fn max(&self) -> f32 {
+        self.inner.state.lock().max
+    }
+
+    fn reset_min_max(&mut self) {
+        let state = &mut *self.inner.state.lock();
+        state.min = state.value;
+        state.max = state.value;
+    }
+
+    fn set_control_software(&mut self, percent: f32) -> bool {
+        if self.inner.ty != SensorType::Control {
+            return false;
+        }
+
+        let state = &mut *self.inner.state.lock();
+        if state.control.is_none() {
+            state.uncontrolled_value = state.value;
+        }
+
+        state.control = Some(percent);
+        true
+    }
+
+    fn set_control_default(&mut self) -> bool {
+        if self.inner.ty != SensorType::Control {
+            return false;
+        }
+
+        let state = &mut *self.inner.state.lock();
+        if state.control.take().is_some() && state.source.is_none() {
+            state.next_value = Some(state.uncontrolled_value);
+        }
+
+        true
+    }
+
+    fn update(&mut self) {
+        self.apply_value();
+    }
+}
+
+/// Hardware shared by tests, along with handles to the CPU package
+/// temperature and fan control sensors so their values can be changed
+/// and checked
+#[cfg(any(test, feature = "test-util"))]
+pub struct TestHardware {
+    /// Backend providing the hardware
+    pub backend: MemoryBackend,
+    /// CPU package temperature sensor
+    pub temperature: MemorySensor,
+    /// Fan control sensor of the Super I/O chip
+    pub control: MemorySensor,
+}
+
+/// Creates the hardware shared by tests, a CPU and a motherboard containing
+/// a Super I/O chip
+#[cfg(any(test, feature = "test-util"))]
+pub fn test_hardware() -> TestHardware {
+    let control = MemorySensor::new(
+        "/lpc/nct6798d/control/0",
+        "Fan Control #1",
+        SensorType::Control,
+        40.0,
+    );
+
+    let temperature = MemorySensor::new(
+        "/intelcpu/0/temperature/0",
+        "CPU Package",
+        SensorType::Temperature,
+        55.0,
+    );
+
+    let cpu = MemoryHardware::new(
+        "/intelcpu/0",
+        "Intel Core i7",
+        HardwareType::Cpu,
+        Vec::new(),
+        vec![
+            temperature.clone(),
+            MemorySensor::new("/intelcpu/0/load/0", "CPU Total", SensorType::Load, 12.0),
+        ],
+    );
+
+    let super_io = MemoryHardware::new(
+        "/lpc/nct6798d",
+        "Nuvoton NCT6798D",
+        HardwareType::SuperIO,
+        Vec::new(),
+        vec![
+            MemorySensor::new("/lpc/nct6798d/fan/0", "Fan #1", SensorType::Fan, 900.0),
+            MemorySensor::new(
+                "/lpc/nct6798d/temperature/0",
+                "System",
+                SensorType::Temperature,
+                35.0,
+            ),
+            control.clone(),
+        ],
+    );
+
+    let motherboard = MemoryHardware::new(
+        "/motherboard",
+        "ASUS PRIME Z790",
+        HardwareType::Motherboard,
+        vec![super_io],
+        Vec::new(),
+    );
+
+    TestHardware {
+        backend: MemoryBackend::new(vec![cpu, motherboard]),
+        temperature,
+        control,
+    }
+}
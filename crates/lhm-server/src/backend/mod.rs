@@ -0,0 +1,91 @@
+//! # Backend
+//!
+//! Abstraction over the source of hardware and sensors used by the server,
+//! allowing the server logic to run against something other than the
+//! Windows only Libre Hardware Monitor bindings
+
+use lhm_shared::{ComputerOptions, HardwareType, SensorType};
+
+#[cfg(windows)]
+mod lhm;
+pub mod memory;
+
+#[cfg(feature = "simulated")]
+pub mod simulated;
+
+/// Source of hardware and sensors for the server
+///
+/// Mirrors the structure of [lhm_sys::Computer] which is the primary
+/// implementation
+pub trait HardwareBackend: Send + 'static {
+    /// Hardware item provided by the backend
+    type Hardware: BackendHardware;
+
+    /// Updates all the hardware and sensors
+    fn update(&mut self);
+
+    /// Set the options for which hardware groups are enabled
+    fn set_options(&mut self, options: &ComputerOptions);
+
+    /// Get the list of top level hardware
+    fn hardware(&self) -> Vec<Self::Hardware>;
+}
+
+/// Hardware item provided by a [HardwareBackend]
+pub trait BackendHardware: Send + Sized + 'static {
+    /// Sensor item provided by the hardware
+    type Sensor: BackendSensor;
+
+    /// Get the unique identifier of the hardware
+    fn identifier(&self) -> String;
+
+    /// Get the name of the hardware
+    fn name(&self) -> String;
+
+    /// Get the type of hardware
+    fn hardware_type(&self) -> HardwareType;
+
+    /// Get all children hardware for this item
+    fn children(&self) -> Vec<Self>;
+
+    /// Get all sensors that belong to this hardware item
+    fn sensors(&self) -> Vec<Self::Sensor>;
+
+    /// Updates the hardware and all the sensors attached to it
+    fn update(&mut self);
+}
+
+/// Sensor item provided by a [BackendHardware]
+pub trait BackendSensor: Send + 'static {
+    /// Get the unique identifier of the sensor
+    fn identifier(&self) -> String;
+
+    /// Get the name of the sensor
+    fn name(&self) -> String;
+
+    /// Get the type of the sensor
+    fn sensor_type(&self) -> SensorType;
+
+    /// Get the last value for the sensor, NaN when the sensor has no value
+    fn value(&self) -> f32;
+
+    /// Get the minimum recorded value for the sensor
+    fn min(&self) -> f32;
+
+    /// Get the maximum recorded value for the sensor
+    fn max(&self) -> f32;
+
+    /// Resets the minimum and maximum recorded values for the sensor
+    fn reset_min_max(&mut self);
+
+    /// Sets a software controlled value for the sensor as a percentage,
+    /// returns false if the sensor cannot be controlled
+    fn set_control_software(&mut self, percent: f32) -> bool;
+
+    /// Restores the default control for the sensor, returns false
+    /// if the sensor cannot be controlled
+    fn set_control_default(&mut self) -> bool;
+
+    /// Updates the sensor value
+    fn update(&mut self);
+}
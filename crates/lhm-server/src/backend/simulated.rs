@@ -0,0 +1,626 @@
+//! Simulated backend that loads a hardware and sensor tree from a JSON or
+//! TOML fixture, generating sensor values from scripted curves so the
+//! server can be used without real sensors
+//!
+//! ```toml
+//! [[hardware]]
+//! identifier = "/intelcpu/0"
+//! name = "Intel Core i7-9700K"
+//! ty = "Cpu"
+//!
+//! [[hardware.sensors]]
+//! identifier = "/intelcpu/0/temperature/0"
+//! name = "CPU Package"
+//! ty = "Temperature"
+//! curve = { type = "sine", min = 40.0, max = 80.0, period_ms = 10000 }
+//! ```
+//!
+//! Recordings of a live session are fixtures using the `samples` curve
+//! and can be loaded the same way
+
+use super::memory::{MemoryBackend, MemoryHardware, MemorySensor, SensorValueSource};
+use lhm_shared::fixture::{Curve, Fixture, FixtureHardware, FixtureSensor};
+use std::{f32::consts::TAU, path::Path, sync::Arc, time::Instant};
+
+/// Seed used for random walks when the fixture does not specify one
+const DEFAULT_SEED: u64 = 0x4C48_4D53_494D_0001;
+
+/// Load a fixture from a `.json` or `.toml` file
+pub fn load_fixture(path: &Path) -> std::io::Result<Fixture> {
+    let contents = std::fs::read_to_string(path)?;
+
+    match path.extension().and_then(|value| value.to_str()) {
+        Some("json") => serde_json::from_str(&contents).map_err(std::io::Error::other),
+        Some("toml") => toml::from_str(&contents).map_err(std::io::Error::other),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "fixture must be a .json or .toml file",
+        )),
+    }
+}
+
+/// Create a backend from a fixture, `base_path` is the path
+/// that replay files are relative to
+pub fn from_fixture(fixture: Fixture, base_path: &Path) -> std::io::Result<MemoryBackend> {
+    let mut builder = FixtureBuilder {
+        base_path,
+        start: Instant::now(),
+        seed: fixture.seed.unwrap_or(DEFAULT_SEED),
+    };
+
+    let hardware = builder.build_hardware(fixture.hardware)?;
+    Ok(MemoryBackend::new(hardware))
+}
+
+/// Load a simulated backend from a `.json` or `.toml` fixture file
+pub fn load(path: &Path) -> std::io::Result<MemoryBackend> {
+    let fixture = load_fixture(path)?;
+    let base_path = path.parent().unwrap_or(Path::new(""));
+    from_fixture(fixture, base_path)
+}
+
+struct FixtureBuilder<'a> {
+    /// Path replay files are relative to
+    base_path: &'a Path,
+    /// Time the simulation started, curves are relative to this
+    start: Instant,
+    /// Next seed for a random walk
+    seed: u64,
+}
+
+impl FixtureBuilder<'_> {
+    fn build_hardware(
+        &mut self,
+        hardware: Vec<FixtureHardware>,
+    ) -> std::io::Result<Vec<MemoryHardware>> {
+        hardware
+            .into_iter()
+            .map(|hardware| {
+                let children = self.build_hardware(hardware.children)?;
+                let sensors = hardware
+                    .sensors
+                    .into_iter()
+                    .map(|sensor| self.build_sensor(sensor))
+                    .collect::<std::io::Result<Vec<_>>>()?;
+
+                Ok(MemoryHardware::new(
+                    hardware.identifier,
+                    hardware.name,
+                    hardware.ty,
+                    children,
+                    sensors,
+                ))
+            })
+            .collect()
+    }
+
+    fn build_sensor(&mut self, sensor: FixtureSensor) -> std::io::Result<MemorySensor> {
+        let start = self.start;
+
+        let sensor = match sensor.curve {
+            Curve::Constant { value } => {
+                MemorySensor::new(sensor.identifier, sensor.name, sensor.ty, value)
+            }
+
+            Curve::Sine {
+                min,
+                max,
+                period_ms,
+                phase_ms,
+            } => MemorySensor::with_source(
+                sensor.identifier,
+                sensor.name,
+                sensor.ty,
+                SineSource {
+                    start,
+                    min,
+                    max,
+                    period_ms: period_ms.max(1),
+                    phase_ms,
+                },
+            ),
+
+            Curve::RandomWalk {
+                start: value,
+                step,
+                min,
+                max,
+            } => {
+                let seed = self.seed;
+                self.seed = self.seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+                MemorySensor::with_source(
+                    sensor.identifier,
+                    sensor.name,
+                    sensor.ty,
+                    RandomWalkSource {
+                        value,
+                        step,
+                        min,
+                        max,
+                        rng: XorShift::new(seed),
+                        started: false,
+                    },
+                )
+            }
+
+            Curve::Replay { path, repeat } => {
+                let points = load_replay(&self.base_path.join(path))?;
+                MemorySensor::with_source(
+                    sensor.identifier,
+                    sensor.name,
+                    sensor.ty,
+                    ReplaySource {
+                        start,
+                        points,
+                        repeat,
+                    },
+                )
+            }
+
+            Curve::Samples {
+                mut samples,
+                repeat,
+            } => {
+                if samples.is_empty() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("sensor {} has no samples", sensor.identifier),
+                    ));
+                }
+
+                samples.sort_by_key(|(elapsed, _)| *elapsed);
+
+                MemorySensor::with_source(
+                    sensor.identifier,
+                    sensor.name,
+                    sensor.ty,
+                    ReplaySource {
+                        start,
+                        points: samples.into(),
+                        repeat,
+                    },
+                )
+            }
+        };
+
+        Ok(sensor)
+    }
+}
+
+/// Loads the `elapsed_ms,value` points from a replay CSV file
+fn load_replay(path: &Path) -> std::io::Result<Arc<[(u64, f32)]>> {
+    let contents = std::fs::read_to_string(path)?;
+
+    parse_replay(&contents).map_err(|err| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("replay file {}: {err}", path.display()),
+        )
+    })
+}
+
+/// Parses the `elapsed_ms,value` points of a replay CSV file, sorted by
+/// the elapsed time. The first row is skipped when it is a header row
+/// and blank lines are ignored
+fn parse_replay(contents: &str) -> Result<Arc<[(u64, f32)]>, String> {
+    let mut points: Vec<(u64, f32)> = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let point = parse_replay_row(line);
+
+        // Header row is the only row allowed to not contain numbers
+        if index == 0 && point.is_none() && !line.starts_with(|c: char| c.is_ascii_digit()) {
+            continue;
+        }
+
+        let point = point.ok_or_else(|| {
+            format!(
+                "line {} must be an elapsed_ms,value row: {line:?}",
+                index + 1
+            )
+        })?;
+
+        points.push(point);
+    }
+
+    if points.is_empty() {
+        return Err("no values".to_string());
+    }
+
+    points.sort_by_key(|(elapsed, _)| *elapsed);
+
+    Ok(points.into())
+}
+
+/// Parses a single `elapsed_ms,value` row
+fn parse_replay_row(line: &str) -> Option<(u64, f32)> {
+    let mut columns = line.split(',');
+    let elapsed = columns.next()?.trim().parse().ok()?;
+    let value = columns.next()?.trim().parse().ok()?;
+
+    // Rows must have exactly two columns
+    if columns.next().is_some() {
+        return None;
+    }
+
+    Some((elapsed, value))
+}
+
+struct SineSource {
+    start: Instant,
+    min: f32,
+    max: f32,
+    period_ms: u64,
+    phase_ms: u64,
+}
+
+impl SineSource {
+    /// Value of the curve `elapsed_ms` after the simulation started
+    fn value_at(&self, elapsed_ms: u64) -> f32 {
+        let elapsed = elapsed_ms + self.phase_ms;
+        let progress = (elapsed % self.period_ms) as f32 / self.period_ms as f32;
+
+        let amplitude = (self.max - self.min) / 2.0;
+        let midpoint = self.min + amplitude;
+
+        midpoint + amplitude * (progress * TAU).sin()
+    }
+}
+
+impl SensorValueSource for SineSource {
+    fn next_value(&mut self) -> Option<f32> {
+        Some(self.value_at(self.start.elapsed().as_millis() as u64))
+    }
+}
+
+struct RandomWalkSource {
+    value: f32,
+    step: f32,
+    min: f32,
+    max: f32,
+    rng: XorShift,
+    /// Whether the first value has been provided, the
+    /// walk begins at the start value
+    started: bool,
+}
+
+impl SensorValueSource for RandomWalkSource {
+    fn next_value(&mut self) -> Option<f32> {
+        if self.started {
+            // Random value between -1.0 and 1.0
+            let direction = self.rng.next_f32() * 2.0 - 1.0;
+            self.value = (self.value + direction * self.step).clamp(self.min, self.max);
+        }
+
+        self.started = true;
+        Some(self.value)
+    }
+}
+
+struct ReplaySource {
+    start: Instant,
+    points: Arc<[(u64, f32)]>,
+    repeat: bool,
+}
+
+impl ReplaySource {
+    /// Value of the replay `elapsed_ms` after the simulation started
+    fn value_at(&self, elapsed_ms: u64) -> Option<f32> {
+        let duration = self.points.last().map(|(elapsed, _)| *elapsed)?;
+
+        let mut elapsed = elapsed_ms;
+        if self.repeat && duration > 0 {
+            elapsed %= duration + 1;
+        }
+
+        // Latest point that has been reached
+        let index = self
+            .points
+            .partition_point(|(point_elapsed, _)| *point_elapsed <= elapsed);
+
+        let (_, value) = self.points[index.saturating_sub(1)];
+        Some(value)
+    }
+}
+
+impl SensorValueSource for ReplaySource {
+    fn next_value(&mut self) -> Option<f32> {
+        self.value_at(self.start.elapsed().as_millis() as u64)
+    }
+}
+
+/// Small xorshift random number generator, deterministic for a seed
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // State must never be zero
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Random value between 0.0 and 1.0
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        RandomWalkSource, ReplaySource, SineSource, XorShift, from_fixture, load, parse_replay,
+    };
+    use crate::backend::{
+        BackendHardware, BackendSensor, HardwareBackend, memory::SensorValueSource,
+    };
+    use lhm_shared::{
+        ComputerOptions, HardwareType, SensorType,
+        fixture::{Curve, Fixture, FixtureHardware, FixtureSensor},
+    };
+    use std::{path::Path, time::Instant};
+
+    fn sine(min: f32, max: f32, period_ms: u64, phase_ms: u64) -> SineSource {
+        SineSource {
+            start: Instant::now(),
+            min,
+            max,
+            period_ms,
+            phase_ms,
+        }
+    }
+
+    fn random_walk(seed: u64) -> RandomWalkSource {
+        RandomWalkSource {
+            value: 50.0,
+            step: 2.0,
+            min: 45.0,
+            max: 55.0,
+            rng: XorShift::new(seed),
+            started: false,
+        }
+    }
+
+    fn replay(points: &[(u64, f32)], repeat: bool) -> ReplaySource {
+        ReplaySource {
+            start: Instant::now(),
+            points: points.into(),
+            repeat,
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {expected} got {actual}"
+        );
+    }
+
+    /// Fixture with a single sensor using the provided curve
+    fn single_sensor(curve: Curve) -> Fixture {
+        Fixture {
+            seed: Some(1),
+            hardware: vec![FixtureHardware {
+                identifier: "/intelcpu/0".to_string(),
+                name: "CPU".to_string(),
+                ty: HardwareType::Cpu,
+                children: Vec::new(),
+                sensors: vec![FixtureSensor {
+                    identifier: "/intelcpu/0/temperature/0".to_string(),
+                    name: "CPU Package".to_string(),
+                    ty: SensorType::Temperature,
+                    curve,
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_sine_curve() {
+        let source = sine(40.0, 80.0, 1000, 0);
+
+        assert_close(source.value_at(0), 60.0);
+        assert_close(source.value_at(250), 80.0);
+        assert_close(source.value_at(500), 60.0);
+        assert_close(source.value_at(750), 40.0);
+
+        // Repeats every period
+        assert_close(source.value_at(1250), source.value_at(250));
+
+        for elapsed in (0..5000).step_by(37) {
+            let value = source.value_at(elapsed);
+            assert!((40.0..=80.0).contains(&value), "{value} out of range");
+        }
+
+        // Phase shifts the curve
+        let shifted = sine(40.0, 80.0, 1000, 250);
+        assert_close(shifted.value_at(0), 80.0);
+    }
+
+    #[test]
+    fn test_random_walk_deterministic() {
+        let mut first = random_walk(42);
+        let mut second = random_walk(42);
+        let mut other = random_walk(43);
+
+        let first: Vec<f32> = (0..100).filter_map(|_| first.next_value()).collect();
+        let second: Vec<f32> = (0..100).filter_map(|_| second.next_value()).collect();
+        let other: Vec<f32> = (0..100).filter_map(|_| other.next_value()).collect();
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+
+        // Walk begins at the start value
+        assert_eq!(first[0], 50.0);
+    }
+
+    #[test]
+    fn test_random_walk_range() {
+        let mut source = random_walk(7);
+        let mut previous = source.next_value().unwrap();
+
+        for _ in 0..1000 {
+            let value = source.next_value().unwrap();
+            assert!((45.0..=55.0).contains(&value), "{value} out of range");
+            assert!((value - previous).abs() <= 2.0, "step too large");
+            previous = value;
+        }
+    }
+
+    #[test]
+    fn test_xorshift_range() {
+        // Zero seed is replaced as it would only produce zeros
+        let mut rng = XorShift::new(0);
+
+        for _ in 0..1000 {
+            let value = rng.next_f32();
+            assert!((0.0..1.0).contains(&value), "{value} out of range");
+        }
+    }
+
+    #[test]
+    fn test_replay_curve() {
+        let source = replay(&[(0, 1.0), (1000, 2.0), (2000, 3.0)], false);
+
+        assert_eq!(source.value_at(0), Some(1.0));
+        assert_eq!(source.value_at(999), Some(1.0));
+        assert_eq!(source.value_at(1000), Some(2.0));
+        assert_eq!(source.value_at(1500), Some(2.0));
+
+        // Last value is held once the replay finishes
+        assert_eq!(source.value_at(10_000), Some(3.0));
+    }
+
+    #[test]
+    fn test_replay_curve_repeat() {
+        let source = replay(&[(0, 1.0), (1000, 2.0), (2000, 3.0)], true);
+
+        assert_eq!(source.value_at(2000), Some(3.0));
+        assert_eq!(source.value_at(2001), Some(1.0));
+        assert_eq!(source.value_at(3001), Some(2.0));
+    }
+
+    #[test]
+    fn test_replay_before_first_point() {
+        let source = replay(&[(500, 1.0), (1000, 2.0)], false);
+        assert_eq!(source.value_at(0), Some(1.0));
+    }
+
+    #[test]
+    fn test_parse_replay() {
+        let points = parse_replay("elapsed_ms,value\n1000, 41.8\n\n0,35.2\n").unwrap();
+        assert_eq!(&points[..], &[(0, 35.2), (1000, 41.8)]);
+
+        // Header row is optional
+        let points = parse_replay("0,1.5\n").unwrap();
+        assert_eq!(&points[..], &[(0, 1.5)]);
+    }
+
+    #[test]
+    fn test_parse_replay_errors() {
+        // Empty files and files with only a header have no values
+        assert_eq!(parse_replay("").unwrap_err(), "no values");
+        assert_eq!(parse_replay("elapsed_ms,value\n").unwrap_err(), "no values");
+
+        // Missing and extra columns
+        assert!(
+            parse_replay("0,1.0\n1000\n")
+                .unwrap_err()
+                .contains("line 2")
+        );
+        assert!(
+            parse_replay("0,1.0\n1000,2.0,3.0\n")
+                .unwrap_err()
+                .contains("line 2")
+        );
+
+        // Non-numeric values
+        assert!(
+            parse_replay("0,1.0\n1000,hot\n")
+                .unwrap_err()
+                .contains("line 2")
+        );
+        assert!(
+            parse_replay("0,1.0\n-5,2.0\n")
+                .unwrap_err()
+                .contains("line 2")
+        );
+
+        // First row that starts like a value is not treated as a header
+        assert!(
+            parse_replay("0,hot\n1000,2.0\n")
+                .unwrap_err()
+                .contains("line 1")
+        );
+    }
+
+    #[test]
+    fn test_replay_file_errors() {
+        let directory = std::env::temp_dir().join(format!("lhm-replay-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("bad.csv"), "elapsed_ms,value\n0,cold\n").unwrap();
+
+        let curve = |path: &str| Curve::Replay {
+            path: path.into(),
+            repeat: true,
+        };
+
+        let err = from_fixture(single_sensor(curve("bad.csv")), &directory)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"));
+
+        let err = from_fixture(single_sensor(curve("missing.csv")), &directory)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+        _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_empty_samples_rejected() {
+        let fixture = single_sensor(Curve::Samples {
+            samples: Vec::new(),
+            repeat: true,
+        });
+
+        let err = from_fixture(fixture, Path::new("")).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_load_example_fixture() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/fixtures/desktop.toml");
+        let mut backend = load(&path).unwrap();
+        backend.set_options(&ComputerOptions::all());
+
+        let hardware = backend.hardware();
+        assert!(!hardware.is_empty());
+
+        // Every sensor starts with a value
+        for hardware in &hardware {
+            for sensor in hardware.sensors() {
+                assert!(
+                    !sensor.value().is_nan(),
+                    "{} has no value",
+                    sensor.identifier()
+                );
+            }
+        }
+    }
+}
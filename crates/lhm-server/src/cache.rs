@@ -1,109 +1,328 @@
+use crate::backend::{BackendHardware, BackendSensor};
 use lhm_shared::{HardwareType, SensorType};
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Instant};
+
+/// Sensor type for a hardware type
+type SensorOf<H> = <H as BackendHardware>::Sensor;
 
 /// Cache holding the currently loaded hardware and sensors
 /// in a fashion that is easily queryable with all sensors
 /// and children resolved
-#[derive(Default)]
-pub struct HardwareCache {
-    /// Flat collection of hardware (Includes all sub-hardware)
-    hardware: Vec<HardwareEntry>,
+///
+/// Entries are reconciled by identifier when the hardware is reloaded so
+/// the cache index of an entry does not change while it remains loaded
+pub struct HardwareCache<H: BackendHardware> {
+    /// Flat collection of hardware (Includes all sub-hardware), removed
+    /// hardware is left as [None] to keep the indexes of other entries
+    hardware: Vec<Option<HardwareEntry<H>>>,
 
-    /// Collection of all sensors for all hardware
-    sensors: Vec<SensorEntry>,
+    /// Collection of all sensors for all hardware, removed sensors
+    /// are left as [None] to keep the indexes of other entries
+    sensors: Vec<Option<SensorEntry<SensorOf<H>>>>,
 
     /// Index cache for looking up hardware by ID
     hardware_lookup: HashMap<String, usize>,
 
     /// Index cache for looking up sensors by ID
     sensor_lookup: HashMap<String, usize>,
+
+    /// Incremented each time entries are added to or removed from the
+    /// cache, indexes obtained during the same generation remain valid
+    generation: u64,
+}
+
+impl<H: BackendHardware> Default for HardwareCache<H> {
+    fn default() -> Self {
+        Self {
+            hardware: Vec::new(),
+            sensors: Vec::new(),
+            hardware_lookup: HashMap::new(),
+            sensor_lookup: HashMap::new(),
+            generation: 0,
+        }
+    }
 }
 
 /// Hardware entry within the cache
-pub struct HardwareEntry {
+pub struct HardwareEntry<H> {
+    /// Unique identifier of the hardware
+    identifier: String,
     /// Index of the parent entry within the cache
     /// if the entry is sub-hardware
     parent_index: Option<usize>,
     /// The hardware item itself
-    hardware: lhm_sys::Hardware,
+    hardware: H,
+    /// Last time the hardware was updated through the cache
+    updated_at: Option<Instant>,
 }
 
 /// Sensor entry within the cache
-pub struct SensorEntry {
+pub struct SensorEntry<S> {
+    /// Unique identifier of the sensor
+    identifier: String,
     /// Index of the parent [HardwareEntry] in the cache
     parent_index: usize,
     /// The sensor item itself
-    sensor: lhm_sys::Sensor,
+    sensor: S,
+    /// Last time the sensor was updated directly through the cache
+    updated_at: Option<Instant>,
 }
 
-impl HardwareCache {
-    pub fn init(&mut self, hardware: Vec<lhm_sys::Hardware>) {
-        self.clear();
-        self.populate(hardware, None);
+/// Entries added to and removed from the cache by [HardwareCache::reconcile]
+#[derive(Default)]
+pub struct CacheChanges {
+    /// Indexes of the added hardware, parents come before their children
+    pub added_hardware: Vec<usize>,
+    /// Index and identifier of the removed hardware
+    pub removed_hardware: Vec<(usize, String)>,
+    /// Indexes of the added sensors
+    pub added_sensors: Vec<usize>,
+    /// Index and identifier of the removed sensors
+    pub removed_sensors: Vec<(usize, String)>,
+}
+
+impl CacheChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added_hardware.is_empty()
+            && self.removed_hardware.is_empty()
+            && self.added_sensors.is_empty()
+            && self.removed_sensors.is_empty()
     }
+}
+
+/// State tracked while reconciling the cache with newly loaded hardware
+struct Reconcile {
+    /// Whether each existing hardware entry is still present
+    seen_hardware: Vec<bool>,
+    /// Whether each existing sensor entry is still present
+    seen_sensors: Vec<bool>,
+    /// Changes made to the cache
+    changes: CacheChanges,
+}
+
+impl<H: BackendHardware> HardwareCache<H> {
+    /// Loads the provided hardware into the cache
+    ///
+    /// Existing entries are matched by identifier and keep their index,
+    /// entries that are no longer present are removed leaving their index
+    /// unused and new entries are appended
+    pub fn reconcile(&mut self, hardware: Vec<H>) -> CacheChanges {
+        let mut state = Reconcile {
+            seen_hardware: vec![false; self.hardware.len()],
+            seen_sensors: vec![false; self.sensors.len()],
+            changes: CacheChanges::default(),
+        };
+
+        self.populate(hardware, None, &mut state);
 
-    fn populate(&mut self, hardware: Vec<lhm_sys::Hardware>, parent_index: Option<usize>) {
+        let mut changes = state.changes;
+
+        for (index, (entry, seen)) in self
+            .hardware
+            .iter_mut()
+            .zip(&state.seen_hardware)
+            .enumerate()
+        {
+            if !seen && let Some(entry) = entry.take() {
+                self.hardware_lookup.remove(&entry.identifier);
+                changes.removed_hardware.push((index, entry.identifier));
+            }
+        }
+
+        for (index, (entry, seen)) in self.sensors.iter_mut().zip(&state.seen_sensors).enumerate() {
+            if !seen && let Some(entry) = entry.take() {
+                self.sensor_lookup.remove(&entry.identifier);
+                changes.removed_sensors.push((index, entry.identifier));
+            }
+        }
+
+        if !changes.is_empty() {
+            self.generation += 1;
+        }
+
+        changes
+    }
+
+    fn populate(&mut self, hardware: Vec<H>, parent_index: Option<usize>, state: &mut Reconcile) {
         for item in hardware {
             let identifier = item.identifier();
-            let children = item.get_children();
+            let children = item.children();
             let sensors = item.sensors();
-            let hardware_index = self.hardware.len();
 
-            self.hardware.push(HardwareEntry {
-                parent_index,
-                hardware: item,
-            });
-            self.hardware_lookup.insert(identifier, hardware_index);
+            let hardware_index = match self.hardware_lookup.get(&identifier) {
+                // Replace the existing entry keeping its index
+                Some(&index) => {
+                    if let Some(seen) = state.seen_hardware.get_mut(index) {
+                        *seen = true;
+                    }
+
+                    if let Some(entry) = &mut self.hardware[index] {
+                        entry.parent_index = parent_index;
+                        entry.hardware = item;
+                    }
+
+                    index
+                }
+                None => {
+                    let index = self.hardware.len();
+                    self.hardware.push(Some(HardwareEntry {
+                        identifier: identifier.clone(),
+                        parent_index,
+                        hardware: item,
+                        updated_at: None,
+                    }));
+                    self.hardware_lookup.insert(identifier, index);
+                    state.changes.added_hardware.push(index);
+                    index
+                }
+            };
 
             // Populate the sensors
             for sensor in sensors {
                 let identifier = sensor.identifier();
-                let sensor_index = self.sensors.len();
 
-                self.sensors.push(SensorEntry {
-                    parent_index: hardware_index,
-                    sensor,
-                });
-                self.sensor_lookup.insert(identifier, sensor_index);
+                match self.sensor_lookup.get(&identifier) {
+                    // Replace the existing entry keeping its index
+                    Some(&index) => {
+                        if let Some(seen) = state.seen_sensors.get_mut(index) {
+                            *seen = true;
+                        }
+
+                        if let Some(entry) = &mut self.sensors[index] {
+                            entry.parent_index = hardware_index;
+                            entry.sensor = sensor;
+                        }
+                    }
+                    None => {
+                        let sensor_index = self.sensors.len();
+                        self.sensors.push(Some(SensorEntry {
+                            identifier: identifier.clone(),
+                            parent_index: hardware_index,
+                            sensor,
+                            updated_at: None,
+                        }));
+                        self.sensor_lookup.insert(identifier, sensor_index);
+                        state.changes.added_sensors.push(sensor_index);
+                    }
+                }
             }
 
             // Populate the cache with children
-            self.populate(children, Some(hardware_index));
+            self.populate(children, Some(hardware_index), state);
         }
     }
 
-    pub fn get_hardware_by_id(&self, identifier: &str) -> Option<(usize, &lhm_sys::Hardware)> {
+    /// Current generation of the cache, changes each time entries
+    /// are added or removed
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Advances the generation for sensors added or removed outside
+    /// of the cache, such as virtual sensors
+    pub fn bump_generation(&mut self) {
+        self.generation += 1;
+    }
+
+    pub fn get_hardware_by_id(&self, identifier: &str) -> Option<(usize, &H)> {
         let index = *self.hardware_lookup.get(identifier)?;
-        let hardware = &self.hardware[index];
+        let hardware = self.hardware[index].as_ref()?;
         Some((index, &hardware.hardware))
     }
 
-    pub fn get_sensor_by_id(&self, identifier: &str) -> Option<(usize, &lhm_sys::Sensor)> {
+    pub fn get_hardware_by_idx(&self, index: usize) -> Option<&H> {
+        self.hardware_entry(index).map(|entry| &entry.hardware)
+    }
+
+    /// Get the cache index of the parent of a hardware item, [None]
+    /// for top level hardware
+    pub fn get_hardware_parent_index(&self, index: usize) -> Option<usize> {
+        self.hardware_entry(index)?.parent_index
+    }
+
+    pub fn get_sensor_by_id(&self, identifier: &str) -> Option<(usize, &SensorOf<H>)> {
         let index = *self.sensor_lookup.get(identifier)?;
-        let sensor = &self.sensors[index];
+        let sensor = self.sensors[index].as_ref()?;
         Some((index, &sensor.sensor))
     }
 
-    pub fn get_hardware_by_id_mut(&mut self, identifier: &str) -> Option<&mut lhm_sys::Hardware> {
-        let index = *self.hardware_lookup.get(identifier)?;
-        let hardware = &mut self.hardware[index];
-        Some(&mut hardware.hardware)
+    /// Get the cache index of the hardware that a sensor belongs to
+    pub fn get_sensor_parent_index(&self, identifier: &str) -> Option<usize> {
+        let index = *self.sensor_lookup.get(identifier)?;
+        Some(self.sensors[index].as_ref()?.parent_index)
     }
 
-    pub fn get_hardware_by_idx_mut(&mut self, index: usize) -> Option<&mut lhm_sys::Hardware> {
-        self.hardware
-            .get_mut(index)
-            .map(|hardware| &mut hardware.hardware)
+    pub fn get_sensor_index(&self, identifier: &str) -> Option<usize> {
+        self.sensor_lookup.get(identifier).copied()
     }
 
-    pub fn get_sensor_by_id_mut(&mut self, identifier: &str) -> Option<&mut lhm_sys::Sensor> {
-        let index = *self.sensor_lookup.get(identifier)?;
-        let sensor = &mut self.sensors[index];
-        Some(&mut sensor.sensor)
+    /// Get the last time a sensor was updated, either directly or
+    /// through the hardware it belongs to
+    pub fn get_sensor_updated_at(&self, index: usize) -> Option<Instant> {
+        let sensor = self.sensor_entry(index)?;
+        let parent_updated_at = self
+            .hardware_entry(sensor.parent_index)
+            .and_then(|parent| parent.updated_at);
+        sensor.updated_at.max(parent_updated_at)
+    }
+
+    /// Updates the sensor at the provided index, recording the time
+    /// of the update
+    pub fn update_sensor_by_idx(&mut self, index: usize) {
+        if let Some(Some(entry)) = self.sensors.get_mut(index) {
+            entry.sensor.update();
+            entry.updated_at = Some(Instant::now());
+        }
+    }
+
+    /// Updates the hardware at the provided index, recording the time
+    /// of the update
+    pub fn update_hardware_by_idx(&mut self, index: usize) {
+        if let Some(Some(entry)) = self.hardware.get_mut(index) {
+            entry.hardware.update();
+            entry.updated_at = Some(Instant::now());
+        }
+    }
+
+    /// Records all the hardware as updated now, used after the hardware
+    /// has been updated outside of the cache
+    pub fn mark_all_updated(&mut self) {
+        let now = Instant::now();
+        for entry in self.hardware.iter_mut().flatten() {
+            entry.updated_at = Some(now);
+        }
+    }
+
+    /// Iterator over the index, type and last update time of
+    /// all the hardware
+    pub fn hardware_updates_iter(
+        &self,
+    ) -> impl Iterator<Item = (usize, HardwareType, Option<Instant>)> + '_ {
+        self.hardware_entries()
+            .map(|(index, entry)| (index, entry.hardware.hardware_type(), entry.updated_at))
     }
 
-    pub fn get_sensor_by_idx_mut(&mut self, index: usize) -> Option<&mut lhm_sys::Sensor> {
-        self.sensors.get_mut(index).map(|sensor| &mut sensor.sensor)
+    pub fn is_empty(&self) -> bool {
+        self.hardware_lookup.is_empty()
+    }
+
+    pub fn get_sensor_by_idx(&self, index: usize) -> Option<&SensorOf<H>> {
+        self.sensor_entry(index).map(|sensor| &sensor.sensor)
+    }
+
+    pub fn get_sensor_by_idx_mut(&mut self, index: usize) -> Option<&mut SensorOf<H>> {
+        self.sensors
+            .get_mut(index)?
+            .as_mut()
+            .map(|sensor| &mut sensor.sensor)
+    }
+
+    /// Get the cache indexes of the sensors belonging to a hardware item,
+    /// or all sensors when no hardware is specified
+    pub fn query_sensor_indexes(&self, parent_index: Option<usize>) -> Vec<usize> {
+        self.query_sensors(parent_index, None)
+            .map(|(index, _)| index)
+            .collect()
     }
 
     /// Creates an iterator that will iterate a filtered subset of the hardware
@@ -112,23 +331,16 @@ impl HardwareCache {
         &self,
         parent_index: Option<Option<usize>>,
         ty: Option<HardwareType>,
-    ) -> impl Iterator<Item = (usize, &lhm_sys::Hardware)> + '_ {
-        let ty_value: Option<i32> = ty.map(|value| value.into());
-
-        self.hardware
-            .iter()
-            .enumerate()
+    ) -> impl Iterator<Item = (usize, &H)> + '_ {
+        self.hardware_entries()
             .filter(move |(_, hardware)| {
                 // Filter by type
-                if ty_value
-                    .as_ref()
-                    .is_some_and(|ty_value| hardware.hardware.get_type().ne(ty_value))
-                {
+                if ty.is_some_and(|ty| hardware.hardware.hardware_type().ne(&ty)) {
                     return false;
                 }
 
                 // Filter by parent
-                if parent_index.is_some_and(|parent_index| parent_index == hardware.parent_index) {
+                if parent_index.is_some_and(|parent_index| parent_index != hardware.parent_index) {
                     return false;
                 }
 
@@ -141,18 +353,14 @@ impl HardwareCache {
         &self,
         parent_index: Option<usize>,
         ty: Option<SensorType>,
-    ) -> impl Iterator<Item = (usize, &lhm_sys::Sensor)> + '_ {
-        let ty_value: Option<i32> = ty.map(|value| value.into());
-
+    ) -> impl Iterator<Item = (usize, &SensorOf<H>)> + '_ {
         self.sensors
             .iter()
             .enumerate()
+            .filter_map(|(index, entry)| Some((index, entry.as_ref()?)))
             .filter(move |(_, sensor)| {
                 // Filter by type
-                if ty_value
-                    .as_ref()
-                    .is_some_and(|ty_value| sensor.sensor.get_type().ne(ty_value))
-                {
+                if ty.is_some_and(|ty| sensor.sensor.sensor_type().ne(&ty)) {
                     return false;
                 }
 
@@ -168,11 +376,164 @@ impl HardwareCache {
             .map(|(index, entry)| (index, &entry.sensor))
     }
 
-    /// Empty the cache
-    pub fn clear(&mut self) {
-        self.hardware.clear();
-        self.sensors.clear();
-        self.hardware_lookup.clear();
-        self.sensor_lookup.clear();
+    /// Iterator over the index and entry of all the loaded hardware
+    fn hardware_entries(&self) -> impl Iterator<Item = (usize, &HardwareEntry<H>)> + '_ {
+        self.hardware
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| Some((index, entry.as_ref()?)))
+    }
+
+    fn hardware_entry(&self, index: usize) -> Option<&HardwareEntry<H>> {
+        self.hardware.get(index)?.as_ref()
+    }
+
+    fn sensor_entry(&self, index: usize) -> Option<&SensorEntry<SensorOf<H>>> {
+        self.sensors.get(index)?.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HardwareCache;
+    use crate::backend::{
+        BackendHardware, BackendSensor,
+        memory::{MemoryHardware, MemorySensor},
+    };
+    use lhm_shared::{HardwareType, SensorType};
+
+    fn hardware(identifier: &str, ty: HardwareType, sensors: &[&str]) -> MemoryHardware {
+        let sensors = sensors
+            .iter()
+            .map(|name| {
+                MemorySensor::new(
+                    format!("{identifier}/{name}"),
+                    *name,
+                    SensorType::Temperature,
+                    40.0,
+                )
+            })
+            .collect();
+
+        MemoryHardware::new(identifier, identifier, ty, Vec::new(), sensors)
+    }
+
+    fn cpu() -> MemoryHardware {
+        hardware(
+            "/intelcpu/0",
+            HardwareType::Cpu,
+            &["temperature/0", "temperature/1"],
+        )
+    }
+
+    fn gpu() -> MemoryHardware {
+        hardware("/gpu-nvidia/0", HardwareType::GpuNvidia, &["temperature/0"])
+    }
+
+    fn storage() -> MemoryHardware {
+        hardware("/nvme/0", HardwareType::Storage, &["temperature/0"])
+    }
+
+    fn sensor_index(cache: &HardwareCache<MemoryHardware>, identifier: &str) -> usize {
+        cache.get_sensor_index(identifier).unwrap()
+    }
+
+    fn hardware_index(cache: &HardwareCache<MemoryHardware>, identifier: &str) -> usize {
+        cache.get_hardware_by_id(identifier).unwrap().0
+    }
+
+    #[test]
+    fn test_reconcile_unchanged() {
+        let mut cache = HardwareCache::default();
+        let changes = cache.reconcile(vec![cpu(), gpu()]);
+
+        assert_eq!(changes.added_hardware, [0, 1]);
+        assert_eq!(changes.added_sensors, [0, 1, 2]);
+        assert_eq!(cache.generation(), 1);
+
+        // Reloading the same hardware keeps the indexes and generation
+        let changes = cache.reconcile(vec![cpu(), gpu()]);
+        assert!(changes.is_empty());
+        assert_eq!(cache.generation(), 1);
+        assert_eq!(hardware_index(&cache, "/gpu-nvidia/0"), 1);
+        assert_eq!(sensor_index(&cache, "/gpu-nvidia/0/temperature/0"), 2);
+    }
+
+    #[test]
+    fn test_reconcile_remove() {
+        let mut cache = HardwareCache::default();
+        cache.reconcile(vec![cpu(), gpu()]);
+
+        let changes = cache.reconcile(vec![gpu()]);
+        assert_eq!(cache.generation(), 2);
+        assert_eq!(changes.removed_hardware, [(0, "/intelcpu/0".to_string())]);
+        assert_eq!(
+            changes.removed_sensors,
+            [
+                (0, "/intelcpu/0/temperature/0".to_string()),
+                (1, "/intelcpu/0/temperature/1".to_string())
+            ]
+        );
+
+        // Stale indexes are rejected
+        assert!(cache.get_hardware_by_idx(0).is_none());
+        assert!(cache.get_sensor_by_idx(0).is_none());
+        assert!(cache.get_sensor_by_idx(1).is_none());
+        assert!(
+            cache
+                .get_sensor_by_id("/intelcpu/0/temperature/0")
+                .is_none()
+        );
+
+        // Remaining entries keep their indexes
+        assert_eq!(hardware_index(&cache, "/gpu-nvidia/0"), 1);
+        assert_eq!(sensor_index(&cache, "/gpu-nvidia/0/temperature/0"), 2);
+        assert_eq!(cache.query_sensors(None, None).count(), 1);
+    }
+
+    #[test]
+    fn test_reconcile_readd() {
+        let mut cache = HardwareCache::default();
+        cache.reconcile(vec![cpu(), gpu()]);
+        cache.reconcile(vec![gpu()]);
+
+        let changes = cache.reconcile(vec![cpu(), gpu()]);
+        assert_eq!(cache.generation(), 3);
+        assert!(changes.removed_hardware.is_empty());
+
+        // Re-added entries are appended
+        assert_eq!(changes.added_hardware, [2]);
+        assert_eq!(changes.added_sensors, [3, 4]);
+        assert_eq!(hardware_index(&cache, "/intelcpu/0"), 2);
+        assert_eq!(hardware_index(&cache, "/gpu-nvidia/0"), 1);
+        assert_eq!(sensor_index(&cache, "/gpu-nvidia/0/temperature/0"), 2);
+        assert_eq!(cache.query_sensor_indexes(Some(2)), [3, 4]);
+        assert!(cache.query_sensor_indexes(Some(0)).is_empty());
+    }
+
+    #[test]
+    fn test_reconcile_removed_index_empty() {
+        let mut cache = HardwareCache::default();
+        cache.reconcile(vec![cpu(), gpu()]);
+        cache.reconcile(vec![cpu()]);
+
+        // New hardware is appended rather than taking the removed indexes
+        let changes = cache.reconcile(vec![cpu(), storage()]);
+        assert_eq!(cache.generation(), 3);
+        assert_eq!(changes.added_hardware, [2]);
+        assert_eq!(changes.added_sensors, [3]);
+
+        // Stale indexes of the removed hardware do not refer to the new hardware
+        assert!(cache.get_hardware_by_idx(1).is_none());
+        assert!(cache.get_sensor_by_idx(2).is_none());
+
+        let hardware = cache.get_hardware_by_idx(2).unwrap();
+        assert_eq!(hardware.identifier(), "/nvme/0");
+        let sensor = cache.get_sensor_by_idx(3).unwrap();
+        assert_eq!(sensor.identifier(), "/nvme/0/temperature/0");
+        assert_eq!(
+            cache.get_sensor_parent_index("/nvme/0/temperature/0"),
+            Some(2)
+        );
     }
 }
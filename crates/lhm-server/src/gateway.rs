@@ -0,0 +1,417 @@
+//! HTTP gateway providing JSON access to the hardware and sensors for
+//! clients that are unable to use the framed protocol
+//!
+//! Each endpoint maps onto a single [PipeRequest]:
+//!
+//! | Endpoint                           | Request                        |
+//! |------------------------------------|--------------------------------|
+//! | `GET /hardware?type=`              | [PipeRequest::QueryHardware]   |
+//! | `GET /hardware/{id}`               | [PipeRequest::GetHardwareById] |
+//! | `GET /hardware/{id}/sensors?type=` | [PipeRequest::QuerySensors]    |
+//! | `GET /sensors?type=`               | [PipeRequest::QuerySensors]    |
+//! | `GET /sensors/{id}`                | [PipeRequest::GetSensorById]   |
+//! | `POST /update`                     | [PipeRequest::UpdateAll]       |
+//!
+//! Identifiers contain slashes so must be percent encoded within the path
+//! (e.g `/sensors/%2Fintelcpu%2F0%2Ftemperature%2F0`)
+//!
+//! With the `websocket` feature the gateway also serves a WebSocket
+//! endpoint at `/ws` (See [crate::websocket])
+
+use crate::{
+    actor::{ActorRequest, ComputerActorHandle, ConnectionId},
+    handle_request, set_endpoint_options,
+};
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State, rejection::QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+#[cfg(feature = "websocket")]
+use lhm_shared::Permission;
+use lhm_shared::{ComputerOptions, ErrorCode, HardwareType, PipeRequest, PipeResponse, SensorType};
+use serde::Deserialize;
+use std::net::{Ipv4Addr, SocketAddr};
+#[cfg(feature = "websocket")]
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+/// Default port the gateway is served on
+const DEFAULT_PORT: u16 = 9185;
+
+/// Configuration for the HTTP gateway
+#[derive(Debug, Clone)]
+pub struct GatewayConfig {
+    /// Port to serve the gateway on, the gateway is only served
+    /// on the loopback address
+    pub port: u16,
+    /// Hardware available through the gateway, defaults to all the hardware
+    pub options: ComputerOptions,
+    /// Permission granted to WebSocket clients. Any web page is able to
+    /// connect to the loopback address so this defaults to [Permission::Read]
+    #[cfg(feature = "websocket")]
+    pub websocket_permission: Permission,
+    /// Maximum number of active subscriptions for each WebSocket client
+    #[cfg(feature = "websocket")]
+    pub websocket_max_subscriptions: usize,
+    /// Origins of the web pages allowed to connect to the WebSocket endpoint
+    /// (e.g `http://localhost:3000`), web pages are rejected when empty.
+    /// Clients that do not send an origin, such as native applications,
+    /// are always allowed
+    #[cfg(feature = "websocket")]
+    pub websocket_allowed_origins: Vec<String>,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            options: ComputerOptions::all(),
+            #[cfg(feature = "websocket")]
+            websocket_permission: Permission::Read,
+            #[cfg(feature = "websocket")]
+            websocket_max_subscriptions: crate::DEFAULT_MAX_SUBSCRIPTIONS,
+            #[cfg(feature = "websocket")]
+            websocket_allowed_origins: Vec::new(),
+        }
+    }
+}
+
+impl GatewayConfig {
+    /// Address the gateway is served on
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, self.port))
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct GatewayState {
+    /// Connection ID used for requests made through the gateway
+    pub(crate) connection_id: ConnectionId,
+    /// Handle to the computer actor
+    pub(crate) handle: ComputerActorHandle,
+    /// Permission granted to WebSocket clients
+    #[cfg(feature = "websocket")]
+    pub(crate) websocket_permission: Permission,
+    /// Maximum number of active subscriptions for each WebSocket client
+    #[cfg(feature = "websocket")]
+    pub(crate) websocket_max_subscriptions: usize,
+    /// Origins of the web pages allowed to connect to the WebSocket endpoint
+    #[cfg(feature = "websocket")]
+    pub(crate) websocket_allowed_origins: Arc<[String]>,
+}
+
+impl GatewayState {
+    /// Sends a request to the actor, error responses are
+    /// provided as errors
+    async fn request(&self, request: PipeRequest) -> Result<PipeResponse, Response> {
+        let request = ActorRequest::Pipe {
+            connection_id: self.connection_id,
+            request,
+        };
+
+        match handle_request(request, &self.handle)
+            .await
+            .unwrap_or_else(|err| err)
+        {
+            PipeResponse::Error { code, message } => Err(error_response(code, message)),
+            response => Ok(response),
+        }
+    }
+}
+
+/// Query parameters for filtering by hardware type
+#[derive(Deserialize)]
+struct HardwareQuery {
+    #[serde(rename = "type")]
+    ty: Option<HardwareType>,
+}
+
+/// Query parameters for filtering by sensor type
+#[derive(Deserialize)]
+struct SensorQuery {
+    #[serde(rename = "type")]
+    ty: Option<SensorType>,
+}
+
+/// Binds the gateway and enables the hardware available through it,
+/// provides the future serving the gateway until the listener fails
+pub(crate) async fn start_gateway(
+    config: GatewayConfig,
+    connection_id: ConnectionId,
+    handle: ComputerActorHandle,
+) -> std::io::Result<impl Future<Output = std::io::Result<()>>> {
+    let listener = TcpListener::bind(config.address()).await?;
+    set_endpoint_options(connection_id, config.options, &handle).await?;
+
+    let state = GatewayState {
+        connection_id,
+        handle,
+        #[cfg(feature = "websocket")]
+        websocket_permission: config.websocket_permission,
+        #[cfg(feature = "websocket")]
+        websocket_max_subscriptions: config.websocket_max_subscriptions,
+        #[cfg(feature = "websocket")]
+        websocket_allowed_origins: config.websocket_allowed_origins.into(),
+    };
+
+    let app = router(state);
+    Ok(async move { axum::serve(listener, app).await })
+}
+
+/// Creates the router serving the gateway endpoints
+fn router(state: GatewayState) -> Router {
+    let app = Router::new()
+        .route("/hardware", get(query_hardware))
+        .route("/hardware/{id}", get(get_hardware))
+        .route("/hardware/{id}/sensors", get(query_hardware_sensors))
+        .route("/sensors", get(query_sensors))
+        .route("/sensors/{id}", get(get_sensor))
+        .route("/update", post(update_all));
+
+    #[cfg(feature = "websocket")]
+    let app = app.route("/ws", get(crate::websocket::upgrade_websocket));
+
+    app.with_state(state)
+}
+
+async fn query_hardware(
+    State(state): State<GatewayState>,
+    query: Result<Query<HardwareQuery>, QueryRejection>,
+) -> Result<Response, Response> {
+    let Query(query) = query.map_err(invalid_query)?;
+    let request = PipeRequest::QueryHardware {
+        parent_id: None,
+        ty: query.ty,
+    };
+
+    match state.request(request).await? {
+        PipeResponse::Hardwares { hardware } => Ok(Json(hardware).into_response()),
+        _ => Err(unexpected_response()),
+    }
+}
+
+async fn get_hardware(
+    State(state): State<GatewayState>,
+    Path(id): Path<String>,
+) -> Result<Response, Response> {
+    match state.request(PipeRequest::GetHardwareById { id }).await? {
+        PipeResponse::Hardware {
+            hardware: Some(hardware),
+        } => Ok(Json(hardware).into_response()),
+        PipeResponse::Hardware { hardware: None } => Err(error_response(
+            ErrorCode::NotFound,
+            "hardware not found".to_string(),
+        )),
+        _ => Err(unexpected_response()),
+    }
+}
+
+async fn query_hardware_sensors(
+    State(state): State<GatewayState>,
+    Path(id): Path<String>,
+    query: Result<Query<SensorQuery>, QueryRejection>,
+) -> Result<Response, Response> {
+    let Query(query) = query.map_err(invalid_query)?;
+    let request = PipeRequest::QuerySensors {
+        parent_id: Some(id),
+        ty: query.ty,
+    };
+
+    match state.request(request).await? {
+        PipeResponse::Sensors { sensors } => Ok(Json(sensors).into_response()),
+        _ => Err(unexpected_response()),
+    }
+}
+
+async fn query_sensors(
+    State(state): State<GatewayState>,
+    query: Result<Query<SensorQuery>, QueryRejection>,
+) -> Result<Response, Response> {
+    let Query(query) = query.map_err(invalid_query)?;
+    let request = PipeRequest::QuerySensors {
+        parent_id: None,
+        ty: query.ty,
+    };
+
+    match state.request(request).await? {
+        PipeResponse::Sensors { sensors } => Ok(Json(sensors).into_response()),
+        _ => Err(unexpected_response()),
+    }
+}
+
+async fn get_sensor(
+    State(state): State<GatewayState>,
+    Path(id): Path<String>,
+) -> Result<Response, Response> {
+    match state.request(PipeRequest::GetSensorById { id }).await? {
+        PipeResponse::Sensor {
+            sensor: Some(sensor),
+        } => Ok(Json(sensor).into_response()),
+        PipeResponse::Sensor { sensor: None } => Err(error_response(
+            ErrorCode::NotFound,
+            "sensor not found".to_string(),
+        )),
+        _ => Err(unexpected_response()),
+    }
+}
+
+async fn update_all(State(state): State<GatewayState>) -> Result<Response, Response> {
+    match state.request(PipeRequest::UpdateAll).await? {
+        PipeResponse::Success => Ok(StatusCode::NO_CONTENT.into_response()),
+        _ => Err(unexpected_response()),
+    }
+}
+
+/// Creates the response for an error, the body is the JSON
+/// encoded [PipeResponse::Error]
+pub(crate) fn error_response(code: ErrorCode, message: String) -> Response {
+    let status = match code {
+        ErrorCode::NotFound => StatusCode::NOT_FOUND,
+        ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+        ErrorCode::PermissionDenied => StatusCode::FORBIDDEN,
+        ErrorCode::BackendUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        ErrorCode::LimitExceeded => StatusCode::TOO_MANY_REQUESTS,
+        ErrorCode::Internal | ErrorCode::Unknown => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    (status, Json(PipeResponse::Error { code, message })).into_response()
+}
+
+/// Creates the response for query parameters that could not be parsed
+fn invalid_query(rejection: QueryRejection) -> Response {
+    error_response(ErrorCode::InvalidRequest, rejection.body_text())
+}
+
+/// Creates the response for when the actor responds with the wrong response
+fn unexpected_response() -> Response {
+    error_response(ErrorCode::Internal, "unexpected response".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GatewayState, router};
+    use crate::test_endpoint_handle;
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::{Method, Request, StatusCode},
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    async fn app() -> Router {
+        let handle = test_endpoint_handle().await;
+
+        router(GatewayState {
+            connection_id: 0,
+            handle,
+            #[cfg(feature = "websocket")]
+            websocket_permission: lhm_shared::Permission::Read,
+            #[cfg(feature = "websocket")]
+            websocket_max_subscriptions: crate::DEFAULT_MAX_SUBSCRIPTIONS,
+            #[cfg(feature = "websocket")]
+            websocket_allowed_origins: Default::default(),
+        })
+    }
+
+    /// Sends a request to the gateway providing the status
+    /// and JSON body of the response
+    async fn send(app: &Router, method: Method, uri: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = match body.is_empty() {
+            true => Value::Null,
+            false => serde_json::from_slice(&body).unwrap(),
+        };
+
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_query_sensors() {
+        let app = app().await;
+        send(&app, Method::POST, "/update").await;
+
+        let (status, body) = send(&app, Method::GET, "/sensors").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 5);
+
+        let (status, body) = send(&app, Method::GET, "/sensors?type=Load").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["identifier"], "/intelcpu/0/load/0");
+        assert_eq!(body.as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_by_encoded_id() {
+        let app = app().await;
+        send(&app, Method::POST, "/update").await;
+
+        let (status, body) = send(
+            &app,
+            Method::GET,
+            "/sensors/%2Fintelcpu%2F0%2Ftemperature%2F0",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "CPU Package");
+        assert_eq!(body["value"], 55.0);
+
+        let (status, body) = send(&app, Method::GET, "/hardware/%2Fintelcpu%2F0/sensors").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        let app = app().await;
+
+        let (status, body) = send(&app, Method::POST, "/update").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(body, Value::Null);
+
+        let (status, _) = send(&app, Method::GET, "/update").await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_not_found() {
+        let app = app().await;
+
+        let (status, body) = send(&app, Method::GET, "/sensors/%2Fmissing").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            json!({ "type": "Error", "code": "NotFound", "message": "sensor not found" })
+        );
+
+        let (status, body) = send(&app, Method::GET, "/hardware/%2Fmissing").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            json!({ "type": "Error", "code": "NotFound", "message": "hardware not found" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_query() {
+        let app = app().await;
+
+        let (status, body) = send(&app, Method::GET, "/hardware?type=NotAType").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["type"], "Error");
+        assert_eq!(body["code"], "InvalidRequest");
+        assert!(body["message"].is_string());
+    }
+}
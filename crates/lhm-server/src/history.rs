@@ -0,0 +1,270 @@
+use lhm_shared::HistoryPoint;
+use std::collections::{HashMap, VecDeque, vec_deque};
+
+/// Bounded history of the values recorded for each sensor, keyed by
+/// identifier so the history is kept when the cache is reloaded
+pub struct SensorHistory {
+    /// Maximum number of samples kept for each sensor
+    length: usize,
+    /// Samples of `(timestamp_ms, value)` for each sensor
+    sensors: HashMap<String, VecDeque<(u64, f32)>>,
+}
+
+impl SensorHistory {
+    pub fn new(length: usize) -> Self {
+        Self {
+            length,
+            sensors: HashMap::new(),
+        }
+    }
+
+    /// Check if any samples are kept
+    pub fn is_enabled(&self) -> bool {
+        self.length > 0
+    }
+
+    /// Records a value for a sensor, removing the oldest sample once the
+    /// history is full. Sensors without a value (NaN) are not recorded
+    ///
+    /// Timestamps before the latest sample (from the system clock stepping
+    /// backwards) are clamped so the samples always remain in order
+    pub fn record(&mut self, identifier: &str, timestamp_ms: u64, value: f32) {
+        if !self.is_enabled() || value.is_nan() {
+            return;
+        }
+
+        let samples = match self.sensors.get_mut(identifier) {
+            Some(value) => value,
+            None => self
+                .sensors
+                .entry(identifier.to_string())
+                .or_insert_with(|| VecDeque::with_capacity(self.length)),
+        };
+
+        let timestamp_ms = match samples.back() {
+            Some((last_ms, _)) => timestamp_ms.max(*last_ms),
+            None => timestamp_ms,
+        };
+
+        if samples.len() >= self.length {
+            samples.pop_front();
+        }
+
+        samples.push_back((timestamp_ms, value));
+    }
+
+    /// Removes the history of a sensor that is no longer loaded
+    pub fn remove(&mut self, identifier: &str) {
+        self.sensors.remove(identifier);
+    }
+
+    /// Queries the history of a sensor for samples recorded at or after `since`
+    ///
+    /// When `downsample` is true the samples are grouped into at most
+    /// `max_points` buckets of equal duration, otherwise only the latest
+    /// `max_points` samples are provided
+    pub fn query(
+        &self,
+        identifier: &str,
+        since: Option<u64>,
+        max_points: Option<usize>,
+        downsample: bool,
+    ) -> Option<Vec<HistoryPoint>> {
+        let samples = self.sensors.get(identifier)?;
+
+        // Find the first sample within the window
+        let start = match since {
+            Some(since) => samples.partition_point(|(timestamp_ms, _)| *timestamp_ms < since),
+            None => 0,
+        };
+        let samples = samples.range(start..);
+        let count = samples.len();
+
+        let points = match max_points {
+            Some(max_points) if downsample && count > max_points => {
+                downsample_samples(samples, max_points)
+            }
+            Some(max_points) => samples
+                .skip(count.saturating_sub(max_points))
+                .map(|(timestamp_ms, value)| HistoryPoint::raw(*timestamp_ms, *value))
+                .collect(),
+            None => samples
+                .map(|(timestamp_ms, value)| HistoryPoint::raw(*timestamp_ms, *value))
+                .collect(),
+        };
+
+        Some(points)
+    }
+}
+
+/// Groups the samples into `buckets` buckets of equal duration providing the
+/// min, average and max value of each bucket, buckets without any samples
+/// are skipped
+fn downsample_samples(
+    samples: vec_deque::Iter<'_, (u64, f32)>,
+    buckets: usize,
+) -> Vec<HistoryPoint> {
+    let (Some((first, _)), Some((last, _))) = (samples.clone().next(), samples.clone().next_back())
+    else {
+        return Vec::new();
+    };
+    let (first, last) = (*first, *last);
+    let buckets = buckets.max(1);
+
+    let bucket_ms = (last.saturating_sub(first) / buckets as u64).max(1);
+
+    let mut points: Vec<HistoryPoint> = Vec::with_capacity(buckets);
+    let mut bucket: Option<(usize, usize)> = None;
+
+    for &(timestamp_ms, value) in samples {
+        let bucket_index =
+            ((timestamp_ms.saturating_sub(first) / bucket_ms) as usize).min(buckets - 1);
+
+        match (bucket, points.last_mut()) {
+            (Some((index, count)), Some(point)) if index == bucket_index => {
+                point.min = point.min.min(value);
+                point.max = point.max.max(value);
+                point.value += (value - point.value) / (count + 1) as f32;
+                bucket = Some((index, count + 1));
+            }
+            _ => {
+                points.push(HistoryPoint {
+                    timestamp_ms: first + bucket_index as u64 * bucket_ms,
+                    value,
+                    min: value,
+                    max: value,
+                });
+                bucket = Some((bucket_index, 1));
+            }
+        }
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SensorHistory;
+
+    /// Creates a history with samples recorded every 10ms from 0 to 90ms
+    /// with values matching their position
+    fn history() -> SensorHistory {
+        let mut history = SensorHistory::new(10);
+        for index in 0..10 {
+            history.record("/cpu/0/load/0", index * 10, index as f32);
+        }
+        history
+    }
+
+    fn timestamps(history: &SensorHistory, since: Option<u64>, max: Option<usize>) -> Vec<u64> {
+        history
+            .query("/cpu/0/load/0", since, max, false)
+            .unwrap()
+            .iter()
+            .map(|point| point.timestamp_ms)
+            .collect()
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut history = SensorHistory::new(0);
+        history.record("/cpu/0/load/0", 0, 1.0);
+
+        assert!(!history.is_enabled());
+        assert!(history.query("/cpu/0/load/0", None, None, false).is_none());
+    }
+
+    #[test]
+    fn test_nan_not_recorded() {
+        let mut history = SensorHistory::new(4);
+        history.record("/cpu/0/load/0", 0, f32::NAN);
+
+        assert!(history.query("/cpu/0/load/0", None, None, false).is_none());
+    }
+
+    #[test]
+    fn test_ring_capacity() {
+        let mut history = history();
+        history.record("/cpu/0/load/0", 100, 10.0);
+        history.record("/cpu/0/load/0", 110, 11.0);
+
+        // Oldest samples are dropped once the history is full
+        let points = history.query("/cpu/0/load/0", None, None, false).unwrap();
+        assert_eq!(points.len(), 10);
+        assert_eq!(points[0].timestamp_ms, 20);
+        assert_eq!(points[9].timestamp_ms, 110);
+        assert_eq!(points[9].value, 11.0);
+    }
+
+    #[test]
+    fn test_range_selection() {
+        let history = history();
+
+        assert_eq!(timestamps(&history, Some(70), None), vec![70, 80, 90]);
+        assert_eq!(timestamps(&history, Some(65), None), vec![70, 80, 90]);
+        assert!(timestamps(&history, Some(100), None).is_empty());
+
+        // Latest samples are kept when limited without downsampling
+        assert_eq!(timestamps(&history, None, Some(2)), vec![80, 90]);
+        assert_eq!(
+            timestamps(&history, Some(50), Some(10)),
+            vec![50, 60, 70, 80, 90]
+        );
+    }
+
+    #[test]
+    fn test_bucketing() {
+        let history = history();
+        let points = history.query("/cpu/0/load/0", None, Some(3), true).unwrap();
+
+        // 90ms span split into 30ms buckets, the last sample is
+        // grouped into the final bucket
+        assert_eq!(points.len(), 3);
+
+        assert_eq!(points[0].timestamp_ms, 0);
+        assert_eq!((points[0].min, points[0].max), (0.0, 2.0));
+        assert_eq!(points[0].value, 1.0);
+
+        assert_eq!(points[1].timestamp_ms, 30);
+        assert_eq!((points[1].min, points[1].max), (3.0, 5.0));
+        assert_eq!(points[1].value, 4.0);
+
+        assert_eq!(points[2].timestamp_ms, 60);
+        assert_eq!((points[2].min, points[2].max), (6.0, 9.0));
+        assert_eq!(points[2].value, 7.5);
+    }
+
+    #[test]
+    fn test_bucketing_not_needed() {
+        let history = history();
+        let points = history
+            .query("/cpu/0/load/0", Some(80), Some(3), true)
+            .unwrap();
+
+        // Fewer samples than buckets are provided as is
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].min, points[0].max), (8.0, 8.0));
+    }
+
+    #[test]
+    fn test_clock_step_backwards() {
+        let mut history = SensorHistory::new(4);
+        history.record("/cpu/0/load/0", 1_000, 1.0);
+        history.record("/cpu/0/load/0", 500, 2.0);
+        history.record("/cpu/0/load/0", 1_200, 3.0);
+
+        assert_eq!(timestamps(&history, None, None), vec![1_000, 1_000, 1_200]);
+
+        let points = history.query("/cpu/0/load/0", None, Some(2), true).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].value, 1.5);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut history = history();
+        history.remove("/cpu/0/load/0");
+
+        assert!(history.query("/cpu/0/load/0", None, None, false).is_none());
+    }
+}
@@ -1,114 +1,852 @@
-use actor::{ComputerActor, ComputerActorHandle, ComputerActorMessage};
-use interprocess::os::windows::{
-    named_pipe::{
-        PipeListenerOptions, pipe_mode,
-        tokio::{DuplexPipeStream, PipeListenerOptionsExt},
+use actor::{ActorRequest, ComputerActor, ComputerActorHandle, ComputerActorMessage, ConnectionId};
+use backend::HardwareBackend;
+use lhm_shared::{
+    ErrorCode, HardwareType, PipeRequest, PipeResponse, ServerEvent, Transport,
+    codec::{DEFAULT_MAX_FRAME_LENGTH, LHMFrame, LHMFrameCodec},
+    pipe::{PipeFuture, PipeTx, TransportStream},
+};
+use parking_lot::Mutex;
+use permission::{Permission, PermissionPolicy, PermissionRules};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
     },
-    security_descriptor::SecurityDescriptor,
+    time::Duration,
 };
-use lhm_shared::{
-    PIPE_NAME, PipeRequest, PipeResponse,
-    codec::{LHMFrame, LHMFrameCodec},
+use tokio::{
+    spawn,
+    sync::{
+        broadcast::{self, error::RecvError},
+        oneshot,
+    },
+    task::AbortHandle,
+    time::{MissedTickBehavior, interval, timeout},
 };
-use pipe::{PipeFuture, PipeTx};
-use tokio::{spawn, sync::oneshot};
 use tokio_util::{bytes::Bytes, codec::Framed};
-use widestring::U16CString;
+use transport::Listener;
+
+pub mod backend;
+pub mod permission;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "gateway")]
+pub mod gateway;
+
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 mod actor;
+mod alert;
 mod cache;
-mod pipe;
+mod history;
+mod sample;
+mod transport;
+mod virtual_sensor;
+
+/// Maximum time to wait for the actor to handle a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Minimum interval allowed between subscription updates
+const MIN_SUBSCRIPTION_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Default maximum number of active subscriptions for each client
+const DEFAULT_MAX_SUBSCRIPTIONS: usize = 32;
+
+/// Default number of samples kept in the history of each sensor,
+/// 30 minutes of samples at a one second refresh
+const DEFAULT_HISTORY_LENGTH: usize = 1800;
+
+/// Minimum interval allowed between background refreshes
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Configuration for the server
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Transport to listen for clients on
+    pub transport: Transport,
+    /// Maximum length of a frame accepted from a client
+    pub max_frame_length: usize,
+    /// Maximum number of active subscriptions for each client, further
+    /// subscriptions are rejected with [ErrorCode::LimitExceeded]
+    pub max_subscriptions: usize,
+    /// Background refresh of the hardware, when [None] the hardware
+    /// is only updated when requested by clients
+    pub refresh: Option<RefreshConfig>,
+    /// Maximum number of samples kept in the history of each sensor,
+    /// samples are recorded each time a sensor is updated. Zero
+    /// disables the history
+    pub history_length: usize,
+    /// Policy deciding which requests each client is allowed to make,
+    /// defaults to [PermissionRules::default]
+    pub policy: Arc<dyn PermissionPolicy>,
+    /// Prometheus / OpenMetrics endpoint, disabled when [None]
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<metrics::MetricsConfig>,
+    /// HTTP / JSON gateway, disabled when [None]
+    #[cfg(feature = "gateway")]
+    pub gateway: Option<gateway::GatewayConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            transport: Transport::default(),
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            max_subscriptions: DEFAULT_MAX_SUBSCRIPTIONS,
+            refresh: None,
+            history_length: DEFAULT_HISTORY_LENGTH,
+            policy: Arc::new(PermissionRules::default()),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "gateway")]
+            gateway: None,
+        }
+    }
+}
+
+/// Configuration for refreshing the hardware in the background
+///
+/// While enabled, update requests from clients are ignored and sensor
+/// values are answered from the last refresh along with the age of the
+/// value, so the hardware is accessed at the same rate regardless of
+/// how many clients are connected
+#[derive(Debug, Clone)]
+pub struct RefreshConfig {
+    /// Interval for hardware without a specific interval
+    pub interval: Duration,
+    /// Intervals for specific types of hardware
+    pub hardware_intervals: Vec<(HardwareType, Duration)>,
+    /// Interval between scans for hardware that has been added or removed
+    pub scan_interval: Duration,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            hardware_intervals: Vec::new(),
+            scan_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RefreshConfig {
+    /// Set a specific interval for a type of hardware
+    pub fn with_hardware_interval(mut self, ty: HardwareType, interval: Duration) -> Self {
+        self.hardware_intervals.retain(|(other, _)| *other != ty);
+        self.hardware_intervals.push((ty, interval));
+        self
+    }
+
+    /// Get the interval for a type of hardware
+    pub fn interval_for(&self, ty: HardwareType) -> Duration {
+        self.hardware_intervals
+            .iter()
+            .find(|(other, _)| *other == ty)
+            .map(|(_, interval)| *interval)
+            .unwrap_or(self.interval)
+            .max(MIN_REFRESH_INTERVAL)
+    }
+
+    /// Interval between checks for hardware that is due to be refreshed
+    fn tick_interval(&self) -> Duration {
+        self.hardware_intervals
+            .iter()
+            .map(|(_, interval)| *interval)
+            .fold(self.interval, Duration::min)
+            .max(MIN_REFRESH_INTERVAL)
+    }
+}
 
-/// Run the server
+/// Run the server using Libre Hardware Monitor listening on the
+/// default named pipe
+#[cfg(windows)]
 pub async fn run_server() -> std::io::Result<()> {
-    // Security descriptor that allows user-land programs to access the pipe
-    let security_descriptor = SecurityDescriptor::deserialize(
-        U16CString::from_str_truncate("D:(A;;GA;;;WD)").as_ucstr(),
-    )?;
-
-    // Create the pipe
-    let listener = PipeListenerOptions::new()
-        .mode(interprocess::os::windows::named_pipe::PipeMode::Bytes)
-        .security_descriptor(Some(security_descriptor))
-        .path(PIPE_NAME)
-        .create_tokio_duplex::<pipe_mode::Bytes>()?;
+    run_server_with_config(ServerConfig::default()).await
+}
+
+/// Run the server using Libre Hardware Monitor with the provided config
+#[cfg(windows)]
+pub async fn run_server_with_config(config: ServerConfig) -> std::io::Result<()> {
+    let computer = lhm_sys::Computer::create()?;
+    run_server_with(config, computer).await
+}
+
+/// Run the server using the provided config and backend
+///
+/// A single computer actor is shared between all connected clients
+pub async fn run_server_with<B: HardwareBackend>(
+    config: ServerConfig,
+    backend: B,
+) -> std::io::Result<()> {
+    let listener = Listener::bind(&config.transport).await?;
+    let handle = ComputerActor::create(backend, &config);
+
+    // Spawn task to refresh the hardware in the background
+    if let Some(refresh) = &config.refresh {
+        spawn(run_refresh(refresh.tick_interval(), handle.clone()));
+    }
+
+    // Spawn task to serve the metrics endpoint
+    #[cfg(feature = "metrics")]
+    if let Some(metrics) = &config.metrics {
+        let serve =
+            metrics::start_metrics(metrics.clone(), next_connection_id(), handle.clone()).await?;
+        spawn(serve);
+    }
+
+    // Spawn task to serve the gateway
+    #[cfg(feature = "gateway")]
+    if let Some(gateway) = &config.gateway {
+        let serve =
+            gateway::start_gateway(gateway.clone(), next_connection_id(), handle.clone()).await?;
+        spawn(serve);
+    }
 
     loop {
-        let stream = listener.accept().await?;
-        handle_pipe_stream(stream);
+        let (stream, identity) = listener.accept().await?;
+        let permission = config.policy.permission(&identity);
+
+        handle_pipe_stream(
+            stream,
+            config.max_frame_length,
+            config.max_subscriptions,
+            next_connection_id(),
+            permission,
+            handle.clone(),
+        );
     }
 }
 
-pub type Pipe = Framed<DuplexPipeStream<pipe_mode::Bytes>, LHMFrameCodec>;
-
-pub fn handle_pipe_stream(stream: DuplexPipeStream<pipe_mode::Bytes>) {
-    // Initialize an actor
-    let handle = match ComputerActor::create() {
-        Ok(value) => value,
-        Err(_cause) => return,
-    };
+/// Creates a unique ID for a new connection
+fn next_connection_id() -> ConnectionId {
+    static NEXT_CONNECTION_ID: AtomicU32 = AtomicU32::new(0);
+    NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
+}
 
-    let pipe = Framed::new(stream, LHMFrameCodec::default());
+fn handle_pipe_stream<S: TransportStream>(
+    stream: S,
+    max_frame_length: usize,
+    max_subscriptions: usize,
+    connection_id: ConnectionId,
+    permission: Permission,
+    handle: ComputerActorHandle,
+) {
+    let pipe = Framed::new(stream, LHMFrameCodec::new(max_frame_length));
     let (future, mut rx, tx) = PipeFuture::new(pipe);
 
     // Spawn task to handle the pipe itself
     spawn(future);
 
+    let connection = Arc::new(Connection {
+        id: connection_id,
+        permission,
+        max_subscriptions,
+        handle,
+        tx: ResponseTx::Pipe(tx),
+        subscriptions: Default::default(),
+    });
+
     // Spawn task to handle messages from the pipe
     spawn(async move {
         while let Some(frame) = rx.recv().await {
-            let handle = handle.clone();
-            let tx = tx.clone();
-
-            spawn(handle_frame(frame, handle, tx));
+            spawn(handle_frame(frame, connection.clone()));
         }
     });
 }
 
-async fn handle_frame(frame: LHMFrame, handle: ComputerActorHandle, tx: PipeTx) {
-    let request = rmp_serde::from_slice(&frame.body);
+/// Sender for the responses to a client
+#[derive(Clone)]
+enum ResponseTx {
+    /// Responses are encoded and written to the pipe
+    Pipe(PipeTx),
+    /// Responses are provided along with their ID for the WebSocket
+    /// endpoint to encode as JSON
+    #[cfg(feature = "websocket")]
+    WebSocket(tokio::sync::mpsc::UnboundedSender<(u32, PipeResponse)>),
+}
+
+/// State for a connected client
+struct Connection {
+    /// Unique ID of the connection
+    id: ConnectionId,
+    /// Permission granted to the client by the policy
+    permission: Permission,
+    /// Maximum number of active subscriptions
+    max_subscriptions: usize,
+    /// Handle to the computer actor
+    handle: ComputerActorHandle,
+    /// Sender for the responses to the client
+    tx: ResponseTx,
+    /// Active subscriptions for the client, keyed by subscription ID
+    subscriptions: Mutex<HashMap<u32, AbortHandle>>,
+}
+
+impl Connection {
+    /// Starts and tracks a subscription, replacing any existing subscription
+    /// using the same ID. Returns false without starting the subscription
+    /// when the client has reached the maximum number of subscriptions
+    fn start_subscription(
+        &self,
+        subscription_id: u32,
+        start: impl FnOnce() -> AbortHandle,
+    ) -> bool {
+        // Lock is held while starting so concurrent requests cannot exceed the limit
+        let subscriptions = &mut *self.subscriptions.lock();
+
+        if subscriptions.len() >= self.max_subscriptions
+            && !subscriptions.contains_key(&subscription_id)
+        {
+            return false;
+        }
+
+        if let Some(previous) = subscriptions.insert(subscription_id, start()) {
+            previous.abort();
+        }
+
+        true
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // Stop any subscriptions that are still running
+        for (_, subscription) in self.subscriptions.get_mut().drain() {
+            subscription.abort();
+        }
+
+        // Release the options requested by the client
+        let (tx, _) = oneshot::channel();
+        _ = self.handle.tx.send(ComputerActorMessage {
+            request: ActorRequest::Disconnect {
+                connection_id: self.id,
+            },
+            tx,
+        });
+    }
+}
+
+async fn handle_frame(frame: LHMFrame, connection: Arc<Connection>) {
+    let request = rmp_serde::from_slice::<PipeRequest>(&frame.body).map_err(|err| err.to_string());
+    handle_message(frame.id, request, connection).await;
+}
+
+/// Handles a request from a client, `id` is the ID of the frame the request
+/// was sent in and is used for the response. Requests that failed to
+/// decode are provided as an error message
+async fn handle_message(
+    id: u32,
+    request: Result<PipeRequest, String>,
+    connection: Arc<Connection>,
+) {
     let response = match request {
+        // Client does not have permission to make the request
+        Ok(request) if request.required_permission() > connection.permission => {
+            permission_denied(&request)
+        }
+
+        // Start a new subscription using the frame ID
+        Ok(PipeRequest::Subscribe {
+            sensor_ids,
+            interval_ms,
+        }) => {
+            let subscription_id = id;
+            let period = Duration::from_millis(interval_ms).max(MIN_SUBSCRIPTION_INTERVAL);
+
+            let started = connection.start_subscription(subscription_id, || {
+                // Respond before starting the subscription so the response is
+                // written before any of the subscription values
+                send_response(
+                    &connection.tx,
+                    subscription_id,
+                    PipeResponse::Subscribed { subscription_id },
+                );
+
+                spawn(run_subscription(
+                    subscription_id,
+                    sensor_ids.into(),
+                    period,
+                    connection.handle.clone(),
+                    connection.tx.clone(),
+                ))
+                .abort_handle()
+            });
+
+            if started {
+                return;
+            }
+
+            subscription_limit(connection.max_subscriptions)
+        }
+
+        // Start forwarding events using the frame ID
+        Ok(PipeRequest::SubscribeEvents) => {
+            let subscription_id = id;
+
+            let started = connection.start_subscription(subscription_id, || {
+                // Receiver is created before responding so no events are missed
+                let events = connection.handle.events.subscribe();
+
+                send_response(
+                    &connection.tx,
+                    subscription_id,
+                    PipeResponse::Subscribed { subscription_id },
+                );
+
+                spawn(run_event_subscription(
+                    subscription_id,
+                    events,
+                    connection.tx.clone(),
+                ))
+                .abort_handle()
+            });
+
+            if started {
+                return;
+            }
+
+            subscription_limit(connection.max_subscriptions)
+        }
+
+        Ok(PipeRequest::Unsubscribe { subscription_id }) => {
+            if let Some(subscription) = connection.subscriptions.lock().remove(&subscription_id) {
+                subscription.abort();
+            }
+
+            PipeResponse::Success
+        }
+
+        Ok(PipeRequest::Batch { requests }) => handle_batch(requests, &connection).await,
+
         // Handle the request
-        Ok(request) => handle_request(request, handle)
-            .await
-            .unwrap_or_else(|err| err),
+        Ok(request) => {
+            let request = ActorRequest::Pipe {
+                connection_id: connection.id,
+                request,
+            };
+
+            handle_request(request, &connection.handle)
+                .await
+                .unwrap_or_else(|err| err)
+        }
+
         // Failed to parse the request
-        Err(err) => {
-            let error = err.to_string();
-            PipeResponse::Error { error }
+        Err(err) => PipeResponse::error(ErrorCode::InvalidRequest, err),
+    };
+
+    send_response(&connection.tx, id, response);
+}
+
+/// Runs the requests of a batch in order using a single actor message,
+/// requests that are not allowed within the batch are answered with
+/// an error without being sent to the actor
+async fn handle_batch(requests: Vec<PipeRequest>, connection: &Connection) -> PipeResponse {
+    let mut responses = Vec::with_capacity(requests.len());
+    let mut allowed = Vec::with_capacity(requests.len());
+
+    for request in requests {
+        let error = match &request {
+            PipeRequest::Subscribe { .. }
+            | PipeRequest::Unsubscribe { .. }
+            | PipeRequest::SubscribeEvents
+            | PipeRequest::Batch { .. } => Some(PipeResponse::error(
+                ErrorCode::InvalidRequest,
+                "request cannot be made within a batch",
+            )),
+            request if request.required_permission() > connection.permission => {
+                Some(permission_denied(request))
+            }
+            _ => None,
+        };
+
+        if error.is_none() {
+            allowed.push(request);
+        }
+
+        responses.push(error);
+    }
+
+    let request = ActorRequest::Pipe {
+        connection_id: connection.id,
+        request: PipeRequest::Batch { requests: allowed },
+    };
+
+    let mut allowed_responses = match handle_request(request, &connection.handle).await {
+        Ok(PipeResponse::Batch { responses }) => responses.into_iter(),
+        Ok(_) => {
+            return PipeResponse::error(ErrorCode::Internal, "unexpected batch response");
         }
+        Err(err) => return err,
     };
 
-    let response_bytes = match rmp_serde::to_vec(&response) {
+    // Fill in the responses for the requests that were sent to the actor
+    let responses = responses
+        .into_iter()
+        .map(|response| {
+            response
+                .or_else(|| allowed_responses.next())
+                .unwrap_or_else(|| {
+                    PipeResponse::error(ErrorCode::Internal, "missing batch response")
+                })
+        })
+        .collect();
+
+    PipeResponse::Batch { responses }
+}
+
+/// Creates the response for a request the client is not allowed to make
+fn permission_denied(request: &PipeRequest) -> PipeResponse {
+    let message = format!(
+        "request requires {:?} permission",
+        request.required_permission()
+    );
+    PipeResponse::error(ErrorCode::PermissionDenied, message)
+}
+
+/// Creates the response for a subscription rejected because the client
+/// has reached the maximum number of subscriptions
+fn subscription_limit(max_subscriptions: usize) -> PipeResponse {
+    let message = format!("client is limited to {max_subscriptions} subscriptions");
+    PipeResponse::error(ErrorCode::LimitExceeded, message)
+}
+
+/// Periodically polls the subscribed sensors sending the values to
+/// the client until the subscription is aborted or the client disconnects
+async fn run_subscription(
+    subscription_id: u32,
+    sensor_ids: Arc<[String]>,
+    period: Duration,
+    handle: ComputerActorHandle,
+    tx: ResponseTx,
+) {
+    let mut interval = interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        let request = ActorRequest::PollSubscription {
+            sensor_ids: sensor_ids.clone(),
+        };
+        let response = handle_request(request, &handle)
+            .await
+            .unwrap_or_else(|err| err);
+
+        if !send_response(&tx, subscription_id, response) {
+            return;
+        }
+    }
+}
+
+/// Forwards events emitted by the actor to the client until the
+/// subscription is aborted or the client disconnects
+async fn run_event_subscription(
+    subscription_id: u32,
+    mut events: broadcast::Receiver<ServerEvent>,
+    tx: ResponseTx,
+) {
+    loop {
+        let response = match events.recv().await {
+            Ok(event) => PipeResponse::Event { event },
+            // Client is not keeping up, let it know events were missed
+            Err(RecvError::Lagged(count)) => {
+                PipeResponse::error(ErrorCode::Internal, format!("missed {count} events"))
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        if !send_response(&tx, subscription_id, response) {
+            return;
+        }
+    }
+}
+
+/// Periodically requests the actor refresh any hardware that is due
+/// to be refreshed until the actor is closed
+async fn run_refresh(period: Duration, handle: ComputerActorHandle) {
+    let mut interval = interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if handle_request(ActorRequest::Refresh, &handle)
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Sends a response to the client, returns false if the client
+/// is no longer connected
+fn send_response(tx: &ResponseTx, id: u32, response: PipeResponse) -> bool {
+    match tx {
+        ResponseTx::Pipe(tx) => send_frame(tx, id, &response),
+        #[cfg(feature = "websocket")]
+        ResponseTx::WebSocket(tx) => tx.send((id, response)).is_ok(),
+    }
+}
+
+/// Encodes and writes a response to the pipe, returns false if the
+/// client is no longer connected
+fn send_frame(tx: &PipeTx, id: u32, response: &PipeResponse) -> bool {
+    let response_bytes = match rmp_serde::to_vec(response) {
         Ok(value) => value,
         Err(_cause) => {
             // Nothing we can do here
-            return;
+            return !tx.is_closed();
         }
     };
 
-    _ = tx.send(LHMFrame {
-        id: frame.id,
+    tx.send(LHMFrame {
+        id,
         body: Bytes::from(response_bytes),
-    });
+    })
+    .is_ok()
 }
 
 async fn handle_request(
-    request: PipeRequest,
-    handle: ComputerActorHandle,
+    request: ActorRequest,
+    handle: &ComputerActorHandle,
 ) -> Result<PipeResponse, PipeResponse> {
     let (tx, rx) = oneshot::channel();
 
     handle
         .tx
         .send(ComputerActorMessage { request, tx })
-        .map_err(|_| PipeResponse::Error {
-            error: "request actor is closed".to_string(),
+        .map_err(|_| {
+            PipeResponse::error(ErrorCode::BackendUnavailable, "request actor is closed")
         })?;
 
-    let response = rx.await.map_err(|_| PipeResponse::Error {
-        error: "request actor is closed".to_string(),
-    })?;
+    let response = timeout(REQUEST_TIMEOUT, rx)
+        .await
+        .map_err(|_| PipeResponse::error(ErrorCode::Timeout, "request timed out"))?
+        .map_err(|_| {
+            PipeResponse::error(ErrorCode::BackendUnavailable, "request actor is closed")
+        })?;
 
     Ok(response)
 }
+
+/// Sets the hardware enabled for one of the HTTP endpoints, requested from
+/// the computer in the same way as the options set by a connected client.
+/// The server fails to start when the options cannot be applied
+#[cfg(any(feature = "metrics", feature = "gateway"))]
+async fn set_endpoint_options(
+    connection_id: ConnectionId,
+    options: lhm_shared::ComputerOptions,
+    handle: &ComputerActorHandle,
+) -> std::io::Result<()> {
+    let request = ActorRequest::Pipe {
+        connection_id,
+        request: PipeRequest::SetOptions { options },
+    };
+
+    match handle_request(request, handle)
+        .await
+        .unwrap_or_else(|err| err)
+    {
+        PipeResponse::Success => Ok(()),
+        PipeResponse::Error { code, message } => Err(std::io::Error::other(format!(
+            "failed to set endpoint options ({code:?}): {message}"
+        ))),
+        _ => Err(std::io::Error::other(
+            "unexpected response setting endpoint options",
+        )),
+    }
+}
+
+/// Creates an actor for the shared test hardware (See [backend::memory::test_hardware])
+/// with all the hardware enabled for the endpoint connection zero
+#[cfg(all(test, feature = "gateway"))]
+async fn test_endpoint_handle() -> ComputerActorHandle {
+    let backend = backend::memory::test_hardware().backend;
+    let handle = ComputerActor::create(backend, &ServerConfig::default());
+    set_endpoint_options(0, lhm_shared::ComputerOptions::all(), &handle)
+        .await
+        .unwrap();
+    handle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Connection, ResponseTx, ServerConfig, handle_message};
+    use crate::{actor::ComputerActor, backend::memory::MemoryBackend};
+    use lhm_shared::{
+        ComputerOptions, ErrorCode, Permission, PipeRequest, PipeResponse, codec::LHMFrame,
+    };
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    /// Creates a connection with the provided permission and subscription
+    /// limit, along with a receiver for the frames sent to the connection
+    fn connection(
+        permission: Permission,
+        max_subscriptions: usize,
+    ) -> (Arc<Connection>, mpsc::UnboundedReceiver<LHMFrame>) {
+        let handle =
+            ComputerActor::create(MemoryBackend::new(Vec::new()), &ServerConfig::default());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let connection = Arc::new(Connection {
+            id: 0,
+            permission,
+            max_subscriptions,
+            handle,
+            tx: ResponseTx::Pipe(tx),
+            subscriptions: Default::default(),
+        });
+
+        (connection, rx)
+    }
+
+    /// Receives the next response for a frame ID, skipping responses
+    /// for other IDs such as subscription values
+    async fn recv_response(rx: &mut mpsc::UnboundedReceiver<LHMFrame>, id: u32) -> PipeResponse {
+        loop {
+            let frame = rx.recv().await.unwrap();
+            let response: PipeResponse = rmp_serde::from_slice(&frame.body).unwrap();
+
+            if frame.id == id && !matches!(response, PipeResponse::SubscriptionValues { .. }) {
+                return response;
+            }
+        }
+    }
+
+    fn subscribe() -> PipeRequest {
+        PipeRequest::Subscribe {
+            sensor_ids: Vec::new(),
+            interval_ms: 60_000,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_limit() {
+        let (connection, mut rx) = connection(Permission::Read, 2);
+
+        handle_message(1, Ok(subscribe()), connection.clone()).await;
+        handle_message(2, Ok(PipeRequest::SubscribeEvents), connection.clone()).await;
+
+        assert!(matches!(
+            recv_response(&mut rx, 1).await,
+            PipeResponse::Subscribed { subscription_id: 1 }
+        ));
+        assert!(matches!(
+            recv_response(&mut rx, 2).await,
+            PipeResponse::Subscribed { subscription_id: 2 }
+        ));
+
+        // Both kinds of subscription are rejected at the limit
+        handle_message(3, Ok(subscribe()), connection.clone()).await;
+        assert!(matches!(
+            recv_response(&mut rx, 3).await,
+            PipeResponse::Error {
+                code: ErrorCode::LimitExceeded,
+                ..
+            }
+        ));
+
+        handle_message(4, Ok(PipeRequest::SubscribeEvents), connection.clone()).await;
+        assert!(matches!(
+            recv_response(&mut rx, 4).await,
+            PipeResponse::Error {
+                code: ErrorCode::LimitExceeded,
+                ..
+            }
+        ));
+        assert_eq!(connection.subscriptions.lock().len(), 2);
+
+        // Replacing an existing subscription is allowed
+        handle_message(1, Ok(subscribe()), connection.clone()).await;
+        assert!(matches!(
+            recv_response(&mut rx, 1).await,
+            PipeResponse::Subscribed { subscription_id: 1 }
+        ));
+
+        // Unsubscribing frees up space for a new subscription
+        handle_message(
+            5,
+            Ok(PipeRequest::Unsubscribe { subscription_id: 2 }),
+            connection.clone(),
+        )
+        .await;
+        assert!(matches!(
+            recv_response(&mut rx, 5).await,
+            PipeResponse::Success
+        ));
+
+        handle_message(6, Ok(subscribe()), connection.clone()).await;
+        assert!(matches!(
+            recv_response(&mut rx, 6).await,
+            PipeResponse::Subscribed { subscription_id: 6 }
+        ));
+    }
+
+    fn is_error(response: &PipeResponse, expected: ErrorCode) -> bool {
+        matches!(response, PipeResponse::Error { code, .. } if *code == expected)
+    }
+
+    #[tokio::test]
+    async fn test_permission_denied() {
+        let (connection, mut rx) = connection(Permission::Read, 2);
+
+        handle_message(1, Ok(PipeRequest::ResetAllMinMax), connection.clone()).await;
+        assert!(is_error(
+            &recv_response(&mut rx, 1).await,
+            ErrorCode::PermissionDenied
+        ));
+
+        handle_message(2, Ok(PipeRequest::GetCacheGeneration), connection.clone()).await;
+        assert!(matches!(
+            recv_response(&mut rx, 2).await,
+            PipeResponse::CacheGeneration { .. }
+        ));
+
+        // Options only apply to the connection so reading is enough
+        let options = ComputerOptions::all();
+        handle_message(
+            3,
+            Ok(PipeRequest::SetOptions { options }),
+            connection.clone(),
+        )
+        .await;
+        assert!(matches!(
+            recv_response(&mut rx, 3).await,
+            PipeResponse::Success
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_batch_permission_per_item() {
+        let (connection, mut rx) = connection(Permission::Configure, 2);
+
+        let requests = vec![
+            PipeRequest::GetCacheGeneration,
+            PipeRequest::SetSensorControl {
+                id: "/lpc/nct6798d/control/0".to_string(),
+                value: 50.0,
+            },
+            PipeRequest::ResetAllMinMax,
+            subscribe(),
+        ];
+        handle_message(1, Ok(PipeRequest::Batch { requests }), connection.clone()).await;
+
+        let PipeResponse::Batch { responses } = recv_response(&mut rx, 1).await else {
+            panic!("expected a batch response");
+        };
+
+        // Denied requests are answered in place without failing the batch
+        assert_eq!(responses.len(), 4);
+        assert!(matches!(responses[0], PipeResponse::CacheGeneration { .. }));
+        assert!(is_error(&responses[1], ErrorCode::PermissionDenied));
+        assert!(matches!(responses[2], PipeResponse::Success));
+        assert!(is_error(&responses[3], ErrorCode::InvalidRequest));
+        assert!(connection.subscriptions.lock().is_empty());
+    }
+}
@@ -0,0 +1,416 @@
+//! Prometheus / OpenMetrics HTTP endpoint exposing the value of every
+//! sensor in the cache as a gauge
+
+use crate::{
+    actor::{ActorRequest, ComputerActorHandle, ConnectionId},
+    handle_request, set_endpoint_options,
+};
+use axum::{
+    Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use lhm_shared::{
+    ComputerOptions, Hardware, PipeRequest, PipeResponse, Sensor, SensorType,
+    snapshot::{Snapshot, SnapshotFilter},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::{Ipv4Addr, SocketAddr},
+    time::Instant,
+};
+use tokio::net::TcpListener;
+
+/// Content type of the OpenMetrics text format
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Default port the metrics endpoint is served on
+const DEFAULT_PORT: u16 = 9184;
+
+/// Configuration for the metrics endpoint
+#[derive(Debug, Clone)]
+pub struct MetricsConfig {
+    /// Address to serve the metrics on, the metrics are available
+    /// at the `/metrics` path
+    pub address: SocketAddr,
+    /// Hardware to include in the metrics, defaults to all the hardware
+    pub options: ComputerOptions,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from((Ipv4Addr::LOCALHOST, DEFAULT_PORT)),
+            options: ComputerOptions::all(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct MetricsState {
+    /// Connection ID used for requests made by the endpoint
+    connection_id: ConnectionId,
+    /// Handle to the computer actor
+    handle: ComputerActorHandle,
+}
+
+/// Binds the metrics endpoint and enables the hardware included in the
+/// metrics, provides the future serving the endpoint until the listener fails
+pub(crate) async fn start_metrics(
+    config: MetricsConfig,
+    connection_id: ConnectionId,
+    handle: ComputerActorHandle,
+) -> std::io::Result<impl Future<Output = std::io::Result<()>>> {
+    let listener = TcpListener::bind(config.address).await?;
+    set_endpoint_options(connection_id, config.options, &handle).await?;
+
+    let app = Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state(MetricsState {
+            connection_id,
+            handle,
+        });
+
+    Ok(async move { axum::serve(listener, app).await })
+}
+
+async fn get_metrics(State(state): State<MetricsState>) -> Response {
+    let start = Instant::now();
+
+    // Updates the hardware unless it is kept updated by the background refresh
+    let request = ActorRequest::Pipe {
+        connection_id: state.connection_id,
+        request: PipeRequest::GetSnapshot {
+            update: true,
+            filter: SnapshotFilter::default(),
+        },
+    };
+
+    let snapshot = match handle_request(request, &state.handle).await {
+        Ok(PipeResponse::Snapshot { snapshot }) => snapshot,
+        Ok(PipeResponse::Error { message, .. }) | Err(PipeResponse::Error { message, .. }) => {
+            return (StatusCode::SERVICE_UNAVAILABLE, message).into_response();
+        }
+        _ => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "unexpected response").into_response();
+        }
+    };
+
+    let body = render_metrics(&snapshot, start);
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], body).into_response()
+}
+
+/// Renders the sensors within the snapshot in the OpenMetrics text format,
+/// `start` is the time the scrape started used for the scrape duration
+fn render_metrics(snapshot: &Snapshot, start: Instant) -> String {
+    // Metrics from the same family must be grouped together
+    let mut families: BTreeMap<MetricFamily, Vec<(&Hardware, &Sensor)>> = BTreeMap::new();
+    for (hardware, sensor) in snapshot.iter_sensors() {
+        families
+            .entry(sensor_metric(sensor.ty))
+            .or_default()
+            .push((hardware, sensor));
+    }
+
+    let mut out = String::new();
+
+    for (family, sensors) in &families {
+        let name = family.name;
+
+        family.write_header(&mut out, None, &format!("Current value of {name} sensors"));
+        for (hardware, sensor) in sensors {
+            family.write_sample(&mut out, None, hardware, sensor, sensor.value);
+        }
+
+        family.write_header(
+            &mut out,
+            Some("min"),
+            &format!("Minimum recorded value of {name} sensors"),
+        );
+        for (hardware, sensor) in sensors {
+            family.write_sample(&mut out, Some("min"), hardware, sensor, sensor.min);
+        }
+
+        family.write_header(
+            &mut out,
+            Some("max"),
+            &format!("Maximum recorded value of {name} sensors"),
+        );
+        for (hardware, sensor) in sensors {
+            family.write_sample(&mut out, Some("max"), hardware, sensor, sensor.max);
+        }
+    }
+
+    let scrape = MetricFamily {
+        name: "scrape_duration",
+        unit: Some("seconds"),
+    };
+    scrape.write_header(&mut out, None, "Time taken to collect the sensor values");
+    _ = writeln!(
+        out,
+        "{} {}",
+        scrape.metric_name(None),
+        start.elapsed().as_secs_f64()
+    );
+
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Name and unit of a family of metrics
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct MetricFamily<'a> {
+    /// Name of the metric without the prefix or unit
+    name: &'a str,
+    /// Unit of the metric values
+    unit: Option<&'a str>,
+}
+
+impl MetricFamily<'_> {
+    /// Full name of the metric, `suffix` is added before the unit
+    fn metric_name(&self, suffix: Option<&str>) -> String {
+        let mut name = format!("lhm_{}", self.name);
+        if let Some(suffix) = suffix {
+            name.push('_');
+            name.push_str(suffix);
+        }
+        if let Some(unit) = self.unit {
+            name.push('_');
+            name.push_str(unit);
+        }
+        name
+    }
+
+    fn write_header(&self, out: &mut String, suffix: Option<&str>, help: &str) {
+        let name = self.metric_name(suffix);
+
+        _ = writeln!(out, "# TYPE {name} gauge");
+        if let Some(unit) = self.unit {
+            _ = writeln!(out, "# UNIT {name} {unit}");
+        }
+        _ = writeln!(out, "# HELP {name} {help}");
+    }
+
+    fn write_sample(
+        &self,
+        out: &mut String,
+        suffix: Option<&str>,
+        hardware: &Hardware,
+        sensor: &Sensor,
+        value: f32,
+    ) {
+        // Sensors without a value are left out
+        if !value.is_finite() {
+            return;
+        }
+
+        _ = writeln!(
+            out,
+            "{}{{hardware=\"{}\",hardware_type=\"{:?}\",hardware_id=\"{}\",sensor=\"{}\",sensor_id=\"{}\"}} {}",
+            self.metric_name(suffix),
+            escape_label(&hardware.name),
+            hardware.ty,
+            escape_label(&hardware.identifier),
+            escape_label(&sensor.name),
+            escape_label(&sensor.identifier),
+            value
+        );
+    }
+}
+
+/// Name and unit of the metric for a type of sensor
+fn sensor_metric(ty: SensorType) -> MetricFamily<'static> {
+    let (name, unit) = match ty {
+        SensorType::Voltage => ("voltage", Some("volts")),
+        SensorType::Current => ("current", Some("amperes")),
+        SensorType::Power => ("power", Some("watts")),
+        SensorType::Clock => ("clock", Some("megahertz")),
+        SensorType::Temperature => ("temperature", Some("celsius")),
+        SensorType::Load => ("load", Some("percent")),
+        SensorType::Frequency => ("frequency", Some("hertz")),
+        SensorType::Fan => ("fan", Some("rpm")),
+        SensorType::Flow => ("flow", Some("liters_per_hour")),
+        SensorType::Control => ("control", Some("percent")),
+        SensorType::Level => ("level", Some("percent")),
+        SensorType::Factor => ("factor", None),
+        SensorType::Data => ("data", Some("gigabytes")),
+        SensorType::SmallData => ("small_data", Some("megabytes")),
+        SensorType::Throughput => ("throughput", Some("bytes_per_second")),
+        SensorType::TimeSpan => ("time_span", Some("seconds")),
+        SensorType::Energy => ("energy", Some("milliwatt_hours")),
+        SensorType::Noise => ("noise", Some("decibels")),
+        SensorType::Conductivity => ("conductivity", Some("microsiemens_per_centimeter")),
+        SensorType::Humidity => ("humidity", Some("percent")),
+        SensorType::Unknown(_) => ("unknown", None),
+    };
+
+    MetricFamily { name, unit }
+}
+
+/// Escapes a label value for the OpenMetrics text format
+fn escape_label(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MetricFamily, escape_label, render_metrics};
+    use lhm_shared::{
+        Hardware, HardwareType, Sensor, SensorType,
+        snapshot::{HardwareNode, Snapshot},
+    };
+    use std::time::Instant;
+
+    fn sensor(index: usize, name: &str, ty: SensorType, value: f32) -> Sensor {
+        Sensor {
+            index,
+            identifier: format!("/intelcpu/0/{index}"),
+            name: name.to_string(),
+            ty,
+            value,
+            min: value,
+            max: value,
+        }
+    }
+
+    fn snapshot(sensors: Vec<Sensor>) -> Snapshot {
+        Snapshot {
+            timestamp_ms: 0,
+            generation: 1,
+            hardware: vec![HardwareNode {
+                hardware: Hardware {
+                    index: 0,
+                    identifier: "/intelcpu/0".to_string(),
+                    name: "Intel Core i7".to_string(),
+                    ty: HardwareType::Cpu,
+                    parent_index: None,
+                    parent_identifier: None,
+                },
+                sensors,
+                children: Vec::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("CPU Core #1"), "CPU Core #1");
+        assert_eq!(escape_label(r#"a "b" c"#), r#"a \"b\" c"#);
+        assert_eq!(escape_label("C:\\temp"), "C:\\\\temp");
+        assert_eq!(escape_label("line\nbreak"), "line\\nbreak");
+    }
+
+    #[test]
+    fn test_metric_name() {
+        let family = MetricFamily {
+            name: "temperature",
+            unit: Some("celsius"),
+        };
+        assert_eq!(family.metric_name(None), "lhm_temperature_celsius");
+        assert_eq!(
+            family.metric_name(Some("max")),
+            "lhm_temperature_max_celsius"
+        );
+
+        let family = MetricFamily {
+            name: "factor",
+            unit: None,
+        };
+        assert_eq!(family.metric_name(Some("min")), "lhm_factor_min");
+    }
+
+    #[test]
+    fn test_render_headers() {
+        let snapshot = snapshot(vec![
+            sensor(0, "CPU Package", SensorType::Temperature, 55.5),
+            sensor(1, "Factor", SensorType::Factor, 2.0),
+        ]);
+        let out = render_metrics(&snapshot, Instant::now());
+        let lines: Vec<&str> = out.lines().collect();
+
+        // Metadata for each family comes before its samples
+        let start = lines
+            .iter()
+            .position(|line| *line == "# TYPE lhm_temperature_celsius gauge")
+            .unwrap();
+        assert_eq!(lines[start + 1], "# UNIT lhm_temperature_celsius celsius");
+        assert_eq!(
+            lines[start + 2],
+            "# HELP lhm_temperature_celsius Current value of temperature sensors"
+        );
+        assert_eq!(
+            lines[start + 3],
+            "lhm_temperature_celsius{hardware=\"Intel Core i7\",hardware_type=\"Cpu\",\
+             hardware_id=\"/intelcpu/0\",sensor=\"CPU Package\",sensor_id=\"/intelcpu/0/0\"} 55.5"
+        );
+        assert!(lines.contains(&"# TYPE lhm_temperature_max_celsius gauge"));
+        assert!(lines.contains(&"# UNIT lhm_temperature_max_celsius celsius"));
+
+        // Metrics without a unit have no unit metadata
+        assert!(lines.contains(&"# TYPE lhm_factor gauge"));
+        assert!(
+            !lines
+                .iter()
+                .any(|line| line.starts_with("# UNIT lhm_factor"))
+        );
+
+        assert!(lines.contains(&"# TYPE lhm_scrape_duration_seconds gauge"));
+        assert!(lines.contains(&"# UNIT lhm_scrape_duration_seconds seconds"));
+    }
+
+    #[test]
+    fn test_render_escapes_labels() {
+        let snapshot = snapshot(vec![sensor(0, "Core \"P\"\n#1", SensorType::Load, 10.0)]);
+        let out = render_metrics(&snapshot, Instant::now());
+
+        assert!(out.contains("sensor=\"Core \\\"P\\\"\\n#1\""));
+        // Escaped new lines do not split the sample
+        assert!(
+            out.lines()
+                .any(|line| line.starts_with("lhm_load_percent{") && line.ends_with(" 10"))
+        );
+    }
+
+    #[test]
+    fn test_render_skips_nan() {
+        let mut missing = sensor(1, "Missing", SensorType::Temperature, f32::NAN);
+        missing.max = 70.0;
+
+        let snapshot = snapshot(vec![
+            sensor(0, "Present", SensorType::Temperature, 50.0),
+            missing,
+        ]);
+        let out = render_metrics(&snapshot, Instant::now());
+
+        assert!(!out.contains("NaN"));
+        let samples = |name: &str| {
+            out.lines()
+                .filter(|line| line.starts_with(&format!("{name}{{")))
+                .count()
+        };
+        assert_eq!(samples("lhm_temperature_celsius"), 1);
+        assert_eq!(samples("lhm_temperature_min_celsius"), 1);
+        assert_eq!(samples("lhm_temperature_max_celsius"), 2);
+    }
+
+    #[test]
+    fn test_render_ends_with_eof() {
+        let out = render_metrics(&snapshot(Vec::new()), Instant::now());
+
+        assert!(out.ends_with("\n# EOF\n"));
+        assert_eq!(out.matches("# EOF").count(), 1);
+        assert!(out.starts_with("# TYPE lhm_scrape_duration_seconds gauge\n"));
+    }
+}
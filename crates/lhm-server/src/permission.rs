@@ -0,0 +1,155 @@
+//! Permissions for connected clients, each request requires a [Permission]
+//! (See [PipeRequest::required_permission]) and a [PermissionPolicy] decides
+//! the permission granted to a client based on its [ClientIdentity]
+//!
+//! [PipeRequest::required_permission]: lhm_shared::PipeRequest::required_permission
+
+use std::fmt::Debug;
+
+pub use lhm_shared::Permission;
+
+/// SID of the Windows local system account
+pub const LOCAL_SYSTEM_SID: &str = "S-1-5-18";
+
+/// Identity of a connected client, fields are [None] when the
+/// transport is unable to identify the client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientIdentity {
+    /// ID of the client process
+    pub process_id: Option<u32>,
+    /// User the client process is running as, the SID string on
+    /// Windows (e.g "S-1-5-18") or the UID on Unix
+    pub user: Option<String>,
+}
+
+/// Policy deciding the permission granted to each client
+pub trait PermissionPolicy: Debug + Send + Sync + 'static {
+    /// Get the highest permission granted to a client, clients
+    /// may make any request requiring that permission or lower
+    fn permission(&self, client: &ClientIdentity) -> Permission;
+}
+
+/// [PermissionPolicy] granting permissions based on the user of the client
+#[derive(Debug, Clone)]
+pub struct PermissionRules {
+    /// Permission for clients that do not match any rule
+    pub default: Permission,
+    /// Rules for specific users, the first matching rule is used
+    pub rules: Vec<PermissionRule>,
+}
+
+/// Rule within [PermissionRules]
+#[derive(Debug, Clone)]
+pub struct PermissionRule {
+    /// User the rule applies to
+    pub user: String,
+    /// Permission granted to the user
+    pub permission: Permission,
+}
+
+impl PermissionRules {
+    /// Rules granting every client the provided permission
+    pub fn allow_all(permission: Permission) -> Self {
+        Self {
+            default: permission,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Add a rule granting a permission to a specific user
+    pub fn with_rule(mut self, user: impl Into<String>, permission: Permission) -> Self {
+        self.rules.push(PermissionRule {
+            user: user.into(),
+            permission,
+        });
+        self
+    }
+}
+
+impl Default for PermissionRules {
+    /// Allows every client to read the hardware, only the local
+    /// system account may configure and control the hardware
+    fn default() -> Self {
+        Self::allow_all(Permission::Read).with_rule(LOCAL_SYSTEM_SID, Permission::Control)
+    }
+}
+
+impl PermissionPolicy for PermissionRules {
+    fn permission(&self, client: &ClientIdentity) -> Permission {
+        client
+            .user
+            .as_deref()
+            .and_then(|user| self.rules.iter().find(|rule| rule.user.eq(user)))
+            .map(|rule| rule.permission)
+            .unwrap_or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientIdentity, LOCAL_SYSTEM_SID, Permission, PermissionPolicy, PermissionRules};
+
+    fn client(user: Option<&str>) -> ClientIdentity {
+        ClientIdentity {
+            process_id: Some(1),
+            user: user.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_default_read_only() {
+        let rules = PermissionRules::default();
+
+        assert_eq!(rules.permission(&client(None)), Permission::Read);
+        assert_eq!(rules.permission(&client(Some("1000"))), Permission::Read);
+        assert_eq!(
+            rules.permission(&client(Some(LOCAL_SYSTEM_SID))),
+            Permission::Control
+        );
+    }
+
+    #[test]
+    fn test_allow_all() {
+        let rules = PermissionRules::allow_all(Permission::Read);
+
+        assert_eq!(rules.permission(&client(None)), Permission::Read);
+        assert_eq!(rules.permission(&client(Some("1000"))), Permission::Read);
+    }
+
+    #[test]
+    fn test_rule_matches_user() {
+        let rules = PermissionRules::allow_all(Permission::Read)
+            .with_rule("S-1-5-18", Permission::Control)
+            .with_rule("1000", Permission::Configure);
+
+        // Windows SID and Unix UID
+        assert_eq!(
+            rules.permission(&client(Some("S-1-5-18"))),
+            Permission::Control
+        );
+        assert_eq!(
+            rules.permission(&client(Some("1000"))),
+            Permission::Configure
+        );
+    }
+
+    #[test]
+    fn test_rule_fallback() {
+        let rules =
+            PermissionRules::allow_all(Permission::Read).with_rule("0", Permission::Control);
+
+        // Unmatched and unidentified users get the default
+        assert_eq!(rules.permission(&client(Some("1000"))), Permission::Read);
+        assert_eq!(rules.permission(&client(Some("00"))), Permission::Read);
+        assert_eq!(rules.permission(&client(None)), Permission::Read);
+    }
+
+    #[test]
+    fn test_first_rule_used() {
+        let rules = PermissionRules::allow_all(Permission::Configure)
+            .with_rule("1000", Permission::Read)
+            .with_rule("1000", Permission::Control);
+
+        assert_eq!(rules.permission(&client(Some("1000"))), Permission::Read);
+    }
+}
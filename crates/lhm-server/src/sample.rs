@@ -0,0 +1,28 @@
+use lhm_shared::{HardwareType, SensorType, alert::SensorSelector};
+
+/// Value of a sensor that alert rules and virtual sensors
+/// are evaluated against
+pub struct SensorSample {
+    /// Unique identifier of the sensor
+    pub identifier: String,
+    /// Type of the sensor
+    pub ty: SensorType,
+    /// Unique identifier of the hardware the sensor belongs to
+    pub hardware_identifier: String,
+    /// Type of the hardware the sensor belongs to
+    pub hardware_ty: HardwareType,
+    /// Current value of the sensor, NaN when the sensor has no value
+    pub value: f32,
+}
+
+impl SensorSample {
+    /// Check if the sensor is selected by a selector
+    pub fn is_selected(&self, selector: &SensorSelector) -> bool {
+        selector.matches(
+            &self.identifier,
+            self.ty,
+            &self.hardware_identifier,
+            self.hardware_ty,
+        )
+    }
+}
@@ -0,0 +1,371 @@
+use crate::permission::ClientIdentity;
+use lhm_shared::{Transport, pipe::TransportStream};
+use tokio::net::TcpListener;
+
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+#[cfg(windows)]
+use interprocess::os::windows::{
+    named_pipe::{
+        PipeListenerOptions, PipeMode, pipe_mode,
+        tokio::{PipeListener, PipeListenerOptionsExt},
+    },
+    security_descriptor::SecurityDescriptor,
+};
+#[cfg(windows)]
+use widestring::U16CString;
+
+/// Listener accepting client connections for a [Transport]
+pub enum Listener {
+    #[cfg(windows)]
+    NamedPipe(PipeListener<pipe_mode::Bytes, pipe_mode::Bytes>),
+    #[cfg(unix)]
+    UnixSocket(UnixListener),
+    Tcp(TcpListener),
+}
+
+impl Listener {
+    /// Start listening on the provided transport
+    pub async fn bind(transport: &Transport) -> std::io::Result<Listener> {
+        match transport {
+            #[cfg(windows)]
+            Transport::NamedPipe(path) => {
+                // Security descriptor that allows user-land programs to access the pipe
+                let security_descriptor = SecurityDescriptor::deserialize(
+                    U16CString::from_str_truncate("D:(A;;GA;;;WD)").as_ucstr(),
+                )?;
+
+                // Create the pipe
+                let listener = PipeListenerOptions::new()
+                    .mode(PipeMode::Bytes)
+                    .security_descriptor(Some(security_descriptor))
+                    .path(path.as_str())
+                    .create_tokio_duplex::<pipe_mode::Bytes>()?;
+
+                Ok(Listener::NamedPipe(listener))
+            }
+
+            #[cfg(unix)]
+            Transport::UnixSocket(path) => {
+                remove_stale_socket(path).await?;
+
+                let listener = UnixListener::bind(path)?;
+                Ok(Listener::UnixSocket(listener))
+            }
+
+            Transport::Tcp(addr) => {
+                // Only allow local connections
+                if !addr.ip().is_loopback() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "tcp transport must use a loopback address",
+                    ));
+                }
+
+                let listener = TcpListener::bind(addr).await?;
+                Ok(Listener::Tcp(listener))
+            }
+        }
+    }
+
+    /// Accept the next client connection along with the identity of the client
+    pub async fn accept(&self) -> std::io::Result<(Box<dyn TransportStream>, ClientIdentity)> {
+        match self {
+            #[cfg(windows)]
+            Listener::NamedPipe(listener) => {
+                let stream = listener.accept().await?;
+                let process_id = stream.client_process_id().ok();
+                let identity = ClientIdentity {
+                    process_id,
+                    user: process_id.and_then(process_user_sid),
+                };
+
+                Ok((Box::new(stream), identity))
+            }
+
+            #[cfg(unix)]
+            Listener::UnixSocket(listener) => {
+                let (stream, _) = listener.accept().await?;
+                let identity = match stream.peer_cred() {
+                    Ok(cred) => ClientIdentity {
+                        process_id: cred.pid().and_then(|pid| u32::try_from(pid).ok()),
+                        user: Some(cred.uid().to_string()),
+                    },
+                    Err(_) => ClientIdentity::default(),
+                };
+
+                Ok((Box::new(stream), identity))
+            }
+
+            // Loopback TCP clients cannot be identified
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept().await?;
+                stream.set_nodelay(true)?;
+                Ok((Box::new(stream), ClientIdentity::default()))
+            }
+        }
+    }
+}
+
+/// Removes the socket left behind by a previous server that is no longer
+/// running. Fails without removing anything when another server is still
+/// listening on the socket or the path is not a socket
+#[cfg(unix)]
+async fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    use std::{io::ErrorKind, os::unix::fs::FileTypeExt};
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(value) => value,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(std::io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists and is not a socket", path.display()),
+        ));
+    }
+
+    match UnixStream::connect(path).await {
+        Ok(_) => Err(std::io::Error::new(
+            ErrorKind::AddrInUse,
+            format!("another server is already listening on {}", path.display()),
+        )),
+
+        // Nothing is listening on the socket
+        Err(err) if err.kind() == ErrorKind::ConnectionRefused => std::fs::remove_file(path),
+
+        Err(err) => Err(err),
+    }
+}
+
+/// Get the SID string of the user that a process is running as
+#[cfg(windows)]
+fn process_user_sid(process_id: u32) -> Option<String> {
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, HANDLE, LocalFree},
+        Security::{
+            Authorization::ConvertSidToStringSidW, GetTokenInformation, TOKEN_QUERY, TOKEN_USER,
+            TokenUser,
+        },
+        System::Threading::{OpenProcess, OpenProcessToken, PROCESS_QUERY_LIMITED_INFORMATION},
+    };
+
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id);
+        if process.is_null() {
+            return None;
+        }
+
+        let mut token: HANDLE = std::ptr::null_mut();
+        let opened = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+        CloseHandle(process);
+
+        if opened == 0 {
+            return None;
+        }
+
+        // Query the required length then the token user itself, u64 storage
+        // keeps the buffer aligned for the TOKEN_USER structure
+        let mut length = 0u32;
+        GetTokenInformation(token, TokenUser, std::ptr::null_mut(), 0, &mut length);
+
+        let mut buffer = vec![0u64; (length as usize).div_ceil(8)];
+        let queried = GetTokenInformation(
+            token,
+            TokenUser,
+            buffer.as_mut_ptr().cast(),
+            length,
+            &mut length,
+        );
+        CloseHandle(token);
+
+        if queried == 0 {
+            return None;
+        }
+
+        let token_user = &*buffer.as_ptr().cast::<TOKEN_USER>();
+
+        let mut sid_string: *mut u16 = std::ptr::null_mut();
+        if ConvertSidToStringSidW(token_user.User.Sid, &mut sid_string) == 0 {
+            return None;
+        }
+
+        let sid = widestring::U16CStr::from_ptr_str(sid_string).to_string_lossy();
+        LocalFree(sid_string.cast());
+
+        Some(sid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Listener;
+    use crate::{
+        ServerConfig, actor::ComputerActor, backend::memory::MemoryBackend, handle_pipe_stream,
+        permission::Permission,
+    };
+    use lhm_shared::{
+        PROTOCOL_VERSION, PipeRequest, PipeResponse, Transport,
+        codec::{DEFAULT_MAX_FRAME_LENGTH, LHMFrame, LHMFrameCodec},
+        pipe::{PipeFuture, TransportStream},
+    };
+    use tokio::spawn;
+    use tokio_util::{bytes::Bytes, codec::Framed};
+
+    /// Serves clients accepted by the listener using an empty backend
+    fn serve(listener: Listener) {
+        let handle =
+            ComputerActor::create(MemoryBackend::new(Vec::new()), &ServerConfig::default());
+
+        spawn(async move {
+            let mut connection_id = 0;
+            while let Ok((stream, _)) = listener.accept().await {
+                handle_pipe_stream(
+                    stream,
+                    DEFAULT_MAX_FRAME_LENGTH,
+                    32,
+                    connection_id,
+                    Permission::Read,
+                    handle.clone(),
+                );
+                connection_id += 1;
+            }
+        });
+    }
+
+    /// Sends a hello request over the stream and checks the server responds
+    /// with its own hello using the same frame ID
+    async fn assert_hello(stream: impl TransportStream) {
+        let pipe = Framed::new(stream, LHMFrameCodec::default());
+        let (future, mut rx, tx) = PipeFuture::new(pipe);
+        spawn(future);
+
+        let request = PipeRequest::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_version: "test".to_string(),
+        };
+        tx.send(LHMFrame {
+            id: 7,
+            body: Bytes::from(rmp_serde::to_vec(&request).unwrap()),
+        })
+        .unwrap();
+
+        let frame = rx.recv().await.unwrap();
+        assert_eq!(frame.id, 7);
+
+        let response: PipeResponse = rmp_serde::from_slice(&frame.body).unwrap();
+        let PipeResponse::Hello { server } = response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert_eq!(server.protocol_version, PROTOCOL_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_round_trip() {
+        let listener = Listener::bind(&Transport::Tcp(([127, 0, 0, 1], 0).into()))
+            .await
+            .unwrap();
+        let Listener::Tcp(tcp) = &listener else {
+            unreachable!()
+        };
+        let address = tcp.local_addr().unwrap();
+        serve(listener);
+
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert_hello(stream).await;
+    }
+
+    #[tokio::test]
+    async fn test_tcp_rejects_non_loopback() {
+        let result = Listener::bind(&Transport::Tcp(([0, 0, 0, 0], 0).into())).await;
+        assert!(result.is_err());
+    }
+
+    #[cfg(unix)]
+    mod unix {
+        use super::{Listener, assert_hello, serve};
+        use lhm_shared::Transport;
+        use std::{io::ErrorKind, path::PathBuf};
+        use tokio::net::UnixStream;
+
+        /// Unique socket path for a test
+        fn socket_path(name: &str) -> PathBuf {
+            let path =
+                std::env::temp_dir().join(format!("lhm-test-{}-{name}.sock", std::process::id()));
+            _ = std::fs::remove_file(&path);
+            path
+        }
+
+        #[tokio::test]
+        async fn test_unix_round_trip() {
+            let path = socket_path("round-trip");
+            let listener = Listener::bind(&Transport::UnixSocket(path.clone()))
+                .await
+                .unwrap();
+            serve(listener);
+
+            let stream = UnixStream::connect(&path).await.unwrap();
+            assert_hello(stream).await;
+
+            _ = std::fs::remove_file(&path);
+        }
+
+        #[tokio::test]
+        async fn test_stale_socket_replaced() {
+            let path = socket_path("stale");
+
+            // Socket file is left behind when the listener is dropped
+            drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+            assert!(path.exists());
+
+            let listener = Listener::bind(&Transport::UnixSocket(path.clone()))
+                .await
+                .unwrap();
+            serve(listener);
+
+            let stream = UnixStream::connect(&path).await.unwrap();
+            assert_hello(stream).await;
+
+            _ = std::fs::remove_file(&path);
+        }
+
+        #[tokio::test]
+        async fn test_live_socket_kept() {
+            let path = socket_path("live");
+            let _running = Listener::bind(&Transport::UnixSocket(path.clone()))
+                .await
+                .unwrap();
+
+            let err = Listener::bind(&Transport::UnixSocket(path.clone()))
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), ErrorKind::AddrInUse);
+
+            // Running server is still reachable
+            UnixStream::connect(&path).await.unwrap();
+
+            _ = std::fs::remove_file(&path);
+        }
+
+        #[tokio::test]
+        async fn test_non_socket_kept() {
+            let path = socket_path("file");
+            std::fs::write(&path, "not a socket").unwrap();
+
+            let err = Listener::bind(&Transport::UnixSocket(path.clone()))
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+
+            _ = std::fs::remove_file(&path);
+        }
+    }
+}
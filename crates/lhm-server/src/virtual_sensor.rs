@@ -0,0 +1,408 @@
+use crate::sample::SensorSample;
+use lhm_shared::{
+    Sensor,
+    alert::SensorSelector,
+    virtual_sensor::{VIRTUAL_SENSOR_PREFIX, VirtualFormula, VirtualSensor},
+};
+use std::time::Instant;
+
+/// Cache index of the first virtual sensor, virtual sensors use indexes
+/// starting from this value so they never overlap the sensors in the cache
+pub const VIRTUAL_INDEX_START: usize = 1 << 30;
+
+/// Virtual sensors registered with the server along with their values
+#[derive(Default)]
+pub struct VirtualSensors {
+    /// Registered virtual sensors, removed sensors leave an empty slot so
+    /// the indexes of the remaining sensors do not change and a stale index
+    /// never refers to another sensor. Slots are not reused, each removed
+    /// sensor keeps its empty slot for the lifetime of the server
+    entries: Vec<Option<VirtualEntry>>,
+}
+
+pub struct VirtualEntry {
+    /// The virtual sensor
+    pub sensor: VirtualSensor,
+    /// Current value of the sensor, NaN when the sensor has no value
+    pub value: f32,
+    /// Minimum recorded value of the sensor
+    pub min: f32,
+    /// Maximum recorded value of the sensor
+    pub max: f32,
+    /// Last time the value of the sensor was computed
+    pub updated_at: Option<Instant>,
+}
+
+impl VirtualEntry {
+    fn new(sensor: VirtualSensor) -> Self {
+        Self {
+            sensor,
+            value: f32::NAN,
+            min: f32::NAN,
+            max: f32::NAN,
+            updated_at: None,
+        }
+    }
+
+    /// Resets the minimum and maximum recorded values for the sensor
+    pub fn reset_min_max(&mut self) {
+        self.min = self.value;
+        self.max = self.value;
+    }
+
+    /// Check if a sensor is an input of the virtual sensor
+    pub fn is_input(&self, sample: &SensorSample) -> bool {
+        match &self.sensor.formula {
+            VirtualFormula::Average { inputs }
+            | VirtualFormula::Min { inputs }
+            | VirtualFormula::Max { inputs }
+            | VirtualFormula::Sum { inputs } => {
+                inputs.iter().any(|selector| sample.is_selected(selector))
+            }
+            VirtualFormula::Weighted { terms, .. } => {
+                terms.iter().any(|term| term.sensor_id == sample.identifier)
+            }
+        }
+    }
+
+    /// Creates the sensor provided to clients
+    pub fn to_sensor(&self, index: usize) -> Sensor {
+        Sensor {
+            index,
+            identifier: self.sensor.identifier(),
+            name: self.sensor.name.clone(),
+            ty: self.sensor.ty,
+            value: self.value,
+            min: self.min,
+            max: self.max,
+        }
+    }
+}
+
+impl VirtualSensors {
+    /// Check if there are no virtual sensors registered
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(Option::is_none)
+    }
+
+    /// Adds a virtual sensor, replacing any existing sensor with the same
+    /// ID. Provides the index of the sensor and whether it replaced
+    /// an existing sensor
+    pub fn add(&mut self, sensor: VirtualSensor) -> (usize, bool) {
+        if let Some(index) = self.get_index_by_id(&sensor.id) {
+            self.entries[index - VIRTUAL_INDEX_START] = Some(VirtualEntry::new(sensor));
+            return (index, true);
+        }
+
+        self.entries.push(Some(VirtualEntry::new(sensor)));
+        (VIRTUAL_INDEX_START + self.entries.len() - 1, false)
+    }
+
+    /// Removes the virtual sensor with the provided ID, providing
+    /// the index and identifier the sensor had
+    pub fn remove(&mut self, id: &str) -> Option<(usize, String)> {
+        let index = self.get_index_by_id(id)?;
+        let entry = self.entries[index - VIRTUAL_INDEX_START].take()?;
+        Some((index, entry.sensor.identifier()))
+    }
+
+    /// Index of the virtual sensor with the provided ID
+    fn get_index_by_id(&self, id: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.as_ref().is_some_and(|entry| entry.sensor.id == id))
+            .map(|position| VIRTUAL_INDEX_START + position)
+    }
+
+    /// Index of the virtual sensor with the provided identifier
+    pub fn get_index(&self, identifier: &str) -> Option<usize> {
+        let id = identifier.strip_prefix(VIRTUAL_SENSOR_PREFIX)?;
+        self.get_index_by_id(id)
+    }
+
+    pub fn get_by_idx(&self, index: usize) -> Option<&VirtualEntry> {
+        let position = index.checked_sub(VIRTUAL_INDEX_START)?;
+        self.entries.get(position)?.as_ref()
+    }
+
+    pub fn get_by_idx_mut(&mut self, index: usize) -> Option<&mut VirtualEntry> {
+        let position = index.checked_sub(VIRTUAL_INDEX_START)?;
+        self.entries.get_mut(position)?.as_mut()
+    }
+
+    /// Iterates the registered virtual sensors along with their indexes
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &VirtualEntry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(position, entry)| Some((VIRTUAL_INDEX_START + position, entry.as_ref()?)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut VirtualEntry> {
+        self.entries.iter_mut().flatten()
+    }
+
+    /// Computes the values of the virtual sensors from the values of
+    /// the provided sensors
+    pub fn evaluate(&mut self, now: Instant, samples: &[SensorSample]) {
+        for entry in self.iter_mut() {
+            let value = compute(&entry.sensor.formula, samples);
+
+            entry.value = value;
+            entry.updated_at = Some(now);
+
+            if !value.is_nan() {
+                entry.min = entry.min.min(value);
+                entry.max = entry.max.max(value);
+            }
+        }
+    }
+}
+
+/// Computes the value of a formula, NaN when none of the inputs have a value
+fn compute(formula: &VirtualFormula, samples: &[SensorSample]) -> f32 {
+    let selected = |inputs| selected_values(inputs, samples);
+
+    match formula {
+        VirtualFormula::Average { inputs } => {
+            let (sum, count) =
+                selected(inputs).fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+
+            if count == 0 {
+                f32::NAN
+            } else {
+                sum / count as f32
+            }
+        }
+
+        // Min and max ignore NaN so the initial NaN is only kept when empty
+        VirtualFormula::Min { inputs } => selected(inputs).fold(f32::NAN, f32::min),
+        VirtualFormula::Max { inputs } => selected(inputs).fold(f32::NAN, f32::max),
+
+        VirtualFormula::Sum { inputs } => selected(inputs)
+            .reduce(|sum, value| sum + value)
+            .unwrap_or(f32::NAN),
+
+        // Every term is required, dropping a term such as the subtracted
+        // sensor of a difference would give a plausible but wrong value
+        VirtualFormula::Weighted { terms, offset } => terms
+            .iter()
+            .map(|term| {
+                samples
+                    .iter()
+                    .find(|sample| sample.identifier == term.sensor_id)
+                    .map(|sample| sample.value * term.weight)
+                    .filter(|value| !value.is_nan())
+            })
+            .try_fold(0.0, |sum, value| Some(sum + value?))
+            .filter(|_| !terms.is_empty())
+            .map(|sum| sum + offset)
+            .unwrap_or(f32::NAN),
+    }
+}
+
+/// Values of the sensors selected by any of the selectors, ignoring
+/// sensors without a value
+fn selected_values<'a>(
+    inputs: &'a [SensorSelector],
+    samples: &'a [SensorSample],
+) -> impl Iterator<Item = f32> + 'a {
+    samples
+        .iter()
+        .filter(|sample| !sample.value.is_nan())
+        .filter(|sample| inputs.iter().any(|selector| sample.is_selected(selector)))
+        .map(|sample| sample.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VIRTUAL_INDEX_START, VirtualSensors};
+    use crate::sample::SensorSample;
+    use lhm_shared::{
+        HardwareType, SensorType,
+        alert::SensorSelector,
+        virtual_sensor::{VirtualFormula, VirtualSensor, WeightedTerm},
+    };
+    use std::time::Instant;
+
+    fn core_temps(values: &[f32]) -> Vec<SensorSample> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| SensorSample {
+                identifier: format!("/intelcpu/0/temperature/{index}"),
+                ty: SensorType::Temperature,
+                hardware_identifier: "/intelcpu/0".to_string(),
+                hardware_ty: HardwareType::Cpu,
+                value: *value,
+            })
+            .collect()
+    }
+
+    fn cpu_temps() -> Vec<SensorSelector> {
+        vec![SensorSelector::Type {
+            ty: SensorType::Temperature,
+            hardware_ty: Some(HardwareType::Cpu),
+        }]
+    }
+
+    fn virtual_sensor(id: &str, formula: VirtualFormula) -> VirtualSensor {
+        VirtualSensor {
+            id: id.to_string(),
+            name: id.to_string(),
+            ty: SensorType::Temperature,
+            formula,
+        }
+    }
+
+    fn evaluate(formula: VirtualFormula, samples: &[SensorSample]) -> f32 {
+        let mut sensors = VirtualSensors::default();
+        let (index, _) = sensors.add(virtual_sensor("test", formula));
+        sensors.evaluate(Instant::now(), samples);
+        sensors.get_by_idx(index).unwrap().value
+    }
+
+    #[test]
+    fn test_aggregates_ignore_missing_values() {
+        let samples = core_temps(&[40.0, f32::NAN, 60.0, 50.0]);
+
+        let inputs = cpu_temps();
+        assert_eq!(evaluate(VirtualFormula::Average { inputs }, &samples), 50.0);
+
+        let inputs = cpu_temps();
+        assert_eq!(evaluate(VirtualFormula::Min { inputs }, &samples), 40.0);
+
+        let inputs = cpu_temps();
+        assert_eq!(evaluate(VirtualFormula::Max { inputs }, &samples), 60.0);
+
+        let inputs = cpu_temps();
+        assert_eq!(evaluate(VirtualFormula::Sum { inputs }, &samples), 150.0);
+    }
+
+    #[test]
+    fn test_no_values_is_nan() {
+        let samples = core_temps(&[f32::NAN]);
+
+        let inputs = cpu_temps();
+        assert!(evaluate(VirtualFormula::Average { inputs }, &samples).is_nan());
+
+        let inputs = cpu_temps();
+        assert!(evaluate(VirtualFormula::Sum { inputs }, &[]).is_nan());
+
+        let inputs = cpu_temps();
+        assert!(evaluate(VirtualFormula::Min { inputs }, &[]).is_nan());
+    }
+
+    #[test]
+    fn test_weighted_sum_with_offset() {
+        let samples = core_temps(&[40.0, 60.0]);
+        let formula = VirtualFormula::Weighted {
+            terms: vec![
+                WeightedTerm {
+                    sensor_id: "/intelcpu/0/temperature/0".to_string(),
+                    weight: 0.5,
+                },
+                WeightedTerm {
+                    sensor_id: "/intelcpu/0/temperature/1".to_string(),
+                    weight: 2.0,
+                },
+            ],
+            offset: -5.0,
+        };
+
+        assert_eq!(evaluate(formula, &samples), 135.0);
+    }
+
+    #[test]
+    fn test_weighted_missing_term_is_nan() {
+        let difference = |second: &str| VirtualFormula::Weighted {
+            terms: vec![
+                WeightedTerm {
+                    sensor_id: "/intelcpu/0/temperature/0".to_string(),
+                    weight: 1.0,
+                },
+                WeightedTerm {
+                    sensor_id: second.to_string(),
+                    weight: -1.0,
+                },
+            ],
+            offset: 0.0,
+        };
+
+        // Sensor without a value
+        let samples = core_temps(&[40.0, f32::NAN]);
+        assert!(evaluate(difference("/intelcpu/0/temperature/1"), &samples).is_nan());
+
+        // Sensor that is not loaded
+        let samples = core_temps(&[40.0, 60.0]);
+        assert!(evaluate(difference("/missing"), &samples).is_nan());
+        assert_eq!(
+            evaluate(difference("/intelcpu/0/temperature/1"), &samples),
+            -20.0
+        );
+
+        // No terms
+        let formula = VirtualFormula::Weighted {
+            terms: Vec::new(),
+            offset: 5.0,
+        };
+        assert!(evaluate(formula, &samples).is_nan());
+    }
+
+    #[test]
+    fn test_indexes_remain_stable() {
+        let mut sensors = VirtualSensors::default();
+        let (first, _) = sensors.add(virtual_sensor(
+            "a",
+            VirtualFormula::Sum {
+                inputs: cpu_temps(),
+            },
+        ));
+        let (second, _) = sensors.add(virtual_sensor(
+            "b",
+            VirtualFormula::Sum {
+                inputs: cpu_temps(),
+            },
+        ));
+
+        assert_eq!(first, VIRTUAL_INDEX_START);
+        assert_eq!(second, VIRTUAL_INDEX_START + 1);
+
+        // Replacing keeps the index
+        let (replaced, existing) = sensors.add(virtual_sensor(
+            "a",
+            VirtualFormula::Max {
+                inputs: cpu_temps(),
+            },
+        ));
+        assert_eq!(replaced, first);
+        assert!(existing);
+
+        // Removing does not move the other sensors
+        assert_eq!(sensors.remove("a"), Some((first, "/virtual/a".to_string())));
+        assert_eq!(sensors.get_index("/virtual/b"), Some(second));
+        assert!(sensors.get_by_idx(first).is_none());
+        assert!(sensors.remove("a").is_none());
+    }
+
+    #[test]
+    fn test_tracks_min_max() {
+        let mut sensors = VirtualSensors::default();
+        let (index, _) = sensors.add(virtual_sensor(
+            "avg",
+            VirtualFormula::Average {
+                inputs: cpu_temps(),
+            },
+        ));
+
+        for value in [50.0, 70.0, f32::NAN, 30.0, 40.0] {
+            sensors.evaluate(Instant::now(), &core_temps(&[value]));
+        }
+
+        let entry = sensors.get_by_idx_mut(index).unwrap();
+        assert_eq!((entry.value, entry.min, entry.max), (40.0, 30.0, 70.0));
+
+        entry.reset_min_max();
+        assert_eq!((entry.min, entry.max), (40.0, 40.0));
+    }
+}
@@ -0,0 +1,336 @@
+//! WebSocket endpoint served by the gateway at `/ws`, allowing clients such
+//! as browsers to make requests using JSON encoded messages
+//!
+//! Each text message sent by the client is a [WebSocketRequest] containing
+//! a JSON encoded [PipeRequest]. Responses are sent as a [WebSocketResponse]
+//! using the ID of the request:
+//!
+//! ```json
+//! {"id": 1, "request": {"type": "QuerySensors", "parent_id": null, "ty": "Temperature"}}
+//! {"id": 1, "response": {"type": "Sensors", "sensors": [...]}}
+//! ```
+//!
+//! Requests are handled in the same way as requests from the pipe, a
+//! [PipeRequest::Subscribe] request pushes the sensor values at the requested
+//! interval using the ID of the request until a [PipeRequest::Unsubscribe]
+//! request is sent or the socket is closed
+//!
+//! Browsers allow any web page to connect to the loopback address, so
+//! connections from web pages are only accepted when their `Origin` is
+//! within [GatewayConfig::websocket_allowed_origins]
+//!
+//! [GatewayConfig::websocket_allowed_origins]: crate::gateway::GatewayConfig::websocket_allowed_origins
+
+use crate::{
+    Connection, ResponseTx,
+    gateway::{GatewayState, error_response},
+    handle_message, next_connection_id,
+};
+use axum::{
+    extract::{
+        State, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    http::{HeaderMap, header},
+    response::Response,
+};
+use futures_util::{SinkExt, StreamExt};
+use lhm_shared::{ErrorCode, PipeRequest, PipeResponse};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::{spawn, sync::mpsc};
+
+/// Message sent by the client
+#[derive(Deserialize)]
+pub struct WebSocketRequest {
+    /// ID of the request, used as the ID of the response
+    pub id: u32,
+    /// The request itself, decoded separately so the ID is still
+    /// available for the response when the request is invalid
+    pub request: serde_json::Value,
+}
+
+/// Message sent to the client
+#[derive(Serialize)]
+pub struct WebSocketResponse {
+    /// ID of the request the response is for, or the ID of the
+    /// subscription for subscription values
+    pub id: u32,
+    /// The response itself
+    pub response: PipeResponse,
+}
+
+pub(crate) async fn upgrade_websocket(
+    State(state): State<GatewayState>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    if !is_origin_allowed(&headers, &state.websocket_allowed_origins) {
+        return error_response(
+            ErrorCode::PermissionDenied,
+            "origin is not allowed".to_string(),
+        );
+    }
+
+    upgrade.on_upgrade(move |socket| handle_websocket(socket, state))
+}
+
+/// Check if the origin of an upgrade request is allowed, requests without
+/// an origin are not made by a web page and are always allowed
+fn is_origin_allowed(headers: &HeaderMap, allowed_origins: &[String]) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+
+    origin.to_str().is_ok_and(|origin| {
+        allowed_origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+    })
+}
+
+async fn handle_websocket(socket: WebSocket, state: GatewayState) {
+    let (mut sink, mut stream) = socket.split();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let connection = Arc::new(Connection {
+        id: next_connection_id(),
+        permission: state.websocket_permission,
+        max_subscriptions: state.websocket_max_subscriptions,
+        handle: state.handle,
+        tx: ResponseTx::WebSocket(tx),
+        subscriptions: Default::default(),
+    });
+
+    // Spawn task to write the responses to the socket
+    spawn(async move {
+        while let Some((id, response)) = rx.recv().await {
+            let text = encode_response(id, response);
+            if sink.send(Message::Text(text.into())).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    while let Some(Ok(message)) = stream.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            // Pings are answered automatically
+            _ => continue,
+        };
+
+        let (id, request) = match serde_json::from_str::<WebSocketRequest>(text.as_str()) {
+            Ok(WebSocketRequest { id, request }) => (
+                id,
+                PipeRequest::deserialize(request).map_err(|err| err.to_string()),
+            ),
+            // Message is missing an ID, respond using the zero ID
+            Err(err) => (0, Err(err.to_string())),
+        };
+
+        spawn(handle_message(id, request, connection.clone()));
+    }
+}
+
+/// Encodes a response as JSON, responses that cannot be encoded are
+/// replaced with an error so the client still receives a response
+fn encode_response(id: u32, response: PipeResponse) -> String {
+    serde_json::to_string(&WebSocketResponse { id, response }).unwrap_or_else(|err| {
+        let response = WebSocketResponse {
+            id,
+            response: PipeResponse::error(
+                ErrorCode::Internal,
+                format!("failed to encode response: {err}"),
+            ),
+        };
+
+        serde_json::to_string(&response).unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::upgrade_websocket;
+    use crate::{gateway::GatewayState, test_endpoint_handle};
+    use axum::{Router, routing::get};
+    use futures_util::{SinkExt, StreamExt};
+    use lhm_shared::Permission;
+    use serde_json::{Value, json};
+    use std::{collections::HashMap, net::SocketAddr};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::{
+        MaybeTlsStream, WebSocketStream, connect_async,
+        tungstenite::{self, Message, client::IntoClientRequest},
+    };
+
+    type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    /// Serves the WebSocket endpoint on a random port, allowing
+    /// the provided origins
+    async fn serve(allowed_origins: &[&str]) -> SocketAddr {
+        let handle = test_endpoint_handle().await;
+
+        let app = Router::new()
+            .route("/ws", get(upgrade_websocket))
+            .with_state(GatewayState {
+                connection_id: 0,
+                handle,
+                websocket_permission: Permission::Read,
+                websocket_max_subscriptions: crate::DEFAULT_MAX_SUBSCRIPTIONS,
+                websocket_allowed_origins: allowed_origins
+                    .iter()
+                    .map(|origin| origin.to_string())
+                    .collect(),
+            });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        address
+    }
+
+    async fn connect(
+        address: SocketAddr,
+        origin: Option<&str>,
+    ) -> Result<Socket, tungstenite::Error> {
+        let mut request = format!("ws://{address}/ws").into_client_request().unwrap();
+        if let Some(origin) = origin {
+            request
+                .headers_mut()
+                .insert("origin", origin.parse().unwrap());
+        }
+
+        connect_async(request).await.map(|(socket, _)| socket)
+    }
+
+    async fn send(socket: &mut Socket, message: Value) {
+        socket
+            .send(Message::Text(message.to_string().into()))
+            .await
+            .unwrap();
+    }
+
+    async fn recv(socket: &mut Socket) -> Value {
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                Message::Text(text) => return serde_json::from_str(text.as_str()).unwrap(),
+                _ => continue,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_id_correlation() {
+        let address = serve(&[]).await;
+        let mut socket = connect(address, None).await.unwrap();
+
+        send(
+            &mut socket,
+            json!({ "id": 7, "request": { "type": "GetCacheGeneration" } }),
+        )
+        .await;
+        send(
+            &mut socket,
+            json!({ "id": 3, "request": { "type": "NotARequest" } }),
+        )
+        .await;
+        send(
+            &mut socket,
+            json!({ "request": { "type": "GetCacheGeneration" } }),
+        )
+        .await;
+
+        // Requests are handled concurrently so responses may be out of order
+        let mut responses = HashMap::new();
+        for _ in 0..3 {
+            let message = recv(&mut socket).await;
+            let id = message["id"].as_u64().unwrap();
+            responses.insert(id, message["response"].clone());
+        }
+
+        assert_eq!(responses[&7]["type"], "CacheGeneration");
+        assert_eq!(responses[&3]["type"], "Error");
+        assert_eq!(responses[&3]["code"], "InvalidRequest");
+
+        // Messages without an ID are answered using the zero ID
+        assert_eq!(responses[&0]["code"], "InvalidRequest");
+    }
+
+    #[tokio::test]
+    async fn test_subscription_push() {
+        let address = serve(&[]).await;
+        let mut socket = connect(address, None).await.unwrap();
+
+        // Load the hardware into the cache
+        send(
+            &mut socket,
+            json!({ "id": 1, "request": { "type": "UpdateAll" } }),
+        )
+        .await;
+        assert_eq!(
+            recv(&mut socket).await,
+            json!({ "id": 1, "response": { "type": "Success" } })
+        );
+
+        let subscribe = json!({
+            "id": 5,
+            "request": {
+                "type": "Subscribe",
+                "sensor_ids": ["/intelcpu/0/temperature/0"],
+                "interval_ms": 100
+            }
+        });
+        send(&mut socket, subscribe).await;
+
+        assert_eq!(
+            recv(&mut socket).await,
+            json!({ "id": 5, "response": { "type": "Subscribed", "subscription_id": 5 } })
+        );
+
+        // Values are pushed using the subscription ID
+        for _ in 0..2 {
+            let message = recv(&mut socket).await;
+            assert_eq!(message["id"], 5);
+            assert_eq!(message["response"]["type"], "SubscriptionValues");
+
+            let value = &message["response"]["values"][0];
+            assert_eq!(value["identifier"], "/intelcpu/0/temperature/0");
+            assert_eq!(value["value"], 55.0);
+        }
+
+        send(
+            &mut socket,
+            json!({ "id": 6, "request": { "type": "Unsubscribe", "subscription_id": 5 } }),
+        )
+        .await;
+
+        loop {
+            let message = recv(&mut socket).await;
+            if message["id"] == 6 {
+                assert_eq!(message["response"]["type"], "Success");
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_origin_allow_list() {
+        let address = serve(&["http://localhost:3000"]).await;
+
+        assert!(
+            connect(address, Some("http://localhost:3000"))
+                .await
+                .is_ok()
+        );
+        assert!(connect(address, None).await.is_ok());
+
+        match connect(address, Some("http://example.com")).await {
+            Err(tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), 403);
+            }
+            Ok(_) => panic!("expected the origin to be rejected"),
+            Err(err) => panic!("unexpected error {err}"),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Configuration for the service loaded from [CONFIG_FILE_NAME] in the
+//! directory of the service executable
+
+use anyhow::Context;
+use lhm_server::permission::{LOCAL_SYSTEM_SID, Permission, PermissionRule, PermissionRules};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Name of the config file
+pub const CONFIG_FILE_NAME: &str = "lhm-service.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ServiceConfig {
+    /// Permissions for connecting clients
+    pub permissions: PermissionsConfig,
+}
+
+/// Permissions granted to connecting clients
+///
+/// ```toml
+/// [permissions]
+/// default = "read"
+///
+/// [[permissions.rules]]
+/// user = "S-1-5-21-1004336348-1177238915-682003330-1001"
+/// permission = "control"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PermissionsConfig {
+    /// Permission for clients that do not match any rule
+    pub default: Permission,
+    /// Rules for specific users identified by SID
+    pub rules: Vec<PermissionRuleConfig>,
+}
+
+impl Default for PermissionsConfig {
+    /// Clients may only read, the local system account
+    /// may configure and control hardware
+    fn default() -> Self {
+        Self {
+            default: Permission::Read,
+            rules: vec![PermissionRuleConfig {
+                user: LOCAL_SYSTEM_SID.to_string(),
+                permission: Permission::Control,
+            }],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PermissionRuleConfig {
+    /// SID of the user
+    pub user: String,
+    /// Permission granted to the user
+    pub permission: Permission,
+}
+
+impl ServiceConfig {
+    /// Load the config from `path`, the default config is
+    /// used when the file does not exist
+    pub fn load(path: &Path) -> anyhow::Result<ServiceConfig> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(value) => value,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(ServiceConfig::default());
+            }
+            Err(err) => return Err(err).context("failed to read config file"),
+        };
+
+        toml::from_str(&contents).context("failed to parse config file")
+    }
+}
+
+impl PermissionsConfig {
+    /// Create the permission policy for the server
+    pub fn into_policy(self) -> PermissionRules {
+        PermissionRules {
+            default: self.default,
+            rules: self
+                .rules
+                .into_iter()
+                .map(|rule| PermissionRule {
+                    user: rule.user,
+                    permission: rule.permission,
+                })
+                .collect(),
+        }
+    }
+}
@@ -7,8 +7,12 @@
 //! Also includes logic for managing the service (Adding, Removing, and Restarting the service)
 
 use anyhow::Context;
+use config::{CONFIG_FILE_NAME, ServiceConfig};
+use lhm_server::ServerConfig;
 use std::env;
 use std::ffi::OsString;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::task::LocalSet;
@@ -18,6 +22,8 @@ use windows_service::service::{
 use windows_service::service_control_handler;
 use windows_service::service_control_handler::ServiceControlHandlerResult;
 
+mod config;
+
 /// Name of the windows service
 pub const SERVICE_NAME: &str = "LibreHardwareMonitorService";
 
@@ -76,6 +82,13 @@ fn setup_working_directory() -> anyhow::Result<()> {
 
 /// Runs the service and handles service events
 fn run_service() -> anyhow::Result<()> {
+    // Load the config from the working directory (The executable directory)
+    let config = ServiceConfig::load(Path::new(CONFIG_FILE_NAME))?;
+    let server_config = ServerConfig {
+        policy: Arc::new(config.permissions.into_policy()),
+        ..Default::default()
+    };
+
     // Create a channel to be able to poll a stop event from the service worker loop.
     let (shutdown_tx, mut shutdown_rx) = mpsc::channel(1);
 
@@ -126,7 +139,7 @@ fn run_service() -> anyhow::Result<()> {
         .expect("Failed building the Runtime");
 
     let local_set = LocalSet::new();
-    local_set.spawn_local(lhm_server::run_server());
+    local_set.spawn_local(lhm_server::run_server_with_config(server_config));
 
     // Block waiting for shutdown
     runtime.block_on(local_set.run_until(async move {
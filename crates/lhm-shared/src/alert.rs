@@ -0,0 +1,119 @@
+//! Threshold alert rules evaluated by the server, registered through
+//! [PipeRequest::AddAlertRule](crate::PipeRequest::AddAlertRule)
+
+use crate::{HardwareType, SensorType};
+use serde::{Deserialize, Serialize};
+
+/// Rule describing when an alert fires for a sensor
+///
+/// Each sensor matched by the `selector` is tracked separately, an alert
+/// fires for a sensor once its value has passed the `threshold` for at
+/// least `duration_ms` and clears once the value has moved back past the
+/// threshold by at least `hysteresis`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    /// Unique name of the rule, adding a rule with the same name
+    /// replaces the existing rule
+    pub name: String,
+
+    /// Sensors the rule applies to
+    pub selector: SensorSelector,
+
+    /// How the sensor value is compared against the threshold
+    pub comparison: AlertComparison,
+
+    /// Value the sensor must pass for the alert to fire
+    pub threshold: f32,
+
+    /// Milliseconds the value must stay past the threshold before the
+    /// alert fires
+    #[serde(default)]
+    pub duration_ms: u64,
+
+    /// Amount the value must move back past the threshold before the
+    /// alert clears, prevents alerts repeatedly firing and clearing
+    /// while the value hovers around the threshold
+    #[serde(default)]
+    pub hysteresis: f32,
+
+    /// Milliseconds after the alert clears before it can fire again
+    #[serde(default)]
+    pub cooldown_ms: u64,
+}
+
+impl AlertRule {
+    /// Check if a value passes the threshold
+    pub fn is_triggered(&self, value: f32) -> bool {
+        match self.comparison {
+            AlertComparison::Above => value > self.threshold,
+            AlertComparison::Below => value < self.threshold,
+        }
+    }
+
+    /// Check if a value has moved back past the threshold by the hysteresis
+    pub fn is_cleared(&self, value: f32) -> bool {
+        match self.comparison {
+            AlertComparison::Above => value <= self.threshold - self.hysteresis,
+            AlertComparison::Below => value >= self.threshold + self.hysteresis,
+        }
+    }
+}
+
+/// Comparison between a sensor value and the threshold of an [AlertRule]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertComparison {
+    /// Fires when the value is above the threshold
+    Above,
+    /// Fires when the value is below the threshold
+    Below,
+}
+
+/// Selects the sensors an [AlertRule] applies to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SensorSelector {
+    /// Specific sensor by identifier
+    Sensor { id: String },
+
+    /// Sensors belonging to a specific hardware item by identifier,
+    /// optionally only sensors of a specific type
+    Hardware { id: String, ty: Option<SensorType> },
+
+    /// Sensors of a specific type, optionally only those belonging
+    /// to hardware of a specific type
+    Type {
+        ty: SensorType,
+        hardware_ty: Option<HardwareType>,
+    },
+}
+
+impl SensorSelector {
+    /// Check if a sensor is selected
+    ///
+    /// `identifier` Identifier of the sensor
+    /// `ty` Type of the sensor
+    /// `hardware_identifier` Identifier of the hardware the sensor belongs to
+    /// `hardware_ty` Type of the hardware the sensor belongs to
+    pub fn matches(
+        &self,
+        identifier: &str,
+        ty: SensorType,
+        hardware_identifier: &str,
+        hardware_ty: HardwareType,
+    ) -> bool {
+        match self {
+            SensorSelector::Sensor { id } => id == identifier,
+            SensorSelector::Hardware { id, ty: filter_ty } => {
+                id == hardware_identifier && filter_ty.is_none_or(|filter_ty| filter_ty == ty)
+            }
+            SensorSelector::Type {
+                ty: filter_ty,
+                hardware_ty: filter_hardware_ty,
+            } => {
+                *filter_ty == ty
+                    && filter_hardware_ty.is_none_or(|filter_ty| filter_ty == hardware_ty)
+            }
+        }
+    }
+}
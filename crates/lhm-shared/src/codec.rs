@@ -1,8 +1,20 @@
+use thiserror::Error;
 use tokio_util::{
     bytes::{Buf, BufMut, Bytes, BytesMut},
     codec::{Decoder, Encoder},
 };
 
+/// Size in bytes of the frame header (id + length)
+pub const FRAME_HEADER_LENGTH: usize = 8;
+
+/// Default maximum length in bytes of a frame body (16 MiB)
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Maximum number of bytes reserved ahead of a partially received frame
+/// body, larger bodies grow the buffer as the bytes arrive
+const MAX_BODY_RESERVE: usize = 64 * 1024;
+
+#[derive(Debug)]
 pub struct LHMFrame {
     pub id: u32,
     pub body: Bytes,
@@ -16,7 +28,7 @@ pub struct LHMFrameHeader {
 impl LHMFrameHeader {
     pub fn try_decode(src: &mut BytesMut) -> Option<LHMFrameHeader> {
         // Ensure we have the full required header bytes
-        if src.len() < 8 {
+        if src.len() < FRAME_HEADER_LENGTH {
             return None;
         }
 
@@ -26,15 +38,78 @@ impl LHMFrameHeader {
     }
 }
 
-#[derive(Default)]
+/// Errors that can occur while encoding or decoding frames
+#[derive(Debug, Error)]
+pub enum LHMCodecError {
+    /// Frame length exceeded the maximum allowed frame length
+    #[error("frame length {length} exceeds the maximum of {max_length}")]
+    FrameTooLarge { length: usize, max_length: usize },
+
+    /// Stream ended part way through a frame header
+    #[error("stream ended with a truncated frame header ({available} of 8 bytes)")]
+    TruncatedHeader { available: usize },
+
+    /// Stream ended part way through a frame body or a previous error
+    /// left the stream in an unknown state
+    #[error("frame stream is corrupted")]
+    CorruptedStream,
+
+    /// Error from the underlying IO stream
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
 pub struct LHMFrameCodec {
+    /// Header of the frame currently being decoded
     header: Option<LHMFrameHeader>,
+    /// Maximum allowed length of a frame body
+    max_frame_length: usize,
+    /// Whether a previous decode error has left the stream in an
+    /// unknown state
+    corrupted: bool,
+}
+
+impl Default for LHMFrameCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_LENGTH)
+    }
+}
+
+impl LHMFrameCodec {
+    /// Create a new codec that will reject frames with a body
+    /// longer than `max_frame_length`
+    pub fn new(max_frame_length: usize) -> Self {
+        Self {
+            header: None,
+            max_frame_length,
+            corrupted: false,
+        }
+    }
+
+    /// Get the maximum allowed length of a frame body
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    fn check_length(&self, length: usize) -> Result<(), LHMCodecError> {
+        if length > self.max_frame_length || length > u32::MAX as usize {
+            return Err(LHMCodecError::FrameTooLarge {
+                length,
+                max_length: self.max_frame_length,
+            });
+        }
+
+        Ok(())
+    }
 }
 
 impl Encoder<LHMFrame> for LHMFrameCodec {
-    type Error = std::io::Error;
+    type Error = LHMCodecError;
 
     fn encode(&mut self, item: LHMFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.check_length(item.body.len())?;
+
+        dst.reserve(FRAME_HEADER_LENGTH + item.body.len());
         dst.put_u32(item.id);
         dst.put_u32(item.body.len() as u32);
         dst.extend_from_slice(&item.body);
@@ -44,9 +119,14 @@ impl Encoder<LHMFrame> for LHMFrameCodec {
 
 impl Decoder for LHMFrameCodec {
     type Item = LHMFrame;
-    type Error = std::io::Error;
+    type Error = LHMCodecError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // Stream position is unknown after an error, nothing more can be read
+        if self.corrupted {
+            return Err(LHMCodecError::CorruptedStream);
+        }
+
         let header = match self.header.as_mut() {
             Some(value) => value,
             None => match LHMFrameHeader::try_decode(src) {
@@ -57,8 +137,15 @@ impl Decoder for LHMFrameCodec {
 
         let length = header.length as usize;
 
+        if let Err(err) = self.check_length(length) {
+            self.header = None;
+            self.corrupted = true;
+            return Err(err);
+        }
+
         // Not enough bytes for the whole message
         if src.len() < length {
+            src.reserve((length - src.len()).min(MAX_BODY_RESERVE));
             return Ok(None);
         }
 
@@ -74,4 +161,206 @@ impl Decoder for LHMFrameCodec {
             body: bytes.freeze(),
         }))
     }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(frame) = self.decode(src)? {
+            return Ok(Some(frame));
+        }
+
+        // Stream ended part way through a frame body
+        if self.header.is_some() {
+            self.corrupted = true;
+            return Err(LHMCodecError::CorruptedStream);
+        }
+
+        // Stream ended part way through a frame header
+        if !src.is_empty() {
+            self.corrupted = true;
+            return Err(LHMCodecError::TruncatedHeader {
+                available: src.len(),
+            });
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DEFAULT_MAX_FRAME_LENGTH, FRAME_HEADER_LENGTH, LHMCodecError, LHMFrame, LHMFrameCodec,
+        MAX_BODY_RESERVE,
+    };
+    use tokio_util::{
+        bytes::{BufMut, Bytes, BytesMut},
+        codec::{Decoder, Encoder},
+    };
+
+    /// Creates the encoded header for a frame
+    fn header(id: u32, length: u32) -> BytesMut {
+        let mut buffer = BytesMut::new();
+        buffer.put_u32(id);
+        buffer.put_u32(length);
+        buffer
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut codec = LHMFrameCodec::default();
+        let mut buffer = BytesMut::new();
+
+        for (id, body) in [(1, &b"hello"[..]), (2, &b""[..]), (u32::MAX, &b"world"[..])] {
+            codec
+                .encode(
+                    LHMFrame {
+                        id,
+                        body: Bytes::from_static(body),
+                    },
+                    &mut buffer,
+                )
+                .unwrap();
+        }
+
+        for (id, body) in [(1, &b"hello"[..]), (2, &b""[..]), (u32::MAX, &b"world"[..])] {
+            let frame = codec.decode(&mut buffer).unwrap().unwrap();
+            assert_eq!(frame.id, id);
+            assert_eq!(&frame.body[..], body);
+        }
+
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_partial_header() {
+        let mut codec = LHMFrameCodec::default();
+        let mut buffer = header(7, 3);
+        let rest = buffer.split_off(FRAME_HEADER_LENGTH - 3);
+
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+
+        // Remainder of the header without the body
+        buffer.extend_from_slice(&rest);
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(b"abc");
+        let frame = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(frame.id, 7);
+        assert_eq!(&frame.body[..], b"abc");
+    }
+
+    #[test]
+    fn test_encode_too_large() {
+        let mut codec = LHMFrameCodec::default();
+        let mut buffer = BytesMut::new();
+
+        let err = codec
+            .encode(
+                LHMFrame {
+                    id: 1,
+                    body: Bytes::from(vec![0; DEFAULT_MAX_FRAME_LENGTH + 1]),
+                },
+                &mut buffer,
+            )
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            LHMCodecError::FrameTooLarge { length, max_length }
+                if length == DEFAULT_MAX_FRAME_LENGTH + 1 && max_length == DEFAULT_MAX_FRAME_LENGTH
+        ));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_decode_too_large() {
+        let mut codec = LHMFrameCodec::default();
+        let mut buffer = header(1, DEFAULT_MAX_FRAME_LENGTH as u32 + 1);
+
+        let err = codec.decode(&mut buffer).unwrap_err();
+        assert!(matches!(
+            err,
+            LHMCodecError::FrameTooLarge { length, .. } if length == DEFAULT_MAX_FRAME_LENGTH + 1
+        ));
+    }
+
+    #[test]
+    fn test_decode_max_length() {
+        let mut codec = LHMFrameCodec::new(4);
+        let mut buffer = header(1, 4);
+        buffer.extend_from_slice(b"full");
+
+        let frame = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(&frame.body[..], b"full");
+    }
+
+    #[test]
+    fn test_decode_limits_reserve() {
+        let mut codec = LHMFrameCodec::default();
+        let mut buffer = header(1, DEFAULT_MAX_FRAME_LENGTH as u32);
+
+        // Header alone must not reserve the whole claimed body
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+        assert!(buffer.capacity() < 2 * MAX_BODY_RESERVE);
+
+        buffer.extend_from_slice(&vec![0; DEFAULT_MAX_FRAME_LENGTH]);
+        let frame = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(frame.body.len(), DEFAULT_MAX_FRAME_LENGTH);
+    }
+
+    #[test]
+    fn test_eof_truncated_header() {
+        let mut codec = LHMFrameCodec::default();
+        let mut buffer = BytesMut::from(&[0, 0, 0][..]);
+
+        let err = codec.decode_eof(&mut buffer).unwrap_err();
+        assert!(matches!(
+            err,
+            LHMCodecError::TruncatedHeader { available: 3 }
+        ));
+    }
+
+    #[test]
+    fn test_eof_truncated_body() {
+        let mut codec = LHMFrameCodec::default();
+        let mut buffer = header(1, 10);
+        buffer.extend_from_slice(b"short");
+
+        let err = codec.decode_eof(&mut buffer).unwrap_err();
+        assert!(matches!(err, LHMCodecError::CorruptedStream));
+    }
+
+    #[test]
+    fn test_eof_clean() {
+        let mut codec = LHMFrameCodec::default();
+        let mut buffer = header(3, 2);
+        buffer.extend_from_slice(b"ok");
+
+        let frame = codec.decode_eof(&mut buffer).unwrap().unwrap();
+        assert_eq!(frame.id, 3);
+        assert!(codec.decode_eof(&mut buffer).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_corrupted_is_sticky() {
+        let mut codec = LHMFrameCodec::new(4);
+        let mut buffer = header(1, 5);
+
+        assert!(matches!(
+            codec.decode(&mut buffer),
+            Err(LHMCodecError::FrameTooLarge { .. })
+        ));
+
+        // Valid frames after the error are still rejected
+        let mut buffer = header(2, 2);
+        buffer.extend_from_slice(b"ok");
+        assert!(matches!(
+            codec.decode(&mut buffer),
+            Err(LHMCodecError::CorruptedStream)
+        ));
+        assert!(matches!(
+            codec.decode_eof(&mut buffer),
+            Err(LHMCodecError::CorruptedStream)
+        ));
+    }
 }
@@ -0,0 +1,87 @@
+//! Portable description of a hardware and sensor tree along with the values
+//! each sensor produces, loaded by the simulated server backend and written
+//! when recording a live session
+
+use crate::{HardwareType, SensorType};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Fixture describing the simulated hardware
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    /// Seed for the random walk curves, the same seed will
+    /// produce the same values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+
+    /// Top level hardware
+    pub hardware: Vec<FixtureHardware>,
+}
+
+/// Hardware item within a [Fixture]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureHardware {
+    pub identifier: String,
+    pub name: String,
+    pub ty: HardwareType,
+    #[serde(default)]
+    pub children: Vec<FixtureHardware>,
+    #[serde(default)]
+    pub sensors: Vec<FixtureSensor>,
+}
+
+/// Sensor item within a [Fixture]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureSensor {
+    pub identifier: String,
+    pub name: String,
+    pub ty: SensorType,
+    pub curve: Curve,
+}
+
+/// Scripted curve used to generate the values for a sensor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Curve {
+    /// Value that never changes
+    Constant { value: f32 },
+
+    /// Sine wave oscillating between `min` and `max`
+    Sine {
+        min: f32,
+        max: f32,
+        period_ms: u64,
+        #[serde(default)]
+        phase_ms: u64,
+    },
+
+    /// Value that moves randomly by up to `step` each update, staying
+    /// between `min` and `max`
+    RandomWalk {
+        start: f32,
+        step: f32,
+        min: f32,
+        max: f32,
+    },
+
+    /// Replay of a recorded CSV file with rows of `elapsed_ms,value`
+    /// optionally preceded by a header row, the path is relative to
+    /// the fixture file
+    Replay {
+        path: PathBuf,
+        #[serde(default = "default_repeat")]
+        repeat: bool,
+    },
+
+    /// Replay of `[elapsed_ms, value]` samples stored within the fixture
+    /// itself, used by recorded sessions
+    Samples {
+        samples: Vec<(u64, f32)>,
+        #[serde(default = "default_repeat")]
+        repeat: bool,
+    },
+}
+
+fn default_repeat() -> bool {
+    true
+}
@@ -1,11 +1,62 @@
+use alert::AlertRule;
 use num_enum::{FromPrimitive, IntoPrimitive};
 use serde::{Deserialize, Serialize};
+use snapshot::{HardwareNode, Snapshot, SnapshotFilter};
+use std::net::SocketAddr;
+use virtual_sensor::VirtualSensor;
 
+#[cfg(unix)]
+use std::path::PathBuf;
+
+pub mod alert;
 pub mod codec;
+pub mod fixture;
+pub mod pipe;
+pub mod snapshot;
+pub mod virtual_sensor;
 
 pub const PIPE_NAME: &str = r"\\.\pipe\LHMLibreHardwareMonitorService";
 
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+/// Default path of the Unix domain socket
+#[cfg(unix)]
+pub const SOCKET_PATH: &str = "/tmp/lhm-service.sock";
+
+/// Version of the protocol spoken over the pipe, this is only changed when
+/// a change is made that older clients or servers are unable to understand.
+///
+/// Additive features are advertised through [Capability] instead
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Transport used for communication between the client and server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    /// Windows named pipe at the provided path
+    #[cfg(windows)]
+    NamedPipe(String),
+
+    /// Unix domain socket at the provided path
+    #[cfg(unix)]
+    UnixSocket(PathBuf),
+
+    /// TCP socket, servers will only listen on loopback addresses
+    Tcp(SocketAddr),
+}
+
+impl Default for Transport {
+    /// Named pipe at [PIPE_NAME]
+    #[cfg(windows)]
+    fn default() -> Self {
+        Transport::NamedPipe(PIPE_NAME.to_string())
+    }
+
+    /// Unix domain socket at [SOCKET_PATH]
+    #[cfg(unix)]
+    fn default() -> Self {
+        Transport::UnixSocket(PathBuf::from(SOCKET_PATH))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct ComputerOptions {
     pub battery_enabled: bool,
     pub controller_enabled: bool,
@@ -18,9 +69,46 @@ pub struct ComputerOptions {
     pub storage_enabled: bool,
 }
 
-#[derive(Deserialize, Serialize)]
+impl ComputerOptions {
+    /// Create options with every hardware group enabled
+    pub fn all() -> ComputerOptions {
+        ComputerOptions {
+            battery_enabled: true,
+            controller_enabled: true,
+            cpu_enabled: true,
+            gpu_enabled: true,
+            memory_enabled: true,
+            motherboard_enabled: true,
+            network_enabled: true,
+            psu_enabled: true,
+            storage_enabled: true,
+        }
+    }
+
+    /// Create options with each hardware group enabled if it
+    /// is enabled in either `self` or `other`
+    pub fn union(&self, other: &ComputerOptions) -> ComputerOptions {
+        ComputerOptions {
+            battery_enabled: self.battery_enabled || other.battery_enabled,
+            controller_enabled: self.controller_enabled || other.controller_enabled,
+            cpu_enabled: self.cpu_enabled || other.cpu_enabled,
+            gpu_enabled: self.gpu_enabled || other.gpu_enabled,
+            memory_enabled: self.memory_enabled || other.memory_enabled,
+            motherboard_enabled: self.motherboard_enabled || other.motherboard_enabled,
+            network_enabled: self.network_enabled || other.network_enabled,
+            psu_enabled: self.psu_enabled || other.psu_enabled,
+            storage_enabled: self.storage_enabled || other.storage_enabled,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum PipeRequest {
+    Hello {
+        protocol_version: u32,
+        client_version: String,
+    },
     SetOptions {
         options: ComputerOptions,
     },
@@ -59,20 +147,329 @@ pub enum PipeRequest {
     UpdateSensorByIndex {
         idx: usize,
     },
+    Subscribe {
+        sensor_ids: Vec<String>,
+        interval_ms: u64,
+    },
+    Unsubscribe {
+        subscription_id: u32,
+    },
+    SubscribeEvents,
+    ResetSensorMinMax {
+        id: String,
+    },
+    ResetHardwareMinMax {
+        id: String,
+    },
+    ResetAllMinMax,
+    SetSensorControl {
+        id: String,
+        /// Control value as a percentage between 0 and 100
+        value: f32,
+    },
+    ResetSensorControl {
+        id: String,
+    },
+    GetSensorHistory {
+        id: String,
+        /// Only include samples recorded at or after this unix timestamp
+        /// in milliseconds
+        since: Option<u64>,
+        /// Maximum number of points to provide
+        max_points: Option<usize>,
+        /// Group the samples into `max_points` min/avg/max buckets instead
+        /// of providing only the latest samples
+        downsample: bool,
+    },
+    GetCacheGeneration,
+    AddAlertRule {
+        rule: AlertRule,
+    },
+    RemoveAlertRule {
+        name: String,
+    },
+    ListAlertRules,
+    AddVirtualSensor {
+        sensor: VirtualSensor,
+    },
+    RemoveVirtualSensor {
+        /// ID of the virtual sensor, without the virtual prefix
+        id: String,
+    },
+    ListVirtualSensors,
+    GetHardwareTree {
+        /// Identifier of the hardware at the root of the tree, the
+        /// tree of all top level hardware is provided when [None]
+        root_id: Option<String>,
+    },
+    GetSnapshot {
+        /// Update all the hardware before taking the snapshot, ignored
+        /// when the server refreshes the hardware in the background
+        update: bool,
+        /// Filter for the hardware and sensors included
+        #[serde(default)]
+        filter: SnapshotFilter,
+    },
+    Batch {
+        /// Requests to run in order, batches cannot contain subscription
+        /// requests or other batches
+        requests: Vec<PipeRequest>,
+    },
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum PipeResponse {
-    Hardware { hardware: Option<Hardware> },
-    Hardwares { hardware: Vec<Hardware> },
+    Hello {
+        server: ServerInfo,
+    },
 
-    Sensor { sensor: Option<Sensor> },
-    SensorValue { value: Option<f32> },
-    Sensors { sensors: Vec<Sensor> },
+    Hardware {
+        hardware: Option<Hardware>,
+    },
+    Hardwares {
+        hardware: Vec<Hardware>,
+    },
+
+    Sensor {
+        sensor: Option<Sensor>,
+    },
+    SensorValue {
+        value: Option<f32>,
+        /// Milliseconds since the value was last updated, [None] if the
+        /// server has not updated the value itself
+        #[serde(default)]
+        age_ms: Option<u64>,
+        /// Minimum recorded value of the sensor
+        #[serde(default)]
+        min: Option<f32>,
+        /// Maximum recorded value of the sensor
+        #[serde(default)]
+        max: Option<f32>,
+    },
+    Sensors {
+        sensors: Vec<Sensor>,
+    },
+    SensorHistory {
+        /// Points within the history, [None] if there is no
+        /// history for the sensor
+        points: Option<Vec<HistoryPoint>>,
+    },
+    CacheGeneration {
+        generation: u64,
+    },
+    AlertRules {
+        rules: Vec<AlertRule>,
+    },
+    VirtualSensors {
+        sensors: Vec<VirtualSensor>,
+    },
+    HardwareTree {
+        nodes: Vec<HardwareNode>,
+    },
+    Snapshot {
+        snapshot: Snapshot,
+    },
+
+    Subscribed {
+        subscription_id: u32,
+    },
+    SubscriptionValues {
+        values: Vec<SensorValueUpdate>,
+    },
+    Event {
+        event: ServerEvent,
+    },
+
+    Batch {
+        /// Response for each request within the batch, in the same
+        /// order as the requests
+        responses: Vec<PipeResponse>,
+    },
 
     Success,
-    Error { error: String },
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+impl PipeResponse {
+    /// Create an error response
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        PipeResponse::Error {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Stable code describing the cause of a [PipeResponse::Error]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// Requested hardware or sensor does not exist
+    NotFound,
+    /// Request could not be parsed or has invalid values
+    InvalidRequest,
+    /// Hardware backend is not available to handle the request
+    BackendUnavailable,
+    /// Client is not allowed to make the request
+    PermissionDenied,
+    /// Hardware backend took too long to handle the request
+    Timeout,
+    /// Client has reached a limit on the resources it can use, such
+    /// as the maximum number of active subscriptions
+    LimitExceeded,
+    /// Unexpected error within the server
+    Internal,
+
+    /// Error code from a newer version of the protocol that
+    /// this version does not know about
+    #[serde(other)]
+    Unknown,
+}
+
+/// Level of access required for a request, each level includes
+/// the levels below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Reading and updating hardware and sensor values, along with enabling
+    /// hardware groups for the connection
+    Read,
+    /// Changing the state shared with other clients, such as recorded
+    /// min/max values, alert rules or virtual sensors
+    Configure,
+    /// Controlling hardware, such as the speed of fans and pumps
+    Control,
+}
+
+impl PipeRequest {
+    /// Permission a client requires to make the request
+    ///
+    /// Any client may send a [PipeRequest::Batch], the permission for
+    /// each request within the batch is checked individually
+    pub fn required_permission(&self) -> Permission {
+        match self {
+            PipeRequest::ResetSensorMinMax { .. }
+            | PipeRequest::ResetHardwareMinMax { .. }
+            | PipeRequest::ResetAllMinMax
+            | PipeRequest::AddAlertRule { .. }
+            | PipeRequest::RemoveAlertRule { .. }
+            | PipeRequest::AddVirtualSensor { .. }
+            | PipeRequest::RemoveVirtualSensor { .. } => Permission::Configure,
+
+            PipeRequest::SetSensorControl { .. } | PipeRequest::ResetSensorControl { .. } => {
+                Permission::Control
+            }
+
+            // Options are combined with those of other connections and
+            // released when the connection closes
+            PipeRequest::Hello { .. }
+            | PipeRequest::SetOptions { .. }
+            | PipeRequest::UpdateAll
+            | PipeRequest::GetHardwareById { .. }
+            | PipeRequest::QueryHardware { .. }
+            | PipeRequest::UpdateHardwareById { .. }
+            | PipeRequest::UpdateHardwareByIndex { .. }
+            | PipeRequest::GetSensorById { .. }
+            | PipeRequest::GetSensorValueById { .. }
+            | PipeRequest::GetSensorValueByIndex { .. }
+            | PipeRequest::QuerySensors { .. }
+            | PipeRequest::UpdateSensorById { .. }
+            | PipeRequest::UpdateSensorByIndex { .. }
+            | PipeRequest::Subscribe { .. }
+            | PipeRequest::Unsubscribe { .. }
+            | PipeRequest::SubscribeEvents
+            | PipeRequest::GetSensorHistory { .. }
+            | PipeRequest::GetCacheGeneration
+            | PipeRequest::ListAlertRules
+            | PipeRequest::ListVirtualSensors
+            | PipeRequest::GetHardwareTree { .. }
+            | PipeRequest::GetSnapshot { .. }
+            | PipeRequest::Batch { .. } => Permission::Read,
+        }
+    }
+}
+
+/// Details about the server provided in response to [PipeRequest::Hello]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// Version of the protocol the server speaks
+    pub protocol_version: u32,
+
+    /// Crate version of the server
+    pub server_version: String,
+
+    /// Optional features supported by the server
+    pub capabilities: Vec<Capability>,
+}
+
+impl ServerInfo {
+    /// Check if the server speaks a protocol that is compatible with
+    /// this version of the protocol
+    pub fn is_compatible(&self) -> bool {
+        self.protocol_version == PROTOCOL_VERSION
+    }
+
+    /// Check if the server supports a specific capability
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// Optional features that a server may support
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Capability {
+    /// Server supports [PipeRequest::Subscribe] for receiving
+    /// pushed sensor values
+    Subscriptions,
+
+    /// Server refreshes the hardware in the background, sensor values
+    /// are read from the last refresh rather than updating on request
+    BackgroundRefresh,
+
+    /// Server supports [PipeRequest::SetSensorControl] for controlling
+    /// [SensorType::Control] sensors such as fans and pumps
+    SensorControl,
+
+    /// Server keeps a history of sensor values available through
+    /// [PipeRequest::GetSensorHistory]
+    SensorHistory,
+
+    /// Cache indexes remain the same across [PipeRequest::UpdateAll] while
+    /// the hardware is present, changes are tracked through the generation
+    /// provided by [PipeRequest::GetCacheGeneration]
+    CacheGeneration,
+
+    /// Server supports [PipeRequest::GetHardwareTree] for loading the
+    /// hardware hierarchy
+    HardwareTree,
+
+    /// Server supports [PipeRequest::GetSnapshot] for loading the whole
+    /// hardware tree along with the sensor values
+    Snapshot,
+
+    /// Server supports [PipeRequest::SubscribeEvents] for receiving
+    /// pushed [ServerEvent]s
+    Events,
+
+    /// Server supports [PipeRequest::AddAlertRule] for evaluating alert
+    /// rules, emitting [ServerEvent::AlertFired] and [ServerEvent::AlertCleared]
+    Alerts,
+
+    /// Server supports [PipeRequest::AddVirtualSensor] for computing
+    /// virtual sensors from the values of other sensors
+    VirtualSensors,
+
+    /// Server supports [PipeRequest::Batch] for sending multiple
+    /// requests in a single frame
+    Batch,
+
+    /// Capability from a newer version of the protocol that
+    /// this version does not know about
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -88,6 +485,15 @@ pub struct Hardware {
 
     /// Type of hardware
     pub ty: HardwareType,
+
+    /// Cache index of the parent hardware, [None] for top level hardware
+    #[serde(default)]
+    pub parent_index: Option<usize>,
+
+    /// Unique identifier of the parent hardware, [None] for top
+    /// level hardware
+    #[serde(default)]
+    pub parent_identifier: Option<String>,
 }
 
 /// Instance of a sensor
@@ -108,6 +514,116 @@ pub struct Sensor {
     /// Value of the sensor will be NaN when the sensor
     /// has no value
     pub value: f32,
+
+    /// Minimum recorded value of the sensor, NaN when
+    /// no value has been recorded
+    #[serde(default = "default_nan")]
+    pub min: f32,
+
+    /// Maximum recorded value of the sensor, NaN when
+    /// no value has been recorded
+    #[serde(default = "default_nan")]
+    pub max: f32,
+}
+
+fn default_nan() -> f32 {
+    f32::NAN
+}
+
+/// Value of a sensor pushed through a subscription
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorValueUpdate {
+    /// Unique identifier of the sensor
+    pub identifier: String,
+
+    /// Value of the sensor, [None] if the sensor could not be found
+    pub value: Option<f32>,
+
+    /// Milliseconds since the value was last updated
+    #[serde(default)]
+    pub age_ms: Option<u64>,
+}
+
+/// Event pushed by the server through [PipeRequest::SubscribeEvents]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ServerEvent {
+    /// Hardware was added to the server cache
+    HardwareAdded { hardware: Hardware },
+
+    /// Hardware was removed from the server cache
+    HardwareRemoved {
+        /// Cache index the hardware had
+        index: usize,
+        /// Unique identifier of the hardware
+        identifier: String,
+    },
+
+    /// Sensor was added to the server cache
+    SensorAdded { sensor: Sensor },
+
+    /// Sensor was removed from the server cache
+    SensorRemoved {
+        /// Cache index the sensor had
+        index: usize,
+        /// Unique identifier of the sensor
+        identifier: String,
+    },
+
+    /// Alert rule fired for a sensor
+    AlertFired {
+        /// Name of the rule
+        rule: String,
+        /// Unique identifier of the sensor
+        sensor_id: String,
+        /// Value of the sensor that fired the alert
+        value: f32,
+    },
+
+    /// Alert rule that previously fired for a sensor has cleared, also
+    /// emitted when the rule is removed or the sensor is no longer present
+    AlertCleared {
+        /// Name of the rule
+        rule: String,
+        /// Unique identifier of the sensor
+        sensor_id: String,
+        /// Last value of the sensor
+        value: f32,
+    },
+
+    /// Event from a newer version of the protocol that
+    /// this version does not know about
+    #[serde(other)]
+    Unknown,
+}
+
+/// Point within the history of a sensor
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HistoryPoint {
+    /// Unix timestamp in milliseconds of the sample, or the start
+    /// of the bucket when downsampled
+    pub timestamp_ms: u64,
+
+    /// Value of the sample, or the average value when downsampled
+    pub value: f32,
+
+    /// Minimum value within the bucket, same as `value` for raw samples
+    pub min: f32,
+
+    /// Maximum value within the bucket, same as `value` for raw samples
+    pub max: f32,
+}
+
+impl HistoryPoint {
+    /// Create a point for a single raw sample
+    pub fn raw(timestamp_ms: u64, value: f32) -> Self {
+        Self {
+            timestamp_ms,
+            value,
+            min: value,
+            max: value,
+        }
+    }
 }
 
 /// Types of hardware
@@ -182,3 +698,190 @@ pub enum SensorType {
     #[num_enum(catch_all)]
     Unknown(i32),
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ComputerOptions, Permission, PipeRequest, SensorType,
+        alert::{AlertComparison, AlertRule, SensorSelector},
+        snapshot::SnapshotFilter,
+        virtual_sensor::{VirtualFormula, VirtualSensor},
+    };
+
+    fn id() -> String {
+        "/intelcpu/0/temperature/0".to_string()
+    }
+
+    #[test]
+    fn test_required_permission() {
+        let selector = SensorSelector::Sensor { id: id() };
+        let requests = [
+            (
+                PipeRequest::Hello {
+                    protocol_version: 1,
+                    client_version: "0.0.0".to_string(),
+                },
+                Permission::Read,
+            ),
+            (
+                PipeRequest::SetOptions {
+                    options: ComputerOptions::default(),
+                },
+                Permission::Read,
+            ),
+            (PipeRequest::UpdateAll, Permission::Read),
+            (PipeRequest::GetHardwareById { id: id() }, Permission::Read),
+            (
+                PipeRequest::QueryHardware {
+                    parent_id: None,
+                    ty: None,
+                },
+                Permission::Read,
+            ),
+            (
+                PipeRequest::UpdateHardwareById { id: id() },
+                Permission::Read,
+            ),
+            (
+                PipeRequest::UpdateHardwareByIndex { idx: 0 },
+                Permission::Read,
+            ),
+            (PipeRequest::GetSensorById { id: id() }, Permission::Read),
+            (
+                PipeRequest::GetSensorValueById {
+                    id: id(),
+                    update: true,
+                },
+                Permission::Read,
+            ),
+            (
+                PipeRequest::GetSensorValueByIndex {
+                    idx: 0,
+                    update: true,
+                },
+                Permission::Read,
+            ),
+            (
+                PipeRequest::QuerySensors {
+                    parent_id: None,
+                    ty: None,
+                },
+                Permission::Read,
+            ),
+            (PipeRequest::UpdateSensorById { id: id() }, Permission::Read),
+            (
+                PipeRequest::UpdateSensorByIndex { idx: 0 },
+                Permission::Read,
+            ),
+            (
+                PipeRequest::Subscribe {
+                    sensor_ids: vec![id()],
+                    interval_ms: 1000,
+                },
+                Permission::Read,
+            ),
+            (
+                PipeRequest::Unsubscribe { subscription_id: 1 },
+                Permission::Read,
+            ),
+            (PipeRequest::SubscribeEvents, Permission::Read),
+            (
+                PipeRequest::ResetSensorMinMax { id: id() },
+                Permission::Configure,
+            ),
+            (
+                PipeRequest::ResetHardwareMinMax { id: id() },
+                Permission::Configure,
+            ),
+            (PipeRequest::ResetAllMinMax, Permission::Configure),
+            (
+                PipeRequest::SetSensorControl {
+                    id: id(),
+                    value: 50.0,
+                },
+                Permission::Control,
+            ),
+            (
+                PipeRequest::ResetSensorControl { id: id() },
+                Permission::Control,
+            ),
+            (
+                PipeRequest::GetSensorHistory {
+                    id: id(),
+                    since: None,
+                    max_points: None,
+                    downsample: false,
+                },
+                Permission::Read,
+            ),
+            (PipeRequest::GetCacheGeneration, Permission::Read),
+            (
+                PipeRequest::AddAlertRule {
+                    rule: AlertRule {
+                        name: "hot".to_string(),
+                        selector: selector.clone(),
+                        comparison: AlertComparison::Above,
+                        threshold: 90.0,
+                        duration_ms: 0,
+                        hysteresis: 0.0,
+                        cooldown_ms: 0,
+                    },
+                },
+                Permission::Configure,
+            ),
+            (
+                PipeRequest::RemoveAlertRule {
+                    name: "hot".to_string(),
+                },
+                Permission::Configure,
+            ),
+            (PipeRequest::ListAlertRules, Permission::Read),
+            (
+                PipeRequest::AddVirtualSensor {
+                    sensor: VirtualSensor {
+                        id: "avg".to_string(),
+                        name: "Average".to_string(),
+                        ty: SensorType::Temperature,
+                        formula: VirtualFormula::Average {
+                            inputs: vec![selector],
+                        },
+                    },
+                },
+                Permission::Configure,
+            ),
+            (
+                PipeRequest::RemoveVirtualSensor {
+                    id: "avg".to_string(),
+                },
+                Permission::Configure,
+            ),
+            (PipeRequest::ListVirtualSensors, Permission::Read),
+            (
+                PipeRequest::GetHardwareTree { root_id: None },
+                Permission::Read,
+            ),
+            (
+                PipeRequest::GetSnapshot {
+                    update: false,
+                    filter: SnapshotFilter::default(),
+                },
+                Permission::Read,
+            ),
+            // Requests within a batch are checked individually
+            (
+                PipeRequest::Batch {
+                    requests: vec![PipeRequest::ResetSensorControl { id: id() }],
+                },
+                Permission::Read,
+            ),
+        ];
+
+        for (index, (request, permission)) in requests.into_iter().enumerate() {
+            assert_eq!(
+                request.required_permission(),
+                permission,
+                "permission for request {index}"
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Framed pipe shared by the client and server for reading and writing
+//! frames over any of the supported transports
+
+use crate::codec::{LHMCodecError, LHMFrame, LHMFrameCodec};
+use futures_util::{SinkExt, StreamExt};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc,
+};
+use tokio_util::codec::Framed;
+
+/// Stream that frames can be read from and written to
+pub trait TransportStream: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T> TransportStream for T where T: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+pub type Pipe<S> = Framed<S, LHMFrameCodec>;
+
+pub type PipeTx = mpsc::UnboundedSender<PipeMessage>;
+pub type PipeRx = mpsc::UnboundedReceiver<PipeMessage>;
+pub type PipeMessage = LHMFrame;
+
+/// Future driving a [Pipe], frames read from the pipe are sent to the
+/// [PipeRx] and frames sent through the [PipeTx] are written to the pipe
+///
+/// Completes once the pipe is closed by the other side, or once all the
+/// [PipeTx] senders are dropped
+pub struct PipeFuture<S> {
+    /// Pipe we are acting upon
+    pipe: Pipe<S>,
+    /// Channel for processing received messages
+    inbound_tx: Option<mpsc::UnboundedSender<PipeMessage>>,
+    /// Channel for outbound messages
+    outbound_rx: mpsc::UnboundedReceiver<PipeMessage>,
+    /// Currently accepted outbound item, ready to be written
+    buffered_item: Option<PipeMessage>,
+    /// Error that stopped the pipe, reported once the pipe is closed
+    error: Option<LHMCodecError>,
+    /// Whether the other side closing the pipe is reported as an error
+    eof_error: bool,
+}
+
+impl<S: TransportStream> PipeFuture<S> {
+    pub fn new(pipe: Pipe<S>) -> (PipeFuture<S>, PipeRx, PipeTx) {
+        let (inbound_tx, inbound_rx) = mpsc::unbounded_channel();
+        let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+
+        let future = PipeFuture {
+            pipe,
+            inbound_tx: Some(inbound_tx),
+            outbound_rx,
+            buffered_item: None,
+            error: None,
+            eof_error: false,
+        };
+
+        (future, inbound_rx, outbound_tx)
+    }
+
+    /// Report the other side closing the pipe as an [std::io::ErrorKind::UnexpectedEof]
+    /// error, used by clients where the server is not expected to close the pipe
+    pub fn with_eof_error(mut self) -> Self {
+        self.eof_error = true;
+        self
+    }
+
+    /// Polls reading and writing the pipe, completes when the pipe is
+    /// closed or an error has occurred
+    fn poll_pipe(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), LHMCodecError>> {
+        // Read messages from the socket
+        while let Some(inbound_tx) = &mut self.inbound_tx {
+            let msg = match self.pipe.poll_next_unpin(cx) {
+                Poll::Ready(Some(result)) => result?,
+
+                // Socket is already closed, cannot ready anything more
+                Poll::Ready(None) if self.eof_error => {
+                    return Poll::Ready(Err(LHMCodecError::Io(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "connection closed unexpectedly",
+                    ))));
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+
+                // Nothing yet, move onto the write polling
+                Poll::Pending => break,
+            };
+
+            if inbound_tx.send(msg).is_err() {
+                // Receiver for messages has dropped, stop reading messages
+                self.inbound_tx.take();
+                break;
+            }
+        }
+
+        // Write messages to the pipe
+        loop {
+            if self.buffered_item.is_some() {
+                // Wait until the pipe is ready
+                ready!(self.pipe.poll_ready_unpin(cx))?;
+
+                // Take the buffered item
+                let packet = self
+                    .buffered_item
+                    .take()
+                    .expect("unexpected write state without a packet");
+
+                // Write the buffered item
+                self.pipe.start_send_unpin(packet)?;
+            }
+
+            match self.outbound_rx.poll_recv(cx) {
+                // Message ready, set the buffered item
+                Poll::Ready(Some(item)) => {
+                    self.buffered_item = Some(item);
+                }
+                // All message senders have dropped, close the pipe
+                Poll::Ready(None) => {
+                    ready!(self.pipe.poll_close_unpin(cx))?;
+                    return Poll::Ready(Ok(()));
+                }
+                Poll::Pending => {
+                    // Failed to flush the pipe
+                    ready!(self.pipe.poll_flush_unpin(cx))?;
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+impl<S: TransportStream> Future for PipeFuture<S> {
+    type Output = Result<(), LHMCodecError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if this.error.is_none() {
+            match ready!(this.poll_pipe(cx)) {
+                Ok(()) => return Poll::Ready(Ok(())),
+                Err(err) => this.error = Some(err),
+            }
+        }
+
+        // Close the pipe before reporting the error, errors from closing are
+        // ignored as the original error is more useful
+        _ = ready!(this.pipe.poll_close_unpin(cx));
+
+        let err = this
+            .error
+            .take()
+            .expect("unexpected close state without an error");
+        Poll::Ready(Err(err))
+    }
+}
@@ -0,0 +1,143 @@
+//! Hardware tree along with the values of each sensor, provided in response
+//! to [PipeRequest::GetSnapshot](crate::PipeRequest::GetSnapshot) and
+//! [PipeRequest::GetHardwareTree](crate::PipeRequest::GetHardwareTree)
+
+use crate::{Hardware, HardwareType, Sensor, SensorType};
+use serde::{Deserialize, Serialize};
+
+/// Snapshot of the loaded hardware and sensors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Unix timestamp in milliseconds when the snapshot was taken
+    pub timestamp_ms: u64,
+
+    /// Generation of the server cache when the snapshot was taken, the
+    /// cache indexes within the snapshot remain valid while the generation
+    /// is unchanged
+    #[serde(default)]
+    pub generation: u64,
+
+    /// Top level hardware
+    pub hardware: Vec<HardwareNode>,
+}
+
+impl Snapshot {
+    /// Iterator over all the hardware within the snapshot, each hardware
+    /// item is visited before its children
+    pub fn iter_hardware(&self) -> HardwareNodeIter<'_> {
+        HardwareNodeIter::new(&self.hardware)
+    }
+
+    /// Iterator over all the sensors within the snapshot along with the
+    /// hardware they belong to
+    pub fn iter_sensors(&self) -> impl Iterator<Item = (&Hardware, &Sensor)> + '_ {
+        self.iter_hardware().flat_map(|node| {
+            node.sensors
+                .iter()
+                .map(move |sensor| (&node.hardware, sensor))
+        })
+    }
+
+    /// Find a hardware item within the snapshot by identifier
+    pub fn find_hardware(&self, identifier: &str) -> Option<&HardwareNode> {
+        self.iter_hardware()
+            .find(|node| node.hardware.identifier == identifier)
+    }
+
+    /// Find a sensor within the snapshot by identifier
+    pub fn find_sensor(&self, identifier: &str) -> Option<&Sensor> {
+        self.iter_sensors()
+            .map(|(_, sensor)| sensor)
+            .find(|sensor| sensor.identifier == identifier)
+    }
+
+    /// Iterator over all the hardware of a specific type
+    pub fn hardware_of_type(&self, ty: HardwareType) -> impl Iterator<Item = &HardwareNode> + '_ {
+        self.iter_hardware()
+            .filter(move |node| node.hardware.ty == ty)
+    }
+
+    /// Iterator over all the sensors of a specific type
+    pub fn sensors_of_type(&self, ty: SensorType) -> impl Iterator<Item = &Sensor> + '_ {
+        self.iter_sensors()
+            .map(|(_, sensor)| sensor)
+            .filter(move |sensor| sensor.ty == ty)
+    }
+}
+
+/// Hardware item along with its sensors and children
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HardwareNode {
+    /// The hardware item itself
+    pub hardware: Hardware,
+
+    /// Sensors belonging to the hardware
+    pub sensors: Vec<Sensor>,
+
+    /// Children of the hardware
+    pub children: Vec<HardwareNode>,
+}
+
+impl HardwareNode {
+    /// Iterator over this hardware item and all of its descendants, each
+    /// hardware item is visited before its children
+    pub fn iter(&self) -> HardwareNodeIter<'_> {
+        HardwareNodeIter::new(std::slice::from_ref(self))
+    }
+
+    /// Find a sensor belonging to this hardware item by name
+    pub fn find_sensor_by_name(&self, name: &str) -> Option<&Sensor> {
+        self.sensors.iter().find(|sensor| sensor.name == name)
+    }
+}
+
+/// Depth first iterator over a collection of [HardwareNode]
+pub struct HardwareNodeIter<'a> {
+    /// Nodes remaining to be visited, in reverse order
+    stack: Vec<&'a HardwareNode>,
+}
+
+impl<'a> HardwareNodeIter<'a> {
+    fn new(nodes: &'a [HardwareNode]) -> Self {
+        Self {
+            stack: nodes.iter().rev().collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for HardwareNodeIter<'a> {
+    type Item = &'a HardwareNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children.iter().rev());
+        Some(node)
+    }
+}
+
+/// Filter for the hardware and sensors included within a [Snapshot]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SnapshotFilter {
+    /// Only include hardware of these types, all hardware is included
+    /// when empty. Hardware that does not match is still included without
+    /// its sensors when it has children that match, keeping the tree intact
+    #[serde(default)]
+    pub hardware_types: Vec<HardwareType>,
+
+    /// Only include sensors of these types, all sensors are included
+    /// when empty
+    #[serde(default)]
+    pub sensor_types: Vec<SensorType>,
+}
+
+impl SnapshotFilter {
+    /// Check if hardware of the provided type is included by the filter
+    pub fn includes_hardware(&self, ty: HardwareType) -> bool {
+        self.hardware_types.is_empty() || self.hardware_types.contains(&ty)
+    }
+
+    /// Check if sensors of the provided type are included by the filter
+    pub fn includes_sensor(&self, ty: SensorType) -> bool {
+        self.sensor_types.is_empty() || self.sensor_types.contains(&ty)
+    }
+}
@@ -0,0 +1,79 @@
+//! Virtual sensors computed by the server from the values of other sensors,
+//! registered through [PipeRequest::AddVirtualSensor](crate::PipeRequest::AddVirtualSensor)
+
+use crate::{SensorType, alert::SensorSelector};
+use serde::{Deserialize, Serialize};
+
+/// Prefix for the identifiers of virtual sensors
+pub const VIRTUAL_SENSOR_PREFIX: &str = "/virtual/";
+
+/// Sensor with a value computed from the values of other sensors
+///
+/// Virtual sensors are provided by [PipeRequest::QuerySensors] and
+/// [PipeRequest::GetSensorById] like any other sensor, they do not belong
+/// to any hardware so are only included when no parent is requested
+///
+/// [PipeRequest::QuerySensors]: crate::PipeRequest::QuerySensors
+/// [PipeRequest::GetSensorById]: crate::PipeRequest::GetSensorById
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualSensor {
+    /// Unique ID of the virtual sensor, adding a virtual sensor with the
+    /// same ID replaces the existing sensor. The identifier of the sensor
+    /// is the ID with the [VIRTUAL_SENSOR_PREFIX] (e.g `/virtual/cpu-core-avg`)
+    pub id: String,
+
+    /// Name of the sensor
+    pub name: String,
+
+    /// Type of the sensor
+    pub ty: SensorType,
+
+    /// Formula computing the value of the sensor
+    pub formula: VirtualFormula,
+}
+
+impl VirtualSensor {
+    /// Identifier of the sensor within the virtual namespace
+    pub fn identifier(&self) -> String {
+        format!("{VIRTUAL_SENSOR_PREFIX}{}", self.id)
+    }
+}
+
+/// Formula computing the value of a [VirtualSensor]
+///
+/// Inputs without a value are ignored by the aggregates, the virtual sensor
+/// has no value when none of its inputs have a value. Virtual sensors cannot
+/// be used as the input of another virtual sensor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VirtualFormula {
+    /// Average of the selected sensors
+    Average { inputs: Vec<SensorSelector> },
+
+    /// Minimum of the selected sensors
+    Min { inputs: Vec<SensorSelector> },
+
+    /// Maximum of the selected sensors
+    Max { inputs: Vec<SensorSelector> },
+
+    /// Sum of the selected sensors
+    Sum { inputs: Vec<SensorSelector> },
+
+    /// Sum of the values of specific sensors each multiplied by a weight,
+    /// plus an offset. Has no value when any of the sensors has no value
+    Weighted {
+        terms: Vec<WeightedTerm>,
+        #[serde(default)]
+        offset: f32,
+    },
+}
+
+/// Term within a [VirtualFormula::Weighted] formula
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightedTerm {
+    /// Identifier of the sensor
+    pub sensor_id: String,
+
+    /// Weight the value of the sensor is multiplied by
+    pub weight: f32,
+}
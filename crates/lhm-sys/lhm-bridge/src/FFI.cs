@@ -191,6 +191,27 @@ public static class FFI
         catch { return float.NaN; }
     }
 
+    [UnmanagedCallersOnly(EntryPoint = "reset_sensor_min_max", CallConvs = new[] { typeof(CallConvCdecl) })]
+    public static void ResetSensorMinMax(SensorPtr ptr)
+    {
+        try { ptr.ResetMinMax(); }
+        catch { }
+    }
+
+    [UnmanagedCallersOnly(EntryPoint = "set_sensor_control_software", CallConvs = new[] { typeof(CallConvCdecl) })]
+    public static int SetSensorControlSoftware(SensorPtr ptr, float value)
+    {
+        try { return ptr.SetControlSoftware(value) ? 1 : 0; }
+        catch { return 0; }
+    }
+
+    [UnmanagedCallersOnly(EntryPoint = "set_sensor_control_default", CallConvs = new[] { typeof(CallConvCdecl) })]
+    public static int SetSensorControlDefault(SensorPtr ptr)
+    {
+        try { return ptr.SetControlDefault() ? 1 : 0; }
+        catch { return 0; }
+    }
+
     [UnmanagedCallersOnly(EntryPoint = "update_sensor", CallConvs = new[] { typeof(CallConvCdecl) })]
     public static void UpdateSensor(SensorPtr ptr)
     {
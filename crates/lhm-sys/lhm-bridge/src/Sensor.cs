@@ -102,6 +102,47 @@ public readonly struct SensorPtr : IDisposable
         return sensor.Max;
     }
 
+    /// <summary>
+    /// Resets the minimum and maximum values recorded
+    /// for the sensor
+    /// </summary>
+    public void ResetMinMax()
+    {
+        sensor.ResetMin();
+        sensor.ResetMax();
+    }
+
+    /// <summary>
+    /// Sets a software controlled value for the sensor
+    /// </summary>
+    /// <param name="value">The control value as a percentage</param>
+    /// <returns>Whether the sensor can be controlled</returns>
+    public bool SetControlSoftware(float value)
+    {
+        if (sensor.Control is not IControl control)
+        {
+            return false;
+        }
+
+        control.SetSoftware(value);
+        return true;
+    }
+
+    /// <summary>
+    /// Restores the default control for the sensor
+    /// </summary>
+    /// <returns>Whether the sensor can be controlled</returns>
+    public bool SetControlDefault()
+    {
+        if (sensor.Control is not IControl control)
+        {
+            return false;
+        }
+
+        control.SetDefault();
+        return true;
+    }
+
     /// <summary>
     /// Update the parent hardware to refresh the 
     /// sensor value
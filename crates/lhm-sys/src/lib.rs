@@ -95,6 +95,9 @@ unsafe extern "C" {
     unsafe fn get_sensor_value(ptr: SensorPtr) -> f32;
     unsafe fn get_sensor_min(ptr: SensorPtr) -> f32;
     unsafe fn get_sensor_max(ptr: SensorPtr) -> f32;
+    unsafe fn reset_sensor_min_max(ptr: SensorPtr);
+    unsafe fn set_sensor_control_software(ptr: SensorPtr, value: f32) -> i32;
+    unsafe fn set_sensor_control_default(ptr: SensorPtr) -> i32;
     unsafe fn update_sensor(ptr: SensorPtr);
     unsafe fn free_sensor(ptr: SensorPtr);
 }
@@ -294,6 +297,27 @@ impl Sensor {
         unsafe { get_sensor_max(self.ptr) }
     }
 
+    /// Resets the minimum and maximum recorded values for the sensor
+    pub fn reset_min_max(&mut self) {
+        unsafe { reset_sensor_min_max(self.ptr) };
+    }
+
+    /// Sets a software controlled value for the sensor as a percentage,
+    /// such as the duty of a fan or pump
+    ///
+    /// Returns false if the sensor cannot be controlled
+    pub fn set_control_software(&mut self, percent: f32) -> bool {
+        unsafe { set_sensor_control_software(self.ptr, percent) != 0 }
+    }
+
+    /// Restores the default control for the sensor, releasing any
+    /// software controlled value
+    ///
+    /// Returns false if the sensor cannot be controlled
+    pub fn set_control_default(&mut self) -> bool {
+        unsafe { set_sensor_control_default(self.ptr) != 0 }
+    }
+
     /// Updates the sensor value
     ///
     /// This updates the parent [Hardware] item to update the sensor